@@ -3,7 +3,9 @@ use json;
 use serde::Serialize;
 use yaml;
 
+mod three_way;
 mod types;
+pub use self::three_way::merge_three_way;
 pub use self::types::*;
 use std::env::vars;
 use std::fs::File;
@@ -170,7 +172,8 @@ where
     }
 }
 
-fn show<V, W>(output_mode: Option<&OutputMode>, value: V, ostream: W) -> Result<(), Error>
+/// Serialize `value` into `ostream` according to `output_mode`, which defaults to JSON.
+pub fn show<V, W>(output_mode: Option<&OutputMode>, value: V, ostream: W) -> Result<(), Error>
 where
     V: Serialize,
     W: io::Write,
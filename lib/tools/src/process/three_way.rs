@@ -0,0 +1,106 @@
+use super::show;
+use super::types::OutputMode;
+use failure::Error;
+use json;
+use treediff::value::Key;
+use treediff::{diff, Delegate, Mutable};
+use yaml;
+
+/// Applies the changes from a base value to theirs onto our value, recording all paths which were
+/// changed differently by both sides.
+struct ThreeWay {
+    cursor: Vec<Key>,
+    merged: json::Value,
+    conflicts: Vec<String>,
+}
+
+fn value_at<'a>(value: &'a json::Value, keys: &[Key]) -> Option<&'a json::Value> {
+    keys.iter().try_fold(value, |value, key| match *key {
+        Key::String(ref key) => value.get(key),
+        Key::Index(index) => value.get(index),
+    })
+}
+
+impl ThreeWay {
+    fn keys_with(&self, key: Option<&Key>) -> Vec<Key> {
+        let mut keys = self.cursor.clone();
+        keys.extend(key.cloned());
+        keys
+    }
+
+    fn conflict(&mut self, keys: &[Key]) {
+        self.conflicts.push(if keys.is_empty() {
+            "<root>".into()
+        } else {
+            keys.iter().map(ToString::to_string).collect::<Vec<_>>().join(".")
+        });
+    }
+}
+
+impl<'a> Delegate<'a, Key, json::Value> for ThreeWay {
+    fn push(&mut self, key: &Key) {
+        self.cursor.push(key.clone());
+    }
+    fn pop(&mut self) {
+        self.cursor.pop();
+    }
+    fn removed<'b>(&mut self, key: &'b Key, removed: &'a json::Value) {
+        let keys = self.keys_with(Some(key));
+        match value_at(&self.merged, &keys) {
+            None => {}
+            Some(ours) if ours == removed => self.merged.remove(&keys),
+            Some(_) => self.conflict(&keys),
+        }
+    }
+    fn added<'b>(&mut self, key: &'b Key, added: &'a json::Value) {
+        let keys = self.keys_with(Some(key));
+        match value_at(&self.merged, &keys) {
+            None => self.merged.set(&keys, added),
+            Some(ours) if ours == added => {}
+            Some(_) => self.conflict(&keys),
+        }
+    }
+    fn unchanged<'b>(&mut self, _value: &'a json::Value) {}
+    fn modified<'b>(&mut self, old: &'a json::Value, new: &'a json::Value) {
+        let keys = self.keys_with(None);
+        match value_at(&self.merged, &keys) {
+            Some(ours) if ours == old => self.merged.set(&keys, new),
+            Some(ours) if ours == new => {}
+            _ => self.conflict(&keys),
+        }
+    }
+}
+
+fn parse(document: &str, mode: OutputMode) -> Result<json::Value, Error> {
+    Ok(match mode {
+        OutputMode::Json => json::from_str(document)?,
+        OutputMode::Yaml => yaml::from_str(document)?,
+    })
+}
+
+/// Merge the changes made from the `base` document to `theirs` into `ours`, all of which are parsed as JSON
+/// or YAML according to `mode`, and return the merged document serialized the same way.
+///
+/// Values are merged structurally, and it is an error if both sides changed the same value differently.
+pub fn merge_three_way(base: &str, ours: &str, theirs: &str, mode: OutputMode) -> Result<String, Error> {
+    let (base, theirs) = (parse(base, mode)?, parse(theirs, mode)?);
+    let mut merger = ThreeWay {
+        cursor: Vec::new(),
+        merged: parse(ours, mode)?,
+        conflicts: Vec::new(),
+    };
+    diff(&base, &theirs, &mut merger);
+    if !merger.conflicts.is_empty() {
+        bail!(
+            "Both sides changed the values at the following paths: {}",
+            merger.conflicts.join(", ")
+        );
+    }
+
+    let mut output = Vec::new();
+    show(Some(&mode), &merger.merged, &mut output)?;
+    if !output.ends_with(b"\n") {
+        output.push(b'\n');
+    }
+    Ok(String::from_utf8(output)?)
+}
@@ -46,7 +46,7 @@ pub enum Command {
     SetOutputMode(OutputMode),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum OutputMode {
     Json,
     Yaml,
@@ -0,0 +1,30 @@
+extern crate sheesy_tools;
+
+use sheesy_tools::process::{merge_three_way, OutputMode};
+
+#[test]
+fn it_merges_changes_to_different_values_of_yaml_documents() {
+    let base = "user: admin\npassword: old\nhost: db\n";
+    let ours = "user: admin\npassword: new\nhost: db\n";
+    let theirs = "user: root\npassword: old\nport: 5432\n";
+    assert_eq!(
+        merge_three_way(base, ours, theirs, OutputMode::Yaml).unwrap(),
+        "---\npassword: new\nport: 5432\nuser: root\n"
+    );
+}
+
+#[test]
+fn it_fails_if_both_sides_changed_the_same_value_differently() {
+    let base = r#"{"db": {"password": "old"}, "user": "admin"}"#;
+    let ours = r#"{"db": {"password": "ours"}, "user": "admin"}"#;
+    let theirs = r#"{"db": {"password": "theirs"}}"#;
+    let err = merge_three_way(base, ours, theirs, OutputMode::Json).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Both sides changed the values at the following paths: db.password"
+    );
+    assert_eq!(
+        merge_three_way(base, ours, ours, OutputMode::Json).unwrap(),
+        "{\n  \"db\": {\n    \"password\": \"ours\"\n  },\n  \"user\": \"admin\"\n}\n"
+    );
+}
@@ -0,0 +1,204 @@
+use crate::backend::{Backend, Key, PlaintextWriter};
+use crate::error::DecryptionError;
+use crate::TrustModel;
+use age::x25519;
+use failure::{err_msg, Error, Fail, ResultExt};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::iter;
+use std::path::PathBuf;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// The environment variable pointing to the file with our age identities.
+pub const AGE_KEY_FILE_ENV: &str = "SY_AGE_KEY_FILE";
+
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+/// A backend using native age X25519 encryption.
+///
+/// Recipients are age public keys (`age1...`), which are their own fingerprint. Our secret keys are read from
+/// an identity file as produced by `age-keygen`, see `AgeBackend::from_env()`.
+/// Trust models don't apply to age, which is why they are ignored.
+#[derive(Debug, Clone)]
+pub struct AgeBackend {
+    identities: PathBuf,
+}
+
+impl AgeBackend {
+    /// Use the identities in the file at `path`.
+    pub fn with_identities(path: impl Into<PathBuf>) -> Self {
+        AgeBackend {
+            identities: path.into(),
+        }
+    }
+
+    /// Use the identities in the file at `$SY_AGE_KEY_FILE`, or in `age/keys.txt` in the user's configuration
+    /// directory otherwise.
+    pub fn from_env() -> Self {
+        AgeBackend::with_identities(env::var_os(AGE_KEY_FILE_ENV).map(PathBuf::from).unwrap_or_else(|| {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .unwrap_or_default()
+                .join("age")
+                .join("keys.txt")
+        }))
+    }
+
+    fn identities(&self) -> Result<Vec<x25519::Identity>, Error> {
+        let content = fs::read_to_string(&self.identities).with_context(|_| {
+            format!(
+                "Could not read age identities from '{}'. Create them with 'age-keygen -o {}' or set {}.",
+                self.identities.display(),
+                self.identities.display(),
+                AGE_KEY_FILE_ENV
+            )
+        })?;
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                x25519::Identity::from_str(l)
+                    .map_err(|e| format_err!("Invalid age identity in '{}': {}", self.identities.display(), e))
+            })
+            .collect()
+    }
+}
+
+impl Default for AgeBackend {
+    fn default() -> Self {
+        AgeBackend::from_env()
+    }
+}
+
+fn recipient(id: &str) -> Result<x25519::Recipient, Error> {
+    x25519::Recipient::from_str(id.trim()).map_err(|e| format_err!("'{}' is not a valid age recipient: {}", id, e))
+}
+
+/// age keys carry no user ids, which is why the recipient doubles as one.
+fn key_from(recipient: &x25519::Recipient) -> Key {
+    let fingerprint = recipient.to_string();
+    Key {
+        user_ids: vec![fingerprint.clone()],
+        fingerprint,
+        can_encrypt: true,
+        ..Default::default()
+    }
+}
+
+fn matches(key: &Key, ids: &[String]) -> bool {
+    ids.is_empty() || ids.iter().any(|id| id.trim() == key.fingerprint)
+}
+
+impl Backend for AgeBackend {
+    fn encrypt(&self, keys: &[Key], plaintext: &[u8], _model: &TrustModel) -> Result<Vec<u8>, Error> {
+        let recipients = keys
+            .iter()
+            .map(|k| recipient(&k.fingerprint))
+            .collect::<Result<Vec<_>, _>>()?;
+        let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| e.context("Failed to encrypt data."))?;
+        let mut ciphertext = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut ciphertext)?;
+        writer.write_all(plaintext)?;
+        writer.finish()?;
+        Ok(ciphertext)
+    }
+
+    fn encrypt_signed(
+        &self,
+        _keys: &[Key],
+        _plaintext: &[u8],
+        _model: &TrustModel,
+        _signing_key: &Key,
+    ) -> Result<Vec<u8>, Error> {
+        Err(err_msg(
+            "age cannot sign what it encrypts, please disable signatures for vaults using it.",
+        ))
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let identities = self.identities()?;
+        let decryptor = age::Decryptor::new(ciphertext).map_err(|e| e.context("Failed to decrypt data."))?;
+        let mut reader = decryptor
+            .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+            .map_err(|e| match e {
+                age::DecryptError::NoMatchingKeys => Error::from(DecryptionError { cause: e.into() }),
+                e => e.context("Failed to decrypt data.").into(),
+            })?;
+        let mut plaintext = PlaintextWriter::with_capacity(ciphertext.len());
+        io::copy(&mut reader, &mut plaintext)?;
+        Ok(plaintext.into_inner())
+    }
+
+    /// age messages carry no signatures.
+    fn decrypt_verified(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error> {
+        Ok((self.decrypt(ciphertext)?, Vec::new()))
+    }
+
+    fn sign_detached(&self, _data: &[u8], _signing_key: &Key) -> Result<Vec<u8>, Error> {
+        Err(err_msg(
+            "age keys cannot sign, please disable signatures for vaults using them.",
+        ))
+    }
+
+    fn verify_detached(&self, _data: &[u8], _signature: &[u8]) -> Result<Vec<String>, Error> {
+        Err(err_msg(
+            "age keys cannot verify signatures, please disable signatures for vaults using them.",
+        ))
+    }
+
+    fn recipients_of(&self, ciphertext: &[u8]) -> Result<Vec<String>, Error> {
+        if !ciphertext.starts_with(AGE_MAGIC) {
+            return Ok(Vec::new());
+        }
+        Err(err_msg(
+            "The recipients of age-encrypted data cannot be determined without decrypting it.",
+        ))
+    }
+
+    fn recipient_ids(&self, key: &Key) -> Result<Vec<String>, Error> {
+        Ok(vec![key.fingerprint.clone()])
+    }
+
+    fn key(&self, id: &str) -> Result<Key, Error> {
+        recipient(id).map(|r| key_from(&r))
+    }
+
+    fn find_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        Ok(ids.iter().filter_map(|id| self.key(id).ok()).collect())
+    }
+
+    fn find_secret_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        Ok(self
+            .identities()?
+            .iter()
+            .map(|i| key_from(&i.to_public()))
+            .filter(|k| matches(k, ids))
+            .collect())
+    }
+
+    fn import_keys(&self, key_data: &[u8]) -> Result<Vec<String>, Error> {
+        String::from_utf8_lossy(key_data)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| recipient(l).map(|r| r.to_string()))
+            .collect()
+    }
+
+    fn export_key(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        let recipient = recipient(&key.fingerprint)?;
+        Ok(recipient.to_string().bytes().chain(iter::once(b'\n')).collect())
+    }
+
+    fn sign_key(&self, key: &Key, _signing_key: &Key) -> Result<(), Error> {
+        Err(format_err!(
+            "age recipients cannot be signed, please add '{}' as verified recipient instead.",
+            key.fingerprint
+        ))
+    }
+}
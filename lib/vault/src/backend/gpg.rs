@@ -0,0 +1,285 @@
+use crate::backend::packets::recipient_key_ids;
+use crate::backend::{Backend, Key, PlaintextWriter, Validity};
+use crate::error::{DecryptionError, EncryptionError};
+use crate::util::{write_at, FingerprintUserId};
+use crate::TrustModel;
+use failure::{self, err_msg, Error, Fail, ResultExt};
+use gpgme;
+use mktemp::Temp;
+use std::fs::File;
+use std::time::UNIX_EPOCH;
+use zeroize::Zeroizing;
+
+/// The default backend, which uses the GNU Privacy Guard through `gpgme`.
+///
+/// Every operation uses its own `gpgme::Context`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GpgBackend;
+
+pub fn new_context() -> Result<gpgme::Context, gpgme::Error> {
+    gpgme::Context::from_protocol(gpgme::Protocol::OpenPgp)
+}
+
+fn fingerprint_of(key: &gpgme::Key) -> Result<String, failure::Error> {
+    key.fingerprint()
+        .map_err(|e| {
+            e.map(Into::into)
+                .unwrap_or_else(|| err_msg("Fingerprint extraction failed"))
+        })
+        .map(ToOwned::to_owned)
+}
+
+fn validity_from(validity: gpgme::Validity) -> Validity {
+    match validity {
+        gpgme::Validity::Never => Validity::Never,
+        gpgme::Validity::Marginal => Validity::Marginal,
+        gpgme::Validity::Full => Validity::Full,
+        gpgme::Validity::Ultimate => Validity::Ultimate,
+        _ => Validity::Unknown,
+    }
+}
+
+/// The trust in a key is the highest validity of any of its user ids, which is what `gpg` displays as well.
+fn key_from(key: &gpgme::Key) -> Result<Key, Error> {
+    Ok(Key {
+        fingerprint: fingerprint_of(key)?,
+        user_ids: key.user_ids().map(|u| u.id().unwrap_or("[none]").to_owned()).collect(),
+        expires: key
+            .primary_key()
+            .and_then(|k| k.expiration_time())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs()),
+        trust: key
+            .user_ids()
+            .map(|u| validity_from(u.validity()))
+            .max()
+            .unwrap_or_default(),
+        revoked: key.is_revoked(),
+        disabled: key.is_disabled(),
+        can_encrypt: key.can_encrypt(),
+    })
+}
+
+fn collect_keys(mut keys_iter: gpgme::context::Keys) -> Result<Vec<Key>, Error> {
+    let keys: Vec<_> = keys_iter.by_ref().collect::<Result<_, _>>()?;
+    if keys_iter.finish()?.is_truncated() {
+        return Err(err_msg("The key list was truncated unexpectedly, while iterating it"));
+    }
+    keys.iter().map(key_from).collect()
+}
+
+fn flags_for_model(model: &TrustModel) -> gpgme::EncryptFlags {
+    let mut flags = gpgme::EncryptFlags::empty();
+    flags.set(
+        gpgme::EncryptFlags::ALWAYS_TRUST,
+        match *model {
+            TrustModel::Always => true,
+            TrustModel::GpgWebOfTrust => false,
+        },
+    );
+    flags
+}
+
+fn decryption_error(err: gpgme::Error, alternative_text: &'static str) -> Error {
+    if err.code() == gpgme::Error::NO_SECKEY.code() {
+        Error::from(DecryptionError { cause: err.into() })
+    } else {
+        err.context(alternative_text).into()
+    }
+}
+
+fn find_offending_keys(ctx: &mut gpgme::Context, keys: &[Key], gpg_keys: &[gpgme::Key]) -> Result<Vec<String>, Error> {
+    let mut output = Vec::new();
+    let mut obuf = Vec::<u8>::new();
+    let temp = Temp::new_file()?;
+    let temp_path = temp.to_path_buf();
+    {
+        let _ibuf = write_at(&temp_path)?;
+    }
+    let mut ibuf = File::open(&temp_path)?;
+    for (key, gpg_key) in keys.iter().zip(gpg_keys) {
+        if let Err(err) = ctx.encrypt(Some(gpg_key), &mut ibuf, &mut obuf) {
+            output.push(format!(
+                "Could not encrypt for recipient {} with error: {}",
+                FingerprintUserId(key),
+                err
+            ));
+        }
+    }
+    Ok(output)
+}
+
+fn encryption_error(err: gpgme::Error, ctx: &mut gpgme::Context, keys: &[Key], gpg_keys: &[gpgme::Key]) -> Error {
+    Error::from(EncryptionError {
+        msg: if err.code() == gpgme::Error::UNUSABLE_PUBKEY.code() {
+            "At least one recipient you try to encrypt for is untrusted. \
+             Consider (locally) signing the key with `gpg --sign-key <recipient>` \
+             or ultimately trusting them."
+                .into()
+        } else {
+            "Failed to encrypt data.".into()
+        },
+        offending_recipients: match find_offending_keys(ctx, keys, gpg_keys) {
+            Ok(v) => v,
+            Err(e) => return e,
+        },
+    })
+}
+
+/// Signatures are made by subkeys, which is why they are mapped to the fingerprint of their primary key.
+fn signers_of(ctx: &mut gpgme::Context, verification: &gpgme::VerificationResult) -> Result<Vec<String>, Error> {
+    let signing_fprs: Vec<String> = verification
+        .signatures()
+        .filter(|sig| sig.status().is_ok())
+        .filter_map(|sig| sig.fingerprint().ok().map(ToOwned::to_owned))
+        .collect();
+    signing_fprs
+        .iter()
+        .map(|fpr| {
+            ctx.get_key(fpr.as_str())
+                .map_err(Error::from)
+                .and_then(|k| fingerprint_of(&k))
+        })
+        .collect()
+}
+
+fn encrypt_with(
+    keys: &[Key],
+    plaintext: &[u8],
+    model: &TrustModel,
+    signing_key: Option<&Key>,
+) -> Result<Vec<u8>, Error> {
+    let mut ctx = new_context()?;
+    let gpg_keys = keys
+        .iter()
+        .map(|k| ctx.get_key(&k.fingerprint))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut encrypted_bytes = Vec::<u8>::new();
+    let result = match signing_key {
+        Some(signing_key) => {
+            let signing_key = ctx.get_secret_key(&signing_key.fingerprint)?;
+            ctx.add_signer(&signing_key)?;
+            ctx.sign_and_encrypt_with_flags(&gpg_keys, plaintext, &mut encrypted_bytes, flags_for_model(model))
+                .map(|_| ())
+        }
+        None => ctx
+            .encrypt_with_flags(&gpg_keys, plaintext, &mut encrypted_bytes, flags_for_model(model))
+            .map(|_| ()),
+    };
+    result.map_err(|e| encryption_error(e, &mut ctx, keys, &gpg_keys))?;
+    Ok(encrypted_bytes)
+}
+
+impl Backend for GpgBackend {
+    fn encrypt(&self, keys: &[Key], plaintext: &[u8], model: &TrustModel) -> Result<Vec<u8>, Error> {
+        encrypt_with(keys, plaintext, model, None)
+    }
+
+    fn encrypt_signed(
+        &self,
+        keys: &[Key],
+        plaintext: &[u8],
+        model: &TrustModel,
+        signing_key: &Key,
+    ) -> Result<Vec<u8>, Error> {
+        encrypt_with(keys, plaintext, model, Some(signing_key))
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let mut ctx = new_context()?;
+        let mut plaintext = PlaintextWriter::with_capacity(ciphertext.len());
+        {
+            let mut output = gpgme::Data::from_writer(&mut plaintext).map_err(|e| e.error())?;
+            ctx.decrypt(ciphertext, &mut output)
+                .map_err(|e| decryption_error(e, "Failed to decrypt data."))?;
+        }
+        Ok(plaintext.into_inner())
+    }
+
+    fn decrypt_verified(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error> {
+        let mut ctx = new_context()?;
+        let mut plaintext = PlaintextWriter::with_capacity(ciphertext.len());
+        let verification = {
+            let mut output = gpgme::Data::from_writer(&mut plaintext).map_err(|e| e.error())?;
+            ctx.decrypt_and_verify(ciphertext, &mut output)
+                .map_err(|e| decryption_error(e, "Failed to decrypt data."))?
+                .1
+        };
+        let signers = signers_of(&mut ctx, &verification)?;
+        Ok((plaintext.into_inner(), signers))
+    }
+
+    fn sign_detached(&self, data: &[u8], signing_key: &Key) -> Result<Vec<u8>, Error> {
+        let mut ctx = new_context()?;
+        let signing_key = ctx.get_secret_key(&signing_key.fingerprint)?;
+        ctx.add_signer(&signing_key)?;
+        ctx.set_armor(true);
+        let mut signature = Vec::new();
+        ctx.sign_detached(data, &mut signature)
+            .context("Failed to sign data.")?;
+        Ok(signature)
+    }
+
+    fn verify_detached(&self, data: &[u8], signature: &[u8]) -> Result<Vec<String>, Error> {
+        let mut ctx = new_context()?;
+        let verification = ctx
+            .verify_detached(signature, data)
+            .context("Failed to verify signature.")?;
+        signers_of(&mut ctx, &verification)
+    }
+
+    fn recipients_of(&self, ciphertext: &[u8]) -> Result<Vec<String>, Error> {
+        recipient_key_ids(ciphertext)
+    }
+
+    fn recipient_ids(&self, key: &Key) -> Result<Vec<String>, Error> {
+        let gpg_key = new_context()?.get_key(&key.fingerprint)?;
+        Ok(gpg_key
+            .subkeys()
+            .filter_map(|k| k.id().ok().map(str::to_uppercase))
+            .collect())
+    }
+
+    fn key(&self, id: &str) -> Result<Key, Error> {
+        key_from(&new_context()?.get_key(id)?)
+    }
+
+    fn find_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        let mut ctx = new_context()?;
+        let keys = collect_keys(ctx.find_keys(ids)?);
+        keys
+    }
+
+    fn find_secret_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        let mut ctx = new_context()?;
+        let keys = collect_keys(ctx.find_secret_keys(ids)?);
+        keys
+    }
+
+    fn import_keys(&self, key_data: &[u8]) -> Result<Vec<String>, Error> {
+        let imports = new_context()?.import(key_data)?;
+        Ok(imports
+            .imports()
+            .filter_map(|i| i.fingerprint().map(ToOwned::to_owned).ok())
+            .collect())
+    }
+
+    fn export_key(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        let mut ctx = new_context()?;
+        let gpg_key = ctx.get_key(&key.fingerprint)?;
+        let mut buf = Vec::new();
+        ctx.set_armor(true);
+        ctx.export_keys([&gpg_key].iter().cloned(), gpgme::ExportMode::empty(), &mut buf)
+            .with_context(|_| err_msg("Failed to export at least one public key with signatures."))?;
+        Ok(buf)
+    }
+
+    fn sign_key(&self, key: &Key, signing_key: &Key) -> Result<(), Error> {
+        let mut ctx = new_context()?;
+        let signing_key = ctx.get_secret_key(&signing_key.fingerprint)?;
+        let key_to_sign = ctx.get_key(&key.fingerprint)?;
+        ctx.add_signer(&signing_key)?;
+        ctx.sign_key(&key_to_sign, None::<&[u8]>, None)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{Encryption, TrustModel, Vault};
+use failure::Error;
+use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+#[cfg(not(any(feature = "gpgme", feature = "sequoia")))]
+compile_error!("At least one OpenPGP implementation must be enabled, using the 'gpgme' or 'sequoia' feature");
+
+mod age;
+#[cfg(feature = "gpgme")]
+mod gpg;
+mod packets;
+#[cfg(feature = "sequoia")]
+mod sequoia;
+
+pub use self::age::{AgeBackend, AGE_KEY_FILE_ENV};
+#[cfg(feature = "gpgme")]
+pub use self::gpg::GpgBackend;
+#[cfg(feature = "sequoia")]
+pub use self::sequoia::{SequoiaBackend, KEYRING_FILE_ENV, KEYRING_PASSPHRASE_ENV};
+
+/// How certain a backend is that a key belongs to the people named by its user ids.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Validity {
+    #[default]
+    Unknown,
+    Never,
+    Marginal,
+    Full,
+    Ultimate,
+}
+
+/// A public (or secret) key as known to a backend, identified by its fingerprint.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct Key {
+    pub fingerprint: String,
+    pub user_ids: Vec<String>,
+    /// The time at which the key expires in seconds since the UNIX epoch, if it expires at all.
+    pub expires: Option<u64>,
+    pub trust: Validity,
+    pub revoked: bool,
+    /// Whether the key was disabled locally, which only `gpg` supports.
+    pub disabled: bool,
+    /// Whether the key has a subkey usable for encryption right now.
+    pub can_encrypt: bool,
+}
+
+/// The cryptographic engine used by a vault to encrypt and decrypt its resources, and to manage the keys
+/// of its recipients.
+///
+/// Keys are always handed out as `Key`, and may be passed back to the backend that created them.
+pub trait Backend: Send + Sync {
+    /// Encrypt `plaintext` for all of the given `keys`.
+    fn encrypt(&self, keys: &[Key], plaintext: &[u8], model: &TrustModel) -> Result<Vec<u8>, Error>;
+    /// Encrypt `plaintext` like `encrypt()`, and sign it with `signing_key`, which must be a key we have the
+    /// secret key for.
+    fn encrypt_signed(
+        &self,
+        keys: &[Key],
+        plaintext: &[u8],
+        model: &TrustModel,
+        signing_key: &Key,
+    ) -> Result<Vec<u8>, Error>;
+    /// Decrypt `ciphertext`, failing with a `DecryptionError` if it was not encrypted for us.
+    ///
+    /// The returned plaintext is zeroized once it is dropped, which is why implementations should avoid leaving
+    /// copies of it in reallocated buffers, for instance by decrypting into a `PlaintextWriter`.
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error>;
+    /// Decrypt `ciphertext` like `decrypt()`, and also return the fingerprints of all known keys which made a
+    /// valid signature of it.
+    fn decrypt_verified(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error>;
+    /// Create a detached signature of `data` with `signing_key`, which must be a key we have the secret key for.
+    fn sign_detached(&self, data: &[u8], signing_key: &Key) -> Result<Vec<u8>, Error>;
+    /// Return the fingerprints of all known keys which made a valid detached `signature` of `data`.
+    fn verify_detached(&self, data: &[u8], signature: &[u8]) -> Result<Vec<String>, Error>;
+    /// Return the ids of all keys `ciphertext` was encrypted for, as stated in its header, without decrypting it.
+    /// The list is empty if `ciphertext` is not encrypted at all.
+    fn recipients_of(&self, ciphertext: &[u8]) -> Result<Vec<String>, Error>;
+    /// Return all ids by which `key` may be referred to in the output of `recipients_of()`.
+    fn recipient_ids(&self, key: &Key) -> Result<Vec<String>, Error>;
+    /// Return the key identified by `id`, which is usually a fingerprint.
+    fn key(&self, id: &str) -> Result<Key, Error>;
+    /// Return all public keys matching any of the given `ids`, or all keys if `ids` is empty.
+    fn find_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error>;
+    /// Return all keys we have a secret key for matching any of the given `ids`, or all of them if `ids` is empty.
+    fn find_secret_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error>;
+    /// Import all keys contained in `key_data`, returning the fingerprints of the imported keys.
+    fn import_keys(&self, key_data: &[u8]) -> Result<Vec<String>, Error>;
+    /// Export the public portion of `key`, including its signatures.
+    fn export_key(&self, key: &Key) -> Result<Vec<u8>, Error>;
+    /// Certify `key` using `signing_key`, which must be a key we have the secret key for.
+    fn sign_key(&self, key: &Key, signing_key: &Key) -> Result<(), Error>;
+}
+
+/// A sink for plaintext, which zeroizes its buffer whenever it has to grow, as well as once it is dropped.
+pub(crate) struct PlaintextWriter(Zeroizing<Vec<u8>>);
+
+impl PlaintextWriter {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        PlaintextWriter(Zeroizing::new(Vec::with_capacity(capacity)))
+    }
+
+    pub(crate) fn into_inner(self) -> Zeroizing<Vec<u8>> {
+        self.0
+    }
+}
+
+impl Write for PlaintextWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.0.len() + buf.len();
+        if len > self.0.capacity() {
+            let mut grown = Zeroizing::new(Vec::with_capacity(cmp::max(len, 2 * self.0.capacity())));
+            grown.extend_from_slice(&self.0);
+            self.0 = grown;
+        }
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A cheaply clonable handle to the backend used by a vault and all of its partitions.
+#[derive(Clone)]
+pub struct SharedBackend(Arc<dyn Backend>);
+
+impl SharedBackend {
+    pub fn new<B: Backend + 'static>(backend: B) -> Self {
+        SharedBackend(Arc::new(backend))
+    }
+
+    /// Create the backend implementing the encryption configured for the given `vault`.
+    pub fn for_vault(vault: &Vault) -> Self {
+        match vault.encryption.unwrap_or_default() {
+            Encryption::Gpg => SharedBackend::openpgp(
+                vault
+                    .all_in_order()
+                    .into_iter()
+                    .filter_map(|partition| vault.gpg_keys_dir_for_auto_import(partition))
+                    .collect(),
+            ),
+            Encryption::Age => SharedBackend::new(AgeBackend::from_env()),
+        }
+    }
+
+    /// If `gpgme` is available, it is preferred over `sequoia`.
+    #[cfg(feature = "gpgme")]
+    fn openpgp(_key_dirs: Vec<PathBuf>) -> Self {
+        SharedBackend::new(GpgBackend)
+    }
+
+    #[cfg(all(feature = "sequoia", not(feature = "gpgme")))]
+    fn openpgp(mut key_dirs: Vec<PathBuf>) -> Self {
+        key_dirs.sort();
+        key_dirs.dedup();
+        SharedBackend::new(SequoiaBackend::from_env(key_dirs))
+    }
+}
+
+impl Default for SharedBackend {
+    fn default() -> Self {
+        SharedBackend::openpgp(Vec::new())
+    }
+}
+
+impl Deref for SharedBackend {
+    type Target = dyn Backend;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for SharedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedBackend")
+    }
+}
+
+/// The backend is runtime state, and does not contribute to the identity of a vault.
+impl PartialEq for SharedBackend {
+    fn eq(&self, _other: &SharedBackend) -> bool {
+        true
+    }
+}
+
+impl Eq for SharedBackend {}
+
+impl Hash for SharedBackend {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[cfg(test)]
+mod tests_plaintext_writer {
+    use super::PlaintextWriter;
+    use std::io::Write;
+
+    #[test]
+    fn it_keeps_all_content_while_growing() {
+        let mut writer = PlaintextWriter::with_capacity(2);
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b" world").unwrap();
+        assert_eq!(&writer.into_inner()[..], b"hello world");
+    }
+}
@@ -0,0 +1,110 @@
+//! Just enough of RFC 4880 to read the recipients of an OpenPGP message without decrypting it.
+use failure::Error;
+
+const TAG_PKESK: u8 = 1;
+const TAG_SKESK: u8 = 3;
+const TAG_MARKER: u8 = 10;
+const ARMOR_BEGIN: &str = "-----BEGIN PGP MESSAGE-----";
+
+/// Returns the tag and the body of the packet at the beginning of `buf`, as well as the remaining bytes.
+/// `None` is returned if `buf` does not start with a packet of known size.
+fn next_packet(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&ctb, buf) = buf.split_first()?;
+    if ctb & 0x80 == 0 {
+        return None;
+    }
+    let be = |bytes: &[u8]| bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+    let (tag, len, header_len) = if ctb & 0x40 != 0 {
+        let first = *buf.first()? as usize;
+        match first {
+            0..=191 => (ctb & 0x3f, first, 1),
+            192..=223 => (ctb & 0x3f, ((first - 192) << 8) + *buf.get(1)? as usize + 192, 2),
+            255 => (ctb & 0x3f, be(buf.get(1..5)?), 5),
+            _ => return None,
+        }
+    } else {
+        let tag = (ctb >> 2) & 0x0f;
+        match ctb & 0x03 {
+            0 => (tag, be(buf.get(..1)?), 1),
+            1 => (tag, be(buf.get(..2)?), 2),
+            2 => (tag, be(buf.get(..4)?), 4),
+            _ => return None,
+        }
+    };
+    let buf = &buf[header_len..];
+    if buf.len() < len {
+        return None;
+    }
+    Some((tag, &buf[..len], &buf[len..]))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut num_bits) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .take_while(|c| *c != b'=')
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | u32::from(value);
+        num_bits += 6;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            decoded.push((bits >> num_bits) as u8);
+            bits &= (1 << num_bits) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Return the binary message within the ASCII armor of `text`, or `None` if it is not an armored message.
+fn dearmor(text: &[u8]) -> Option<Result<Vec<u8>, Error>> {
+    let text = String::from_utf8_lossy(text);
+    let mut lines = text.trim_start().lines().map(str::trim_end);
+    if lines.next()? != ARMOR_BEGIN {
+        return None;
+    }
+    // Armor headers are separated from the data by an empty line.
+    let data: String = lines
+        .skip_while(|line| !line.is_empty())
+        .skip(1)
+        .take_while(|line| !line.starts_with('=') && !line.starts_with("-----"))
+        .collect();
+    Some(decode_base64(&data).ok_or_else(|| format_err!("The ASCII armor of the message is invalid")))
+}
+
+/// Return the key ids, as upper-case hex, of all public keys the message in `ciphertext` was encrypted for.
+/// The message may be ASCII-armored. Anonymous recipients are returned as a key id consisting of zeros only.
+/// If `ciphertext` is not an encrypted OpenPGP message, the returned list is empty.
+pub fn recipient_key_ids(ciphertext: &[u8]) -> Result<Vec<String>, Error> {
+    let dearmored = match dearmor(ciphertext) {
+        Some(message) => Some(message?),
+        None => None,
+    };
+    let mut ciphertext = dearmored.as_deref().unwrap_or(ciphertext);
+    let mut key_ids = Vec::new();
+    while let Some((tag, body, rest)) = next_packet(ciphertext) {
+        match tag {
+            TAG_PKESK => match body.first() {
+                Some(3) if body.len() >= 9 => key_ids.push(body[1..9].iter().map(|b| format!("{:02X}", b)).collect()),
+                Some(version) => bail!(
+                    "Unsupported public-key encrypted session key packet version {}",
+                    version
+                ),
+                None => bail!("Found an empty public-key encrypted session key packet"),
+            },
+            TAG_SKESK | TAG_MARKER => {}
+            _ => break,
+        }
+        ciphertext = rest;
+    }
+    Ok(key_ids)
+}
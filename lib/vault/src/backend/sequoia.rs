@@ -0,0 +1,645 @@
+use crate::backend::packets::recipient_key_ids;
+use crate::backend::{Backend, Key, PlaintextWriter, Validity};
+use crate::error::{DecryptionError, EncryptionError};
+use crate::transaction::with_suffix;
+use crate::util::FingerprintUserId;
+use crate::TrustModel;
+use failure::{err_msg, Error, ResultExt};
+use sequoia_openpgp as openpgp;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+use zeroize::Zeroizing;
+
+use self::openpgp::cert::CertParser;
+use self::openpgp::crypto::{KeyPair, Password, SessionKey};
+use self::openpgp::packet::{key, Key as PgpKey, PKESK, SKESK};
+use self::openpgp::parse::stream::{
+    DecryptionHelper, DecryptorBuilder, DetachedVerifierBuilder, MessageLayer, MessageStructure, VerificationHelper,
+};
+use self::openpgp::parse::Parse;
+use self::openpgp::policy::{Policy, StandardPolicy};
+use self::openpgp::serialize::stream::{Armorer, Encryptor, LiteralWriter, Message, Recipient, Signer};
+use self::openpgp::serialize::Serialize;
+use self::openpgp::types::{Features, RevocationStatus, SignatureType, SymmetricAlgorithm};
+use self::openpgp::{armor, Cert, Fingerprint, KeyHandle};
+
+/// The environment variable pointing to the keyring file with our secret keys and all imported public keys.
+pub const KEYRING_FILE_ENV: &str = "SY_KEYRING_FILE";
+/// The environment variable with the passphrase to unlock secret keys in the keyring, if they are protected.
+pub const KEYRING_PASSPHRASE_ENV: &str = "SY_KEYRING_PASSPHRASE";
+
+/// A pure-Rust OpenPGP backend, producing and consuming the same messages as `gpg` does.
+///
+/// Keys are read from a keyring file (see `SequoiaBackend::from_env()`), which receives all imports and
+/// signatures, as well as from the key files in the given directories, which are never written to.
+/// Only secret keys in the keyring are our own, and the web of trust is limited to keys directly certified by one
+/// of them. All keys are read once, and again only after the keyring was changed.
+#[derive(Debug, Clone)]
+pub struct SequoiaBackend {
+    keyring: PathBuf,
+    key_dirs: Vec<PathBuf>,
+    certs: Arc<RwLock<Option<Arc<Vec<Cert>>>>>,
+}
+
+impl SequoiaBackend {
+    /// Use the keyring at `keyring`, and all keys in the files contained in `key_dirs`.
+    pub fn new(keyring: impl Into<PathBuf>, key_dirs: Vec<PathBuf>) -> Self {
+        SequoiaBackend {
+            keyring: keyring.into(),
+            key_dirs,
+            certs: Default::default(),
+        }
+    }
+
+    /// Use the keyring at `$SY_KEYRING_FILE`, or `sheesy/keyring.pgp` in the user's configuration directory
+    /// otherwise.
+    pub fn from_env(key_dirs: Vec<PathBuf>) -> Self {
+        SequoiaBackend::new(
+            env::var_os(KEYRING_FILE_ENV).map(PathBuf::from).unwrap_or_else(|| {
+                env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                    .unwrap_or_default()
+                    .join("sheesy")
+                    .join("keyring.pgp")
+            }),
+            key_dirs,
+        )
+    }
+
+    fn keyring(&self) -> Result<Vec<Cert>, Error> {
+        if !self.keyring.is_file() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read(&self.keyring)
+            .with_context(|_| format!("Could not read keyring at '{}'", self.keyring.display()))?;
+        Ok(parse_certs(&data).with_context(|_| format!("Could not parse keyring at '{}'", self.keyring.display()))?)
+    }
+
+    fn write_keyring(&self, certs: &[Cert]) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        for cert in certs {
+            cert.as_tsk().serialize(&mut buf).map_err(pgp_error)?;
+        }
+        if let Some(dir) = self.keyring.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let staged = with_suffix(&self.keyring, ".tmp");
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&staged)
+            .and_then(|mut f| f.write_all(&buf).and_then(|_| f.sync_all()))
+            .and_then(|_| fs::rename(&staged, &self.keyring))
+            .with_context(|_| format!("Could not write keyring at '{}'", self.keyring.display()))?;
+        *self.certs.write().expect("lock not poisoned") = None;
+        Ok(())
+    }
+
+    /// All certificates we know, loaded once and shared by all clones of this backend.
+    fn certs(&self) -> Result<Arc<Vec<Cert>>, Error> {
+        if let Some(certs) = self.certs.read().expect("lock not poisoned").as_ref() {
+            return Ok(certs.clone());
+        }
+        let certs = Arc::new(self.load_certs()?);
+        *self.certs.write().expect("lock not poisoned") = Some(certs.clone());
+        Ok(certs)
+    }
+
+    /// All certificates of the keyring, merged with the public parts of those in the key directories.
+    /// Secret key material in key directories is ignored, as everyone who can write to them could
+    /// otherwise make their key one of our own.
+    fn load_certs(&self) -> Result<Vec<Cert>, Error> {
+        let mut certs = self.keyring()?;
+        for dir in &self.key_dirs {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let data = fs::read(&path)?;
+                let key_file_certs =
+                    parse_certs(&data).with_context(|_| format!("Could not parse key file at '{}'", path.display()))?;
+                certs = merge(
+                    certs,
+                    key_file_certs
+                        .into_iter()
+                        .map(Cert::strip_secret_key_material)
+                        .collect(),
+                )?;
+            }
+        }
+        Ok(certs)
+    }
+
+    fn cert(&self, fingerprint: &str) -> Result<Cert, Error> {
+        self.certs()?
+            .iter()
+            .find(|c| c.fingerprint().to_hex() == fingerprint)
+            .cloned()
+            .ok_or_else(|| format_err!("No key with fingerprint '{}' found", fingerprint))
+    }
+}
+
+fn pgp_error(err: impl fmt::Display) -> Error {
+    err_msg(format!("{:#}", err))
+}
+
+fn policy() -> StandardPolicy<'static> {
+    StandardPolicy::new()
+}
+
+fn passphrase() -> Option<Password> {
+    env::var(KEYRING_PASSPHRASE_ENV).ok().map(Password::from)
+}
+
+fn parse_certs(data: &[u8]) -> Result<Vec<Cert>, Error> {
+    CertParser::from_bytes(data)
+        .map_err(pgp_error)?
+        .map(|c| c.map_err(pgp_error))
+        .collect()
+}
+
+fn merge(certs: Vec<Cert>, new_certs: Vec<Cert>) -> Result<Vec<Cert>, Error> {
+    let mut by_fpr: BTreeMap<Fingerprint, Cert> = certs.into_iter().map(|c| (c.fingerprint(), c)).collect();
+    for cert in new_certs {
+        let cert = match by_fpr.remove(&cert.fingerprint()) {
+            Some(existing) => existing.merge_public_and_secret(cert).map_err(pgp_error)?,
+            None => cert,
+        };
+        by_fpr.insert(cert.fingerprint(), cert);
+    }
+    Ok(by_fpr.into_values().collect())
+}
+
+/// Our own certs are trusted ultimately, and those certified by one of them fully.
+fn key_from(cert: &Cert, own_certs: &[&Cert]) -> Key {
+    let trust = if own_certs.iter().any(|own| own.fingerprint() == cert.fingerprint()) {
+        Validity::Ultimate
+    } else if is_valid(cert, own_certs) {
+        Validity::Full
+    } else {
+        Validity::Unknown
+    };
+    Key {
+        fingerprint: cert.fingerprint().to_hex(),
+        user_ids: cert
+            .userids()
+            .map(|u| String::from_utf8_lossy(u.userid().value()).into_owned())
+            .collect(),
+        expires: cert
+            .with_policy(&policy(), None)
+            .ok()
+            .and_then(|valid| valid.primary_key().key_expiration_time())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs()),
+        trust,
+        revoked: matches!(cert.revocation_status(&policy(), None), RevocationStatus::Revoked(_)),
+        disabled: false,
+        can_encrypt: !encryption_keys(&policy(), cert).is_empty(),
+    }
+}
+
+fn own_certs(certs: &[Cert]) -> Vec<&Cert> {
+    certs.iter().filter(|c| c.is_tsk()).collect()
+}
+
+/// Match `id` like `gpg` would: as (the end of) a fingerprint or key-id, or as part of a user id.
+fn matches(cert: &Cert, id: &str) -> bool {
+    let upper_id = id.to_uppercase();
+    let lower_id = id.to_lowercase();
+    cert.keys().any(|k| k.key().fingerprint().to_hex().ends_with(&upper_id))
+        || cert.userids().any(|u| {
+            String::from_utf8_lossy(u.userid().value())
+                .to_lowercase()
+                .contains(&lower_id)
+        })
+}
+
+fn matching<'a>(certs: &'a [Cert], ids: &[String]) -> Vec<&'a Cert> {
+    certs
+        .iter()
+        .filter(|c| ids.is_empty() || ids.iter().any(|id| matches(c, id)))
+        .collect()
+}
+
+fn keypair(key: PgpKey<key::SecretParts, key::UnspecifiedRole>) -> Result<KeyPair, Error> {
+    let key = if key.has_unencrypted_secret() {
+        key
+    } else {
+        let passphrase = passphrase().ok_or_else(|| {
+            format_err!(
+                "The secret key {} is protected by a passphrase, which needs to be set in {}",
+                key.fingerprint().to_hex(),
+                KEYRING_PASSPHRASE_ENV
+            )
+        })?;
+        key.decrypt_secret(&passphrase).map_err(pgp_error)?
+    };
+    key.into_keypair().map_err(pgp_error)
+}
+
+/// A cert is valid if it is one of our own, or if one of its user ids was certified by one of our own keys.
+fn is_valid(cert: &Cert, own_certs: &[&Cert]) -> bool {
+    own_certs.iter().any(|own| own.fingerprint() == cert.fingerprint())
+        || cert.userids().any(|uid| {
+            uid.certifications().any(|sig| {
+                own_certs.iter().flat_map(|own| own.keys()).any(|signer| {
+                    sig.get_issuers()
+                        .iter()
+                        .any(|issuer| issuer.aliases(KeyHandle::from(signer.key().fingerprint())))
+                        && sig
+                            .verify_userid_binding(signer.key(), cert.primary_key().key(), uid.userid())
+                            .is_ok()
+                })
+            })
+        })
+}
+
+/// Recipients are addressed by key-id and limited to SEIPDv1, which is what `gpg` produces and understands.
+fn encryption_keys<'a>(p: &'a dyn Policy, cert: &'a Cert) -> Vec<Recipient<'a>> {
+    cert.keys()
+        .with_policy(p, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .for_storage_encryption()
+        .for_transport_encryption()
+        .map(|ka| {
+            Recipient::new(
+                Features::empty().set_seipdv1(),
+                KeyHandle::from(ka.key().keyid()),
+                ka.key(),
+            )
+        })
+        .collect()
+}
+
+fn signing_keypair(p: &dyn Policy, cert: &Cert) -> Result<KeyPair, Error> {
+    let signing_key = cert
+        .keys()
+        .secret()
+        .with_policy(p, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .for_signing()
+        .next()
+        .ok_or_else(|| format_err!("The key {} cannot be used for signing", cert.fingerprint().to_hex()))?;
+    keypair(signing_key.key().clone().role_into_unspecified())
+}
+
+/// Signatures are verified using all certs we know, and the fingerprints of the certs of all valid
+/// signatures are collected in `signers`.
+struct Helper<'a> {
+    policy: &'a dyn Policy,
+    certs: Arc<Vec<Cert>>,
+    signers: Vec<String>,
+    had_secret_key: Rc<Cell<bool>>,
+}
+
+impl<'a> VerificationHelper for Helper<'a> {
+    fn get_certs(&mut self, _ids: &[KeyHandle]) -> openpgp::Result<Vec<Cert>> {
+        Ok(self.certs.to_vec())
+    }
+
+    fn check(&mut self, structure: MessageStructure) -> openpgp::Result<()> {
+        for layer in structure.into_iter() {
+            if let MessageLayer::SignatureGroup { results } = layer {
+                self.signers.extend(
+                    results
+                        .into_iter()
+                        .filter_map(Result::ok)
+                        .map(|good| good.ka.cert().fingerprint().to_hex()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> DecryptionHelper for Helper<'a> {
+    fn decrypt(
+        &mut self,
+        pkesks: &[PKESK],
+        _skesks: &[SKESK],
+        sym_algo: Option<SymmetricAlgorithm>,
+        decrypt: &mut dyn FnMut(Option<SymmetricAlgorithm>, &SessionKey) -> bool,
+    ) -> openpgp::Result<Option<Cert>> {
+        for cert in self.certs.iter().filter(|c| c.is_tsk()) {
+            for ka in cert
+                .keys()
+                .secret()
+                .with_policy(self.policy, None)
+                .supported()
+                .for_storage_encryption()
+                .for_transport_encryption()
+            {
+                let handle = KeyHandle::from(ka.key().fingerprint());
+                for pkesk in pkesks
+                    .iter()
+                    .filter(|p| p.recipient().map(|r| r.aliases(&handle)).unwrap_or(false))
+                {
+                    self.had_secret_key.set(true);
+                    let mut pair = keypair(ka.key().clone().role_into_unspecified())
+                        .map_err(|e| openpgp::anyhow::anyhow!("{}", e))?;
+                    if pkesk
+                        .decrypt(&mut pair, sym_algo)
+                        .map(|(algo, session_key)| decrypt(algo, &session_key))
+                        .unwrap_or(false)
+                    {
+                        return Ok(Some(cert.clone()));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl SequoiaBackend {
+    fn encrypt_with(
+        &self,
+        keys: &[Key],
+        plaintext: &[u8],
+        model: &TrustModel,
+        signing_key: Option<&Key>,
+    ) -> Result<Vec<u8>, Error> {
+        let p = policy();
+        let all_certs = self.certs()?;
+        let certs = keys
+            .iter()
+            .map(|k| {
+                all_certs
+                    .iter()
+                    .find(|c| c.fingerprint().to_hex() == k.fingerprint)
+                    .ok_or_else(|| format_err!("No key with fingerprint '{}' found", k.fingerprint))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let own_certs = own_certs(&all_certs);
+
+        let mut offending_recipients = Vec::new();
+        let mut untrusted = false;
+        let mut recipients = Vec::new();
+        for (key, cert) in keys.iter().zip(&certs) {
+            if *model == TrustModel::GpgWebOfTrust && !is_valid(cert, &own_certs) {
+                untrusted = true;
+                offending_recipients.push(format!(
+                    "Could not encrypt for recipient {} with error: Unusable public key",
+                    FingerprintUserId(key)
+                ));
+                continue;
+            }
+            let cert_recipients = encryption_keys(&p, cert);
+            if cert_recipients.is_empty() {
+                offending_recipients.push(format!(
+                    "Could not encrypt for recipient {} with error: No encryption capable key",
+                    FingerprintUserId(key)
+                ));
+            }
+            recipients.extend(cert_recipients);
+        }
+        if !offending_recipients.is_empty() {
+            return Err(EncryptionError {
+                msg: if untrusted {
+                    "At least one recipient you try to encrypt for is untrusted. \
+                     Consider (locally) signing the key with `gpg --sign-key <recipient>` \
+                     or ultimately trusting them."
+                        .into()
+                } else {
+                    "Failed to encrypt data.".into()
+                },
+                offending_recipients,
+            }
+            .into());
+        }
+
+        let signer = match signing_key {
+            Some(signing_key) => Some(signing_keypair(
+                &p,
+                own_certs
+                    .iter()
+                    .find(|c| c.fingerprint().to_hex() == signing_key.fingerprint)
+                    .ok_or_else(|| format_err!("No secret key with fingerprint '{}' found", signing_key.fingerprint))?,
+            )?),
+            None => None,
+        };
+
+        let mut ciphertext = Vec::new();
+        {
+            let message = Message::new(&mut ciphertext);
+            let mut message = Encryptor::for_recipients(message, recipients)
+                .build()
+                .map_err(pgp_error)?;
+            if let Some(signer) = signer {
+                message = Signer::new(message, signer)
+                    .and_then(Signer::build)
+                    .map_err(pgp_error)?;
+            }
+            let mut message = LiteralWriter::new(message).build().map_err(pgp_error)?;
+            message.write_all(plaintext)?;
+            message.finalize().map_err(pgp_error)?;
+        }
+        Ok(ciphertext)
+    }
+
+    fn decrypt_with_signers(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error> {
+        let p = policy();
+        let had_secret_key = Rc::new(Cell::new(false));
+        let helper = Helper {
+            policy: &p,
+            certs: self.certs()?,
+            signers: Vec::new(),
+            had_secret_key: had_secret_key.clone(),
+        };
+        let mut plaintext = PlaintextWriter::with_capacity(ciphertext.len());
+        let mut decryptor = DecryptorBuilder::from_bytes(ciphertext)
+            .and_then(|d| d.with_policy(&p, None, helper))
+            .map_err(|e| {
+                if had_secret_key.get() {
+                    pgp_error(e).context("Failed to decrypt data.").into()
+                } else {
+                    Error::from(DecryptionError { cause: pgp_error(e) })
+                }
+            })?;
+        io::copy(&mut decryptor, &mut plaintext).context("Failed to decrypt data.")?;
+        Ok((plaintext.into_inner(), decryptor.into_helper().signers))
+    }
+}
+
+impl Backend for SequoiaBackend {
+    fn encrypt(&self, keys: &[Key], plaintext: &[u8], model: &TrustModel) -> Result<Vec<u8>, Error> {
+        self.encrypt_with(keys, plaintext, model, None)
+    }
+
+    fn encrypt_signed(
+        &self,
+        keys: &[Key],
+        plaintext: &[u8],
+        model: &TrustModel,
+        signing_key: &Key,
+    ) -> Result<Vec<u8>, Error> {
+        self.encrypt_with(keys, plaintext, model, Some(signing_key))
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.decrypt_with_signers(ciphertext).map(|(plaintext, _)| plaintext)
+    }
+
+    fn decrypt_verified(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error> {
+        self.decrypt_with_signers(ciphertext)
+    }
+
+    fn sign_detached(&self, data: &[u8], signing_key: &Key) -> Result<Vec<u8>, Error> {
+        let p = policy();
+        let signer = signing_keypair(&p, &self.cert(&signing_key.fingerprint)?)?;
+        let mut signature = Vec::new();
+        {
+            let message = Message::new(&mut signature);
+            let message = Armorer::new(message)
+                .kind(armor::Kind::Signature)
+                .build()
+                .map_err(pgp_error)?;
+            let mut message = Signer::new(message, signer)
+                .map(Signer::detached)
+                .and_then(Signer::build)
+                .map_err(pgp_error)?;
+            message.write_all(data)?;
+            message.finalize().map_err(pgp_error)?;
+        }
+        Ok(signature)
+    }
+
+    fn verify_detached(&self, data: &[u8], signature: &[u8]) -> Result<Vec<String>, Error> {
+        let p = policy();
+        let helper = Helper {
+            policy: &p,
+            certs: self.certs()?,
+            signers: Vec::new(),
+            had_secret_key: Rc::new(Cell::new(false)),
+        };
+        let mut verifier = DetachedVerifierBuilder::from_bytes(signature)
+            .and_then(|v| v.with_policy(&p, None, helper))
+            .map_err(pgp_error)
+            .context("Failed to read signature.")?;
+        verifier
+            .verify_bytes(data)
+            .map_err(pgp_error)
+            .context("Failed to verify signature.")?;
+        Ok(verifier.into_helper().signers)
+    }
+
+    fn recipients_of(&self, ciphertext: &[u8]) -> Result<Vec<String>, Error> {
+        recipient_key_ids(ciphertext)
+    }
+
+    fn recipient_ids(&self, key: &Key) -> Result<Vec<String>, Error> {
+        Ok(self
+            .cert(&key.fingerprint)?
+            .keys()
+            .map(|k| k.key().keyid().to_hex())
+            .collect())
+    }
+
+    /// Like `gpg`, refuse to pick one of several keys matching `id`.
+    fn key(&self, id: &str) -> Result<Key, Error> {
+        let certs = self.certs()?;
+        match matching(&certs, &[id.to_owned()]).as_slice() {
+            [] => Err(format_err!("No key matching '{}' found", id)),
+            [cert] => Ok(key_from(cert, &own_certs(&certs))),
+            certs => Err(format_err!(
+                "The id '{}' is ambiguous, as it matches the keys {}",
+                id,
+                certs
+                    .iter()
+                    .map(|c| c.fingerprint().to_hex())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    fn find_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        let certs = self.certs()?;
+        let own_certs = own_certs(&certs);
+        Ok(matching(&certs, ids).iter().map(|c| key_from(c, &own_certs)).collect())
+    }
+
+    fn find_secret_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        let certs = self.certs()?;
+        let own_certs = own_certs(&certs);
+        Ok(matching(&certs, ids)
+            .iter()
+            .filter(|c| c.is_tsk())
+            .map(|c| key_from(c, &own_certs))
+            .collect())
+    }
+
+    /// Keys are imported from key directories, so secret key material is never imported.
+    fn import_keys(&self, key_data: &[u8]) -> Result<Vec<String>, Error> {
+        let certs: Vec<Cert> = parse_certs(key_data)?
+            .into_iter()
+            .map(Cert::strip_secret_key_material)
+            .collect();
+        let fingerprints = certs.iter().map(|c| c.fingerprint().to_hex()).collect();
+        self.write_keyring(&merge(self.keyring()?, certs)?)?;
+        Ok(fingerprints)
+    }
+
+    fn export_key(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.cert(&key.fingerprint)?
+            .armored()
+            .serialize(&mut buf)
+            .map_err(pgp_error)
+            .with_context(|_| err_msg("Failed to export at least one public key with signatures."))?;
+        Ok(buf)
+    }
+
+    fn sign_key(&self, key: &Key, signing_key: &Key) -> Result<(), Error> {
+        let p = policy();
+        let signer = self.cert(&signing_key.fingerprint)?;
+        let signer_key = signer
+            .keys()
+            .secret()
+            .with_policy(&p, None)
+            .supported()
+            .alive()
+            .revoked(false)
+            .for_certification()
+            .next()
+            .ok_or_else(|| format_err!("The key {} cannot be used for signing", signing_key.fingerprint))?;
+        let mut signer_pair = keypair(signer_key.key().clone().role_into_unspecified())?;
+
+        let cert = self.cert(&key.fingerprint)?;
+        let mut certifications = Vec::new();
+        for uid in cert.userids() {
+            certifications.push(openpgp::Packet::from(uid.userid().clone()));
+            certifications.push(
+                uid.userid()
+                    .certify(&mut signer_pair, &cert, SignatureType::GenericCertification, None, None)
+                    .map_err(pgp_error)?
+                    .into(),
+            );
+        }
+        let (cert, _) = cert.insert_packets(certifications).map_err(pgp_error)?;
+        self.write_keyring(&merge(self.keyring()?, vec![cert])?)
+    }
+}
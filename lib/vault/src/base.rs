@@ -1,18 +1,22 @@
+use crate::backend::{Backend, Key, SharedBackend};
 use crate::error::{IOMode, VaultError};
+use crate::rules::AccessRule;
+use crate::signature::verified_recipients_default;
 use crate::spec::WriteMode;
-use crate::util::{strip_ext, write_at, FingerprintUserId, ResetCWD};
+use crate::transaction::{with_suffix, ChangedPaths, Transaction};
+use crate::util::{absolute, write_at, FingerprintUserId};
 use failure::{err_msg, Error, ResultExt};
-use glob::glob;
-use gpgme;
 use serde_yaml;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io;
 use std::io::{stdin, BufRead, BufReader, Read, Write};
 use std::iter::once;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread;
 
 pub const GPG_GLOB: &str = "**/*.gpg";
 pub fn recipients_default() -> PathBuf {
@@ -60,6 +64,35 @@ impl FromStr for TrustModel {
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone, Copy, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encryption {
+    #[default]
+    Gpg,
+    Age,
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        Ok(match s {
+            "gpg" => Encryption::Gpg,
+            "age" => Encryption::Age,
+            _ => return Err(format!("Unknown encryption: '{}'", s)),
+        })
+    }
+}
+
+/// What to do if a resource was not signed by one of the current recipients of its partition.
+/// If set for a partition, its resources are also signed when they are encrypted.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignaturePolicy {
+    Warn,
+    Require,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Hash)]
 pub struct Vault {
     pub name: Option<String>,
@@ -73,10 +106,44 @@ pub struct Vault {
     pub resolved_at: PathBuf,
     #[serde(skip)]
     pub vault_path: Option<PathBuf>,
+    #[serde(skip)]
+    pub backend: SharedBackend,
+    #[serde(skip)]
+    pub jobs: Option<usize>,
+    /// The files written or removed by this vault and all of its partitions, see `set_changed_paths()`.
+    #[serde(skip)]
+    pub changed_paths: ChangedPaths,
+    /// The file recording the recipients of each partition when their signature was last verified,
+    /// see `verify_recipients_list()`.
+    #[serde(skip, default = "verified_recipients_default")]
+    pub verified_recipients: PathBuf,
     #[serde(default)]
     pub auto_import: Option<bool>,
     #[serde(default)]
     pub trust_model: Option<TrustModel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_commit: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<SignaturePolicy>,
+    /// Named groups of recipients by their fingerprints, which recipients files can refer to as `@name`.
+    /// Only the groups of the first vault in the configuration file are used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<BTreeMap<String, Vec<String>>>,
+    /// The groups of the first vault in the configuration file, see `share_groups()`.
+    #[serde(skip)]
+    pub shared_groups: BTreeMap<String, Vec<String>>,
+    /// Recipients of resources by their path, which differ from the recipients of this partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<AccessRule>>,
+    /// If set, the recipients of the first vault in the configuration file, the leader, are recipients
+    /// of this partition as well.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherit_recipients: Option<bool>,
+    /// The recipients file of the leader if this partition inherits its recipients, see `share_recipients()`.
+    #[serde(skip)]
+    pub inherited_recipients: Option<PathBuf>,
     #[serde(default = "secrets_default")]
     pub secrets: PathBuf,
     pub gpg_keys: Option<PathBuf>,
@@ -91,8 +158,20 @@ impl Default for Vault {
             index: 0,
             partitions: Default::default(),
             trust_model: Default::default(),
+            encryption: None,
+            auto_commit: None,
+            signatures: None,
+            groups: None,
+            shared_groups: Default::default(),
+            rules: None,
+            inherit_recipients: None,
+            inherited_recipients: None,
             auto_import: Some(true),
             vault_path: None,
+            backend: Default::default(),
+            jobs: None,
+            changed_paths: Default::default(),
+            verified_recipients: verified_recipients_default(),
             name: None,
             secrets: secrets_default(),
             resolved_at: secrets_default(),
@@ -118,11 +197,23 @@ impl Vault {
                             partitions: Vec::new(),
                             resolved_at: path.to_owned(),
                             vault_path: None,
+                            backend: Default::default(),
+                            jobs: None,
+                            changed_paths: Default::default(),
+                            verified_recipients: verified_recipients_default(),
                             secrets: PathBuf::from("."),
                             gpg_keys: None,
                             recipients: recipients_default(),
                             auto_import: Some(false),
                             trust_model: Some(TrustModel::GpgWebOfTrust),
+                            encryption: None,
+                            auto_commit: None,
+                            signatures: None,
+                            groups: None,
+                            shared_groups: Default::default(),
+                            rules: None,
+                            inherit_recipients: None,
+                            inherited_recipients: None,
                         };
                         vault = vault.set_resolved_at(
                             &recipients_path
@@ -171,7 +262,48 @@ impl Vault {
         Ok(self)
     }
 
+    /// Use the given backend for all cryptographic operations of this vault and all of its partitions.
+    pub fn set_backend(mut self, backend: SharedBackend) -> Self {
+        for partition in &mut self.partitions {
+            partition.backend = backend.clone();
+        }
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+
+    /// Set the amount of threads to use for re-encrypting resources, or use one per CPU if `None`.
+    pub fn set_jobs(mut self, jobs: Option<usize>) -> Self {
+        for partition in &mut self.partitions {
+            partition.jobs = jobs;
+        }
+        self.jobs = jobs;
+        self
+    }
+
+    /// Record all files written or removed by this vault and all of its partitions in `changed_paths`.
+    pub fn set_changed_paths(mut self, changed_paths: ChangedPaths) -> Self {
+        for partition in &mut self.partitions {
+            partition.changed_paths = changed_paths.clone();
+        }
+        self.changed_paths = changed_paths;
+        self
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+            .unwrap_or(1)
+            .max(1)
+    }
+
     pub fn validate(&self) -> Result<(), Error> {
+        for vault in once(self).chain(self.partitions.iter()) {
+            vault.validate_rules()?;
+        }
         if self.partitions.is_empty() {
             return Ok(());
         }
@@ -220,6 +352,16 @@ impl Vault {
                 seen.insert(path);
             }
         }
+        if let Some(partition) = self
+            .partitions
+            .iter()
+            .find(|p| p.encryption.is_some() && p.encryption.unwrap_or_default() != self.encryption.unwrap_or_default())
+        {
+            bail!(
+                "Partition at '{}' must use the same encryption as its leader",
+                partition.secrets_path().display()
+            );
+        }
 
         Ok(())
     }
@@ -230,26 +372,43 @@ impl Vault {
                 return Err(VaultError::ConfigurationFileExists(path.to_owned()));
             }
         }
+        let content = self.serialized(path)?;
+        write_at(path)
+            .and_then(|mut file| file.write_all(&content))
+            .map_err(|cause| VaultError::from_io_err(cause, path, &IOMode::Write))?;
+        self.changed_paths.record(path);
+        Ok(())
+    }
+
+    /// Stage the configuration file of this vault to be written once `transaction` is committed.
+    pub fn stage_serialization(&self, transaction: &mut Transaction) -> Result<(), Error> {
+        let path = absolute(
+            self.vault_path
+                .as_ref()
+                .ok_or_else(|| err_msg("Expected vault to know its configuration file"))?,
+        );
+        let content = self.serialized(&path)?;
+        transaction.stage_write(&path, &content)
+    }
+
+    /// The content of the configuration file at `path` which describes this vault and all of its partitions.
+    fn serialized(&self, path: &Path) -> Result<Vec<u8>, VaultError> {
         self.validate().map_err(VaultError::Validation)?;
 
         match self.kind {
-            VaultKind::Partition => return Err(VaultError::PartitionUnsupported),
+            VaultKind::Partition => Err(VaultError::PartitionUnsupported),
             VaultKind::Leader => {
-                let mut file = write_at(path).map_err(|cause| VaultError::from_io_err(cause, path, &IOMode::Write))?;
-                let all_vaults = self.all_in_order();
-                for vault in &all_vaults {
-                    serde_yaml::to_writer(&mut file, vault)
-                        .map_err(|cause| VaultError::Serialization {
-                            cause,
-                            path: path.to_owned(),
-                        })
-                        .and_then(|_| {
-                            writeln!(file).map_err(|cause| VaultError::from_io_err(cause, path, &IOMode::Write))
-                        })?;
+                let mut content = Vec::new();
+                for vault in self.all_in_order() {
+                    serde_yaml::to_writer(&mut content, vault).map_err(|cause| VaultError::Serialization {
+                        cause,
+                        path: path.to_owned(),
+                    })?;
+                    content.push(b'\n');
                 }
+                Ok(content)
             }
         }
-        Ok(())
     }
 
     pub fn absolute_path(&self, path: &Path) -> PathBuf {
@@ -275,15 +434,11 @@ impl Vault {
         for partition in once(self).chain(self.partitions.iter()) {
             writeln!(w, "{}", partition.url())?;
             let dir = partition.secrets_path();
-            if !dir.is_dir() {
-                continue;
-            }
-            let _change_cwd = ResetCWD::from_path(&dir)?;
-            for entry in glob(GPG_GLOB).expect("valid pattern").filter_map(Result::ok) {
+            for resource in partition.resources()? {
                 if has_multiple_partitions {
-                    writeln!(w, "{}", dir.join(strip_ext(&entry)).display())?;
+                    writeln!(w, "{}", dir.join(resource).display())?;
                 } else {
-                    writeln!(w, "{}", strip_ext(&entry).display())?;
+                    writeln!(w, "{}", resource.display())?;
                 }
             }
         }
@@ -291,6 +446,18 @@ impl Vault {
     }
 
     pub fn write_recipients_list(&self, recipients: &mut Vec<String>) -> Result<PathBuf, Error> {
+        let mut transaction = Transaction::default().record_changes_in(self.changed_paths.clone());
+        let recipients_path = self.stage_recipients_list(recipients, &mut transaction)?;
+        transaction.commit()?;
+        Ok(recipients_path)
+    }
+
+    /// As `write_recipients_list()`, but only writes the recipients file once `transaction` is committed.
+    pub fn stage_recipients_list(
+        &self,
+        recipients: &mut Vec<String>,
+        transaction: &mut Transaction,
+    ) -> Result<PathBuf, Error> {
         recipients.sort();
         recipients.dedup();
 
@@ -303,17 +470,14 @@ impl Vault {
                 ))?;
             }
         }
-        let mut writer = write_at(&recipients_path).context(format!(
-            "Failed to open recipients at '{}' file for writing",
-            recipients_path.display()
-        ))?;
-        for recipient in recipients {
-            writeln!(&mut writer, "{}", recipient).context(format!(
-                "Failed to write recipient '{}' to file at '{}'",
-                recipient,
+        let content: String = recipients.iter().map(|r| format!("{}\n", r)).collect();
+        transaction
+            .stage_write(&recipients_path, content.as_bytes())
+            .context(format!(
+                "Failed to write recipients to file at '{}'",
                 recipients_path.display()
-            ))?
-        }
+            ))?;
+        self.stage_recipients_signature(transaction)?;
         Ok(recipients_path)
     }
 
@@ -321,29 +485,65 @@ impl Vault {
         self.absolute_path(&self.recipients)
     }
 
+    /// The entries of the recipients file as they are, which may refer to groups as `@name`.
+    pub fn recipients_entries(&self) -> Result<Vec<String>, Error> {
+        read_recipients_list(&self.recipients_path())
+    }
+
+    /// The entries of the recipients file of the leader if this partition inherits its recipients.
+    pub fn inherited_recipients_entries(&self) -> Result<Vec<String>, Error> {
+        match self.inherited_recipients.as_ref() {
+            Some(path) => read_recipients_list(path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The recipients of this partition, including inherited ones, with references to groups replaced by their members.
     pub fn recipients_list(&self) -> Result<Vec<String>, Error> {
-        let recipients_file_path = self.recipients_path();
-        let rfile = File::open(&recipients_file_path).map(BufReader::new).context(format!(
-            "Could not open recipients file at '{}' for reading",
-            recipients_file_path.display()
-        ))?;
-        Ok(rfile.lines().collect::<Result<_, _>>().context(format!(
-            "Could not read all recipients from file at '{}'",
-            recipients_file_path.display()
-        ))?)
+        let mut entries = self.recipients_entries()?;
+        entries.extend(self.inherited_recipients_entries()?);
+        self.expand_groups(entries)
+    }
+
+    /// The recipients as they will be once `transaction` is committed.
+    pub fn staged_recipients_list(&self, transaction: &Transaction) -> Result<Vec<String>, Error> {
+        let read_staged = |path: &Path| match transaction.staged_path(path) {
+            Some(staged) => read_recipients_list(staged),
+            None => read_recipients_list(path),
+        };
+        let mut entries = read_staged(&self.recipients_path())?;
+        if let Some(path) = self.inherited_recipients.as_ref() {
+            entries.extend(read_staged(path)?);
+        }
+        self.expand_groups(entries)
+    }
+
+    /// The journal of an interrupted transaction is kept next to the vault configuration file.
+    pub fn journal_path(&self) -> Option<PathBuf> {
+        self.vault_path.as_ref().map(|p| with_suffix(p, ".journal"))
+    }
+
+    /// Start a transaction which can be resumed if it is interrupted, if this vault has a configuration file.
+    pub fn transaction(&self) -> Result<Transaction, Error> {
+        let transaction = match self.journal_path() {
+            Some(journal_path) => Transaction::journaled(&journal_path)?,
+            None => Transaction::default(),
+        };
+        Ok(transaction.record_changes_in(self.changed_paths.clone()))
     }
 
     pub fn keys_by_ids(
         &self,
-        ctx: &mut gpgme::Context,
+        backend: &dyn Backend,
         ids: &[String],
         type_of_ids_for_errors: &str,
         gpg_keys_dir: Option<&Path>,
         output: &mut dyn io::Write,
-    ) -> Result<Vec<gpgme::Key>, Error> {
-        ctx.find_keys(ids)
+    ) -> Result<Vec<Key>, Error> {
+        backend
+            .find_keys(ids)
             .context(format!("Could not iterate keys for given {}s", type_of_ids_for_errors))?;
-        let (keys, missing): (Vec<gpgme::Key>, Vec<String>) = ids.iter().map(|id| (ctx.get_key(id), id)).fold(
+        let (keys, missing): (Vec<Key>, Vec<String>) = ids.iter().map(|id| (backend.key(id), id)).fold(
             (Vec::new(), Vec::new()),
             |(mut keys, mut missing), (r, id)| {
                 match r {
@@ -360,9 +560,9 @@ impl Vault {
         let diff: isize = ids.len() as isize - keys.len() as isize;
         let mut msg = vec![if diff > 0 {
             if let Some(dir) = gpg_keys_dir {
-                self.import_keys(ctx, dir, &missing, output)
+                self.import_keys(backend, dir, &missing, output)
                     .context("Could not auto-import all required keys")?;
-                return self.keys_by_ids(ctx, ids, type_of_ids_for_errors, None, output);
+                return self.keys_by_ids(backend, ids, type_of_ids_for_errors, None, output);
             }
 
             let mut msg = format!(
@@ -419,10 +619,10 @@ impl Vault {
 
     pub fn recipient_keys(
         &self,
-        ctx: &mut gpgme::Context,
+        backend: &dyn Backend,
         gpg_keys_dir: Option<&Path>,
         output: &mut dyn io::Write,
-    ) -> Result<Vec<gpgme::Key>, Error> {
+    ) -> Result<Vec<Key>, Error> {
         let recipients_fprs = self.recipients_list()?;
         if recipients_fprs.is_empty() {
             return Err(format_err!(
@@ -430,7 +630,8 @@ impl Vault {
                 self.recipients.display()
             ));
         }
-        self.keys_by_ids(ctx, &recipients_fprs, "recipient", gpg_keys_dir, output)
+        self.verify_recipients_list(backend, output)?;
+        self.keys_by_ids(backend, &recipients_fprs, "recipient", gpg_keys_dir, output)
     }
 
     fn vault_path_for_display(&self) -> String {
@@ -494,11 +695,23 @@ impl VaultExt for Vec<Vault> {
         });
 
         vault.partitions = self;
-        Ok(vault)
+        let backend = SharedBackend::for_vault(&vault);
+        Ok(vault.set_backend(backend).share_groups().share_recipients())
     }
 }
 
-fn normalize(p: &Path) -> PathBuf {
+fn read_recipients_list(recipients_file_path: &Path) -> Result<Vec<String>, Error> {
+    let rfile = File::open(recipients_file_path).map(BufReader::new).context(format!(
+        "Could not open recipients file at '{}' for reading",
+        recipients_file_path.display()
+    ))?;
+    Ok(rfile.lines().collect::<Result<_, _>>().context(format!(
+        "Could not read all recipients from file at '{}'",
+        recipients_file_path.display()
+    ))?)
+}
+
+pub fn normalize(p: &Path) -> PathBuf {
     use std::path::Component;
     let mut p = p.components().fold(PathBuf::new(), |mut p, c| {
         match c {
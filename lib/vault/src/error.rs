@@ -1,12 +1,7 @@
-use crate::util::write_at;
-use crate::util::FingerprintUserId;
 use failure;
 use failure::Fail;
-use gpgme;
-use mktemp::Temp;
 use serde_yaml;
 use std::fmt;
-use std::fs::File;
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -14,17 +9,7 @@ use std::path::{Path, PathBuf};
 #[fail(display = "The content was not encrypted for you.")]
 pub struct DecryptionError {
     #[cause]
-    pub cause: gpgme::Error,
-}
-
-impl DecryptionError {
-    pub fn caused_by(err: gpgme::Error, alternative_text: &'static str) -> failure::Error {
-        if err.code() == gpgme::Error::NO_SECKEY.code() {
-            failure::Error::from(DecryptionError { cause: err })
-        } else {
-            err.context(alternative_text).into()
-        }
-    }
+    pub cause: failure::Error,
 }
 
 #[derive(Debug, Fail)]
@@ -43,51 +28,6 @@ impl fmt::Display for EncryptionError {
     }
 }
 
-fn find_offending_keys(ctx: &mut gpgme::Context, keys: &[gpgme::Key]) -> Result<Vec<String>, failure::Error> {
-    let mut output = Vec::new();
-    let mut obuf = Vec::<u8>::new();
-    let temp = Temp::new_file()?;
-    let temp_path = temp.to_path_buf();
-    {
-        let _ibuf = write_at(&temp_path)?;
-    }
-    let mut ibuf = File::open(&temp_path)?;
-    for key in keys {
-        if let Err(err) = ctx.encrypt(Some(key), &mut ibuf, &mut obuf) {
-            output.push(format!(
-                "Could not encrypt for recipient {} with error: {}",
-                FingerprintUserId(key),
-                err
-            ));
-        }
-    }
-    Ok(output)
-}
-
-impl EncryptionError {
-    pub fn caused_by(
-        err: gpgme::Error,
-        alternative_text: String,
-        ctx: &mut gpgme::Context,
-        keys: &[gpgme::Key],
-    ) -> failure::Error {
-        failure::Error::from(EncryptionError {
-            msg: if err.code() == gpgme::Error::UNUSABLE_PUBKEY.code() {
-                "At least one recipient you try to encrypt for is untrusted. \
-                 Consider (locally) signing the key with `gpg --sign-key <recipient>` \
-                 or ultimately trusting them."
-                    .into()
-            } else {
-                alternative_text
-            },
-            offending_recipients: match find_offending_keys(ctx, keys) {
-                Ok(v) => v,
-                Err(e) => return e,
-            },
-        })
-    }
-}
-
 #[derive(Debug, Fail)]
 pub enum VaultError {
     ConfigurationFileExists(PathBuf),
@@ -0,0 +1,193 @@
+use crate::backend::{Backend, Key};
+use crate::base::{Encryption, Vault};
+use crate::transaction::is_transaction_file;
+use crate::util::{absolute, FingerprintUserId, ResetCWD};
+use failure::{Error, ResultExt};
+use glob::glob;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+fn describe_key_id(backend: &dyn Backend, key_id: &str) -> String {
+    match backend.key(key_id) {
+        Ok(key) => format!("{}", FingerprintUserId(&key)),
+        Err(_) => format!("unknown key {}", key_id),
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Return a description of everything that is wrong with a resource encrypted for the given `key_ids`,
+/// given the `recipients` it should be encrypted for along with all of their ids.
+fn problems_of(backend: &dyn Backend, key_ids: &[String], recipients: &[(Key, Vec<String>)]) -> Vec<String> {
+    let is_one_of = |ids: &[String], id: &str| ids.iter().any(|other| other.eq_ignore_ascii_case(id));
+    let extra = key_ids
+        .iter()
+        .filter(|id| !recipients.iter().any(|(_, ids)| is_one_of(ids, id)))
+        .map(|id| format!("encrypted for {}, who is not a recipient", describe_key_id(backend, id)));
+    let missing = recipients
+        .iter()
+        .filter(|(_, ids)| !ids.iter().any(|id| is_one_of(key_ids, id)))
+        .map(|(key, _)| format!("not encrypted for recipient {}", FingerprintUserId(key)));
+    extra.chain(missing).collect()
+}
+
+impl Vault {
+    /// The files and directories of the vault itself, which may be placed among its resources.
+    fn own_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![absolute(&self.verified_recipients)];
+        files.extend(self.vault_path.iter().map(|p| absolute(p)));
+        files.extend(self.journal_path().map(|p| absolute(&p)));
+        for partition in self.all_in_order() {
+            files.push(absolute(&partition.recipients_path()));
+            files.push(absolute(&partition.recipients_signature_path()));
+            files.extend(partition.gpg_keys_dir().ok().map(|p| absolute(&p)));
+        }
+        files
+    }
+
+    /// Verify that all resources of all partitions are encrypted for exactly the recipients of their partition,
+    /// as adjusted by its access rules, without decrypting them. Access rules which contradict the recipients of
+    /// their partition count as problems, too, as do files which are not encrypted at all, whether or not they have
+    /// the '.gpg' extension. Hidden files and the files of the vault itself are not resources.
+    /// The recipients of age-encrypted resources cannot be determined without decrypting them, which is why only
+    /// their encryption is verified.
+    /// Every problem is written to `output`, and an error is returned if there was one.
+    pub fn fsck(&self, output: &mut dyn Write, error: &mut dyn Write) -> Result<(), Error> {
+        let backend = self.backend();
+        let checks_recipients = self.encryption.unwrap_or_default() != Encryption::Age;
+        let has_multiple_partitions = !self.partitions.is_empty();
+        let own_files = self.own_files();
+        let (mut num_resources, mut num_problems) = (0, 0);
+        let mut ids_by_fingerprint = HashMap::new();
+        for partition in self.all_in_order() {
+            let keys = match partition.resource_keys(
+                backend,
+                self.gpg_keys_dir_for_auto_import(partition).as_deref(),
+                error,
+            ) {
+                Ok(keys) => Some(keys),
+                Err(err) => {
+                    num_problems += 1;
+                    writeln!(
+                        output,
+                        "{}: could not determine the keys of its recipients: {}",
+                        partition.recipients_path().display(),
+                        err
+                    )
+                    .ok();
+                    None
+                }
+            };
+            for problem in keys.iter().flat_map(|keys| keys.contradictions()) {
+                num_problems += 1;
+                writeln!(output, "{}: {}", partition.recipients_path().display(), problem).ok();
+            }
+
+            let dir = partition.secrets_path();
+            if !dir.is_dir() {
+                continue;
+            }
+            let nested_partitions: Vec<PathBuf> = self
+                .all_in_order()
+                .into_iter()
+                .map(|p| absolute(&p.secrets_path()))
+                .filter(|other| other != &absolute(&dir) && other.starts_with(absolute(&dir)))
+                .collect();
+            let files: Vec<PathBuf> = {
+                let _change_cwd = ResetCWD::from_path(&dir)?;
+                glob("**/*").expect("valid pattern").filter_map(Result::ok).collect()
+            };
+            for resource in files {
+                let path = dir.join(&resource);
+                let absolute_path = absolute(&path);
+                if !path.is_file()
+                    || is_hidden(&resource)
+                    || is_transaction_file(&path)
+                    || own_files
+                        .iter()
+                        .chain(&nested_partitions)
+                        .any(|p| absolute_path.starts_with(p))
+                {
+                    continue;
+                }
+                num_resources += 1;
+                let display_path = if has_multiple_partitions { &path } else { &resource };
+                if resource.extension() != Some(OsStr::new("gpg")) {
+                    num_problems += 1;
+                    writeln!(output, "{}: not encrypted", display_path.display()).ok();
+                    continue;
+                }
+                let ciphertext = fs::read(&path).context(format!("Could not read resource at '{}'", path.display()))?;
+                let key_ids = match backend.recipients_of(&ciphertext) {
+                    Ok(key_ids) => key_ids,
+                    Err(_) if !checks_recipients => continue,
+                    Err(err) => {
+                        num_problems += 1;
+                        writeln!(
+                            output,
+                            "{}: could not determine its recipients: {}",
+                            display_path.display(),
+                            err
+                        )
+                        .ok();
+                        continue;
+                    }
+                };
+                if key_ids.is_empty() {
+                    num_problems += 1;
+                    writeln!(output, "{}: not encrypted", display_path.display()).ok();
+                    continue;
+                }
+                let keys = match keys {
+                    Some(ref keys) => keys,
+                    None => continue,
+                };
+                let mut recipients = Vec::new();
+                for key in keys.for_resource(&resource) {
+                    if !ids_by_fingerprint.contains_key(&key.fingerprint) {
+                        ids_by_fingerprint.insert(key.fingerprint.clone(), backend.recipient_ids(&key)?);
+                    }
+                    let ids = ids_by_fingerprint[&key.fingerprint].clone();
+                    recipients.push((key, ids));
+                }
+                for problem in problems_of(backend, &key_ids, &recipients) {
+                    num_problems += 1;
+                    writeln!(output, "{}: {}", display_path.display(), problem).ok();
+                }
+            }
+        }
+        if num_problems == 0 {
+            if checks_recipients {
+                writeln!(
+                    output,
+                    "All {} resource(s) are encrypted for exactly their recipients.",
+                    num_resources
+                )
+                .ok();
+            } else {
+                writeln!(
+                    output,
+                    "All {} resource(s) are encrypted. Their recipients were not checked, as those of age-encrypted \
+                     resources cannot be determined without decrypting them.",
+                    num_resources
+                )
+                .ok();
+            }
+            Ok(())
+        } else {
+            Err(format_err!(
+                "Found {} problem(s) while checking {} resource(s).",
+                num_problems,
+                num_resources
+            ))
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use crate::base::{normalize, Vault};
+use crate::error::{first_cause_of_type, DecryptionError};
+use crate::signature;
+use crate::util::{shell_quote, strip_ext, write_at, PlaintextFile};
+use failure::{err_msg, Error, ResultExt};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path};
+use std::process::Command;
+use std::str;
+use tools::process::{merge_three_way, OutputMode};
+use zeroize::Zeroizing;
+
+const GIT_DRIVER: &str = "sheesy";
+
+/// Return the pattern matching all resources in the `secrets` directory of a partition, relative to the
+/// vault directory, or `None` if it is not within the vault directory.
+fn resources_pattern(secrets: &Path) -> Option<String> {
+    let mut components = Vec::new();
+    for component in secrets.components() {
+        match component {
+            Component::Normal(c) => components.push(c.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    components.push("**/*.gpg");
+    Some(components.join("/"))
+}
+
+/// Run git with `args` in `dir`, and return what it wrote to stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, Error> {
+    let result = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("Failed to run 'git' - is it installed?")?;
+    if !result.status.success() {
+        bail!(
+            "'git {}' failed in '{}': {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+    Ok(result.stdout)
+}
+
+/// Return the format by which the resource at `path` can be merged structurally, judging by its extension.
+fn structured_format(path: &Path) -> Option<OutputMode> {
+    let path = if path.extension().is_some_and(|ext| ext == "gpg") {
+        strip_ext(path)
+    } else {
+        path.to_owned()
+    };
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Some(OutputMode::Json),
+        Some("yml") | Some("yaml") => Some(OutputMode::Yaml),
+        _ => None,
+    }
+}
+
+/// Merge the changes from `base` to `theirs` into `ours` line by line using 'git merge-file', and return
+/// the result along with the amount of conflicts, which are marked within it.
+/// The plaintext only touches private temporary files, which are removed right away.
+fn merge_lines(base: &[u8], ours: &[u8], theirs: &[u8]) -> Result<(Zeroizing<Vec<u8>>, usize), Error> {
+    let files = [ours, base, theirs]
+        .iter()
+        .map(|content| {
+            let file = PlaintextFile::new("merge").context("Could not create temporary file to merge in.")?;
+            write_at(file.path())
+                .and_then(|mut w| w.write_all(content))
+                .context("Failed to write content to merge.")?;
+            Ok(file)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let result = Command::new("git")
+        .args(["merge-file", "-p", "-L", "ours", "-L", "base", "-L", "theirs"])
+        .args(files.iter().map(PlaintextFile::path))
+        .output()
+        .context("Failed to run 'git merge-file' - is git installed?")?;
+    let merged = Zeroizing::new(result.stdout);
+    match result.status.code() {
+        Some(conflicts) if (0..128).contains(&conflicts) => Ok((merged, conflicts as usize)),
+        _ => bail!(
+            "'git merge-file' failed: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ),
+    }
+}
+
+impl Vault {
+    /// Write the decrypted content of the resource file at `path` to `output`, as git does for diffs when
+    /// configured to use it as textconv program.
+    /// Resources which were not encrypted for us produce a note instead of an error, so that diffs still work.
+    pub fn git_textconv(&self, path: &Path, output: &mut dyn Write) -> Result<(), Error> {
+        let ciphertext = fs::read(path).context(format!("Could not read resource at '{}'.", path.display()))?;
+        if ciphertext.is_empty() {
+            return Ok(());
+        }
+        match self.backend().decrypt(&ciphertext) {
+            Ok(plaintext) => output.write_all(&plaintext)?,
+            Err(ref err) if first_cause_of_type::<DecryptionError>(err).is_some() => {
+                writeln!(output, "<This resource was not encrypted for you.>")?
+            }
+            Err(err) => {
+                return Err(err
+                    .context(format!("Failed to decrypt resource at '{}'.", path.display()))
+                    .into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge the changes made from the resource at `base` to the one at `theirs` into the one at `ours`, and write
+    /// the result to `ours`, re-encrypted for the recipients of its partition, as git expects from a merge driver.
+    ///
+    /// `path` is the path of the resource in the work tree, which is needed to find its partition if there
+    /// are multiple. Resources are merged line by line, and if that fails, JSON and YAML resources are merged
+    /// structurally. Remaining conflicts are marked within the encrypted content, and cause an error.
+    pub fn git_merge_driver(
+        &self,
+        base: &Path,
+        ours: &Path,
+        theirs: &Path,
+        path: Option<&Path>,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let (partition, resource_path) = match path {
+            Some(path) => {
+                let cwd = env::current_dir()?;
+                let vault_dir = normalize(&cwd.join(&self.resolved_at));
+                let resource = normalize(&cwd.join(path));
+                let resource = resource.strip_prefix(&vault_dir).map_err(|_| {
+                    format_err!(
+                        "The resource at '{}' is not within the vault at '{}'",
+                        path.display(),
+                        vault_dir.display()
+                    )
+                })?;
+                let (partition, resource) = self.partition_by_owned_path(resource.to_owned())?;
+                (partition, Some(resource))
+            }
+            None if self.has_rules() => {
+                bail!("The path of the resource in the work tree is needed to apply the access rules of its partition.")
+            }
+            None if self.partitions.is_empty() => (self, None),
+            None => bail!("The path of the resource in the work tree is needed to find its partition."),
+        };
+        let mut decrypt = |file: &Path| -> Result<Zeroizing<Vec<u8>>, Error> {
+            let ciphertext = fs::read(file).context(format!("Could not read resource at '{}'.", file.display()))?;
+            if ciphertext.is_empty() {
+                return Ok(Zeroizing::new(ciphertext));
+            }
+            let (plaintext, warning) = partition
+                .decrypt_verified(self.backend(), &ciphertext, path.unwrap_or(file))
+                .context(format!("Failed to decrypt resource at '{}'.", file.display()))?;
+            if let Some(warning) = warning {
+                writeln!(output, "{}", warning).ok();
+            }
+            Ok(plaintext)
+        };
+        let (base_content, our_content, their_content) = (decrypt(base)?, decrypt(ours)?, decrypt(theirs)?);
+
+        let (mut merged, mut conflicts) = merge_lines(&base_content, &our_content, &their_content)?;
+        if let Some(mode) = path.and_then(structured_format).filter(|_| conflicts > 0) {
+            let structured = str::from_utf8(&base_content)
+                .and_then(|base| str::from_utf8(&our_content).map(|ours| (base, ours)))
+                .and_then(|(base, ours)| str::from_utf8(&their_content).map(|theirs| (base, ours, theirs)))
+                .map_err(Error::from)
+                .and_then(|(base, ours, theirs)| merge_three_way(base, ours, theirs, mode));
+            if let Ok(document) = structured {
+                merged = Zeroizing::new(document.into_bytes());
+                conflicts = 0;
+            }
+        }
+
+        let keys = partition.resource_keys(
+            self.backend(),
+            self.gpg_keys_dir_for_auto_import(partition).as_deref(),
+            output,
+        )?;
+        let ciphertext = signature::encrypt(
+            self.backend(),
+            &match resource_path {
+                Some(resource_path) => keys.for_resource(&resource_path),
+                None => keys.partition_keys().to_vec(),
+            },
+            &merged,
+            &self.find_trust_model(partition),
+            partition.signing_key(self.backend())?.as_ref(),
+        )?;
+        write_at(ours)
+            .and_then(|mut w| w.write_all(&ciphertext))
+            .context(format!("Failed to write merged resource to '{}'.", ours.display()))?;
+
+        let display_path = path.unwrap_or(ours);
+        if conflicts > 0 {
+            bail!(
+                "Merged '{}' with {} conflict(s), which are marked within its encrypted content.",
+                display_path.display(),
+                conflicts
+            );
+        }
+        writeln!(output, "Merged '{}'.", display_path.display()).ok();
+        Ok(())
+    }
+
+    /// Configure the git repository containing this vault to show the plaintext of resources in diffs, and
+    /// to merge them with `git_merge_driver()`, using `program` to invoke the vault command line.
+    /// `program` is used by git as a shell command, so any paths within it must be quoted with `shell_quote()`.
+    /// All resources of all partitions within the vault directory get the 'sheesy' diff and merge drivers
+    /// assigned in the '.gitattributes' file next to the vault configuration.
+    pub fn git_setup(&self, program: &str, output: &mut dyn Write) -> Result<(), Error> {
+        let vault_path = self
+            .vault_path
+            .as_ref()
+            .ok_or_else(|| err_msg("Expected vault to know its configuration file"))?;
+        let vault_path = fs::canonicalize(vault_path).context(format!(
+            "Could not resolve the vault configuration file at '{}'",
+            vault_path.display()
+        ))?;
+
+        let attributes_path = self.resolved_at.join(".gitattributes");
+        let existing = if attributes_path.is_file() {
+            fs::read_to_string(&attributes_path).context(format!("Failed to read '{}'", attributes_path.display()))?
+        } else {
+            String::new()
+        };
+        let mut lines: Vec<String> = existing.lines().map(ToOwned::to_owned).collect();
+        let mut changed = false;
+        for partition in self.all_in_order() {
+            let pattern = match resources_pattern(&partition.secrets) {
+                Some(pattern) => pattern,
+                None => {
+                    writeln!(
+                        output,
+                        "Skipped partition at '{}' as it is not within the vault directory.",
+                        partition.secrets.display()
+                    )
+                    .ok();
+                    continue;
+                }
+            };
+            let line = format!("{} diff={} merge={}", pattern, GIT_DRIVER, GIT_DRIVER);
+            match lines
+                .iter_mut()
+                .find(|l| l.split_whitespace().next() == Some(pattern.as_str()))
+            {
+                Some(ref existing) if existing.trim() == line => continue,
+                Some(existing) => {
+                    writeln!(
+                        output,
+                        "Replaced '{}' with '{}' in '{}'.",
+                        existing,
+                        line,
+                        attributes_path.display()
+                    )
+                    .ok();
+                    *existing = line;
+                }
+                None => {
+                    writeln!(output, "Added '{}' to '{}'.", line, attributes_path.display()).ok();
+                    lines.push(line);
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            write_at(&attributes_path)
+                .and_then(|mut w| w.write_all(format!("{}\n", lines.join("\n")).as_bytes()))
+                .context(format!("Failed to write '{}'", attributes_path.display()))?;
+            self.changed_paths.record(&attributes_path);
+        }
+
+        let vault_path = vault_path.to_str().ok_or_else(|| {
+            format_err!(
+                "Cannot configure git to use the vault configuration at '{}' as its path is not valid UTF-8",
+                vault_path.display()
+            )
+        })?;
+        let command = format!("{} --config-file={}", program, shell_quote(vault_path));
+        for (key, value) in &[
+            (
+                format!("diff.{}.textconv", GIT_DRIVER),
+                format!("{} git-textconv", command),
+            ),
+            (
+                format!("merge.{}.name", GIT_DRIVER),
+                "merge encrypted vault resources".to_owned(),
+            ),
+            (
+                format!("merge.{}.driver", GIT_DRIVER),
+                format!("{} git-merge-driver %O %A %B %P", command),
+            ),
+        ] {
+            git(&self.resolved_at, &["config", key, value])?;
+            writeln!(output, "Set '{}' to {}", key, value).ok();
+        }
+        Ok(())
+    }
+
+    /// Commit the files recorded in `changed_paths` within the vault directory to git, if `auto_commit` is
+    /// enabled in the first vault of the configuration file. No other files are committed, even if they are
+    /// staged already. The commit message is obtained from `message`, given the amount of changed resources.
+    /// Nothing is committed if nothing changed.
+    pub fn commit_changes<F>(&self, message: F, output: &mut dyn Write) -> Result<(), Error>
+    where
+        F: FnOnce(usize) -> String,
+    {
+        if !self.all_in_order().first().and_then(|v| v.auto_commit).unwrap_or(false) {
+            return Ok(());
+        }
+        let dir = &self.resolved_at;
+        let absolute_dir = normalize(&env::current_dir()?.join(dir));
+        let paths =
+            self.changed_paths
+                .paths()
+                .into_iter()
+                .filter_map(|path| {
+                    let relative = path.strip_prefix(&absolute_dir).ok()?.to_str().map(ToOwned::to_owned);
+                    Some(relative.ok_or_else(|| {
+                        format_err!("Cannot commit '{}' as its path is not valid UTF-8", path.display())
+                    }))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let (existing, removed): (Vec<&str>, Vec<&str>) =
+            paths.iter().map(String::as_str).partition(|p| dir.join(p).exists());
+        if !existing.is_empty() {
+            git(dir, &[&["add", "--"], existing.as_slice()].concat())?;
+        }
+        if !removed.is_empty() {
+            git(
+                dir,
+                &[
+                    &["rm", "--cached", "--quiet", "--ignore-unmatch", "--"],
+                    removed.as_slice(),
+                ]
+                .concat(),
+            )?;
+        }
+        let pathspecs: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let staged = git(
+            dir,
+            &[
+                &[
+                    "diff",
+                    "--cached",
+                    "--name-only",
+                    "--no-renames",
+                    "--relative",
+                    "-z",
+                    "--",
+                ],
+                pathspecs.as_slice(),
+            ]
+            .concat(),
+        )?;
+        let changed: Vec<&str> = str::from_utf8(&staged)?.split('\0').filter(|p| !p.is_empty()).collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let message = message(changed.iter().filter(|p| p.ends_with(".gpg")).count());
+        git(
+            dir,
+            &[&["commit", "--quiet", "--message", &message, "--"], changed.as_slice()].concat(),
+        )?;
+        writeln!(output, "Committed {} changed file(s): {}", changed.len(), message).ok();
+        Ok(())
+    }
+}
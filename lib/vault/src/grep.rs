@@ -0,0 +1,138 @@
+use crate::base::{Vault, GPG_GLOB};
+use crate::error::{first_cause_of_type, DecryptionError};
+use crate::resource::existing_resource_path;
+use crate::util::{map_in_order, strip_ext, ResetCWD};
+use failure::{Error, ResultExt};
+use glob::glob;
+use regex::bytes::Regex;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A resource to search, along with the path it is presented by.
+struct Candidate<'a> {
+    partition: &'a Vault,
+    path: PathBuf,
+    display_path: PathBuf,
+}
+
+impl Vault {
+    /// Return all resources at `paths`, which may be resources or directories, or all resources of all partitions
+    /// if `paths` is empty.
+    fn grep_candidates(&self, paths: &[PathBuf]) -> Result<Vec<Candidate<'_>>, Error> {
+        let has_multiple_partitions = !self.partitions.is_empty();
+        let roots: Vec<(&Vault, PathBuf)> = if paths.is_empty() {
+            self.all_in_order()
+                .into_iter()
+                .filter(|partition| partition.secrets_path().is_dir())
+                .map(|partition| (partition, PathBuf::new()))
+                .collect()
+        } else {
+            paths
+                .iter()
+                .map(|path| self.partition_by_owned_path(path.to_owned()))
+                .collect::<Result<_, _>>()?
+        };
+
+        let mut candidates = Vec::new();
+        for (partition, path) in roots {
+            let secrets_dir = partition.secrets_path();
+            let dir = secrets_dir.join(&path);
+            let files: Vec<PathBuf> = if dir.is_dir() {
+                let _change_cwd = ResetCWD::from_path(&dir)?;
+                glob(GPG_GLOB)
+                    .expect("valid pattern")
+                    .filter_map(Result::ok)
+                    .map(|resource| dir.join(resource))
+                    .collect()
+            } else {
+                vec![existing_resource_path(partition, path)?]
+            };
+            candidates.extend(files.into_iter().map(|file| {
+                let resource = file
+                    .strip_prefix(&secrets_dir)
+                    .expect("resource within secrets directory");
+                let resource = if resource.extension().is_some_and(|ext| ext == "gpg") {
+                    strip_ext(resource)
+                } else {
+                    resource.to_owned()
+                };
+                Candidate {
+                    partition,
+                    display_path: if has_multiple_partitions {
+                        partition.secrets.join(resource)
+                    } else {
+                        resource
+                    },
+                    path: file,
+                }
+            }));
+        }
+        Ok(candidates)
+    }
+
+    /// Write all lines matching `pattern` of the resources at `paths`, or of all resources if there is none,
+    /// to `output`, each prefixed with the path of its resource.
+    /// Resources are decrypted in memory only. Those which were not encrypted for us are skipped with a note
+    /// written to `error`, as are warnings about their signatures, and it is an error if no line matched.
+    pub fn grep(
+        &self,
+        pattern: &str,
+        paths: &[PathBuf],
+        output: &mut dyn Write,
+        error: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let regex = Regex::new(pattern).context(format!("Invalid regular expression '{}'", pattern))?;
+        let candidates = self.grep_candidates(paths)?;
+        let mut num_matches = 0;
+        map_in_order(
+            &candidates,
+            self.jobs(),
+            |candidate| {
+                let ciphertext = fs::read(&candidate.path)
+                    .context(format!("Could not read resource at '{}'.", candidate.path.display()))?;
+                match candidate
+                    .partition
+                    .decrypt_verified(self.backend(), &ciphertext, &candidate.display_path)
+                {
+                    Ok(decrypted) => Ok(Some(decrypted)),
+                    Err(ref err) if first_cause_of_type::<DecryptionError>(err).is_some() => Ok(None),
+                    Err(err) => Err(err
+                        .context(format!("Failed to decrypt resource at '{}'.", candidate.path.display()))
+                        .into()),
+                }
+            },
+            |candidate, decrypted| {
+                let plaintext = match decrypted {
+                    Some((plaintext, warning)) => {
+                        if let Some(warning) = warning {
+                            writeln!(error, "{}", warning).ok();
+                        }
+                        plaintext
+                    }
+                    None => {
+                        writeln!(
+                            error,
+                            "Skipped '{}' as it was not encrypted for you.",
+                            candidate.display_path.display()
+                        )
+                        .ok();
+                        return Ok(());
+                    }
+                };
+                let text = plaintext.strip_suffix(b"\n").unwrap_or(&plaintext);
+                for line in text.split(|b| *b == b'\n').filter(|line| regex.is_match(line)) {
+                    num_matches += 1;
+                    write!(output, "{}:", candidate.display_path.display())?;
+                    output.write_all(line)?;
+                    writeln!(output)?;
+                }
+                Ok(())
+            },
+        )?;
+        if num_matches == 0 {
+            bail!("No line matched '{}'", pattern);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use crate::backend::Key;
+use crate::base::Vault;
+use crate::spec::SigningMode;
+use crate::transaction::Transaction;
+use crate::util::{absolute, KeyDisplay, KeylistDisplay, UserIdFingerprint};
+use failure::Error;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+const GROUP_PREFIX: char = '@';
+
+/// Return the name of the group an `entry` of a recipients file refers to, if it refers to one at all.
+fn group_name(entry: &str) -> Option<&str> {
+    entry.strip_prefix(GROUP_PREFIX)
+}
+
+impl Vault {
+    /// Make the groups of the first vault in the configuration file known to this vault and all of its partitions.
+    pub fn share_groups(mut self) -> Self {
+        let groups = self.all_in_order()[0].groups.clone().unwrap_or_default();
+        for partition in &mut self.partitions {
+            partition.shared_groups = groups.clone();
+        }
+        self.shared_groups = groups;
+        self
+    }
+
+    /// Replace all references to groups among the `entries` of the recipients file of this partition
+    /// by the members of the respective group.
+    pub fn expand_groups(&self, entries: Vec<String>) -> Result<Vec<String>, Error> {
+        let mut recipients = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match group_name(&entry) {
+                Some(name) => recipients.extend(
+                    self.shared_groups
+                        .get(name)
+                        .ok_or_else(|| {
+                            format_err!(
+                                "The recipients file at '{}' refers to the group '{}', \
+                                 which is not defined in the vault configuration.",
+                                self.recipients_path().display(),
+                                entry
+                            )
+                        })?
+                        .iter()
+                        .cloned(),
+                ),
+                None => recipients.push(entry),
+            }
+        }
+        let mut seen = HashSet::new();
+        recipients.retain(|recipient| seen.insert(recipient.clone()));
+        Ok(recipients)
+    }
+
+    /// The entries of the recipients file of this partition which refer to groups.
+    pub fn group_references(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .recipients_entries()?
+            .into_iter()
+            .filter(|entry| group_name(entry).is_some())
+            .collect())
+    }
+
+    /// The members of all groups the recipients file of this partition refers to.
+    pub fn group_members(&self) -> Result<Vec<String>, Error> {
+        self.expand_groups(self.group_references()?)
+    }
+
+    fn partitions_referring_to(&self, group: &str) -> Result<Vec<&Vault>, Error> {
+        let mut partitions = Vec::new();
+        for partition in self.all_in_order() {
+            if partition
+                .recipients_entries()?
+                .iter()
+                .chain(&partition.inherited_recipients_entries()?)
+                .chain(
+                    partition
+                        .rules
+                        .iter()
+                        .flatten()
+                        .flat_map(|rule| rule.recipients.iter().flatten().chain(&rule.extra_recipients)),
+                )
+                .any(|entry| group_name(entry) == Some(group))
+            {
+                partitions.push(partition);
+            }
+        }
+        Ok(partitions)
+    }
+
+    /// The vault whose groups are used, which is the first one in the configuration file.
+    fn first_in_order_mut(&mut self) -> &mut Vault {
+        let first_index = self.all_in_order()[0].index;
+        if self.index == first_index {
+            self
+        } else {
+            self.partitions
+                .iter_mut()
+                .find(|partition| partition.index == first_index)
+                .expect("first vault to be a partition")
+        }
+    }
+
+    /// This vault with the groups of its configuration file as they will be once `transaction` is committed.
+    pub fn with_staged_groups(&self, transaction: &Transaction) -> Result<Vault, Error> {
+        let staged = match self
+            .vault_path
+            .as_ref()
+            .and_then(|path| transaction.staged_path(&absolute(path)))
+        {
+            Some(staged) => staged,
+            None => return Ok(self.clone()),
+        };
+        let groups = Vault::from_file(staged)?
+            .into_iter()
+            .next()
+            .and_then(|first| first.groups);
+        let mut vault = self.clone();
+        vault.first_in_order_mut().groups = groups;
+        Ok(vault.share_groups())
+    }
+
+    /// Stage the configuration file with the changed `group`, along with the signatures of the recipients of
+    /// all partitions referring to it, made with `signing_keys`, and their re-encrypted resources, and commit
+    /// them all at once. The resources are verified as they were `previously`, before the change.
+    /// If `removed_fingerprints` are given, their key files are removed as well unless they are still needed.
+    fn commit_group_change(
+        &self,
+        previously: &Vault,
+        group: &str,
+        signing_keys: &[(usize, Key)],
+        removed_fingerprints: &[String],
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let mut transaction = self.transaction()?;
+        self.stage_serialization(&mut transaction)?;
+        self.stage_recipients_signatures_with(signing_keys, &mut transaction)?;
+        if let Ok(gpg_keys_dir) = self.find_gpg_keys_dir() {
+            for fingerprint in removed_fingerprints {
+                if self.is_key_needed(fingerprint, &transaction)? {
+                    continue;
+                }
+                let key_path = gpg_keys_dir.join(fingerprint);
+                if key_path.is_file() {
+                    transaction.stage_removal(&key_path)?;
+                    writeln!(output, "Removed key file at '{}'", key_path.display()).ok();
+                }
+            }
+        }
+        let partitions = self.partitions_referring_to(group)?;
+        if partitions.is_empty() {
+            writeln!(
+                output,
+                "No recipients file refers to group '{}{}' yet, so there is nothing to re-encrypt.",
+                GROUP_PREFIX, group
+            )
+            .ok();
+        }
+        let mut num_reencrypted = 0;
+        for partition in previously
+            .all_in_order()
+            .into_iter()
+            .filter(|previous| partitions.iter().any(|p| p.index == previous.index))
+        {
+            num_reencrypted += previously.reencrypt_for_staged_recipients(partition, &mut transaction, output)?;
+        }
+        transaction.commit()?;
+        if !partitions.is_empty() {
+            writeln!(
+                output,
+                "Re-encrypted {} resource(s) in {} partition(s)",
+                num_reencrypted,
+                partitions.len()
+            )
+            .ok();
+        }
+        Ok(())
+    }
+
+    /// Returns true if the key with `fingerprint` is still needed once `transaction` is committed, as it belongs
+    /// to a recipient of a partition or one of its access rules, or to the member of a group.
+    fn is_key_needed(&self, fingerprint: &str, transaction: &Transaction) -> Result<bool, Error> {
+        for partition in self.all_in_order() {
+            if partition
+                .staged_recipients_list(transaction)?
+                .into_iter()
+                .chain(partition.rule_recipients()?)
+                .chain(partition.shared_groups.values().flatten().cloned())
+                .any(|recipient| recipient == fingerprint)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Add the recipients identified by `gpg_key_ids` to `group`, which is created if it does not exist yet,
+    /// and sign the recipients of all partitions referring to it before re-encrypting them, all at once.
+    /// Keys are imported and signed like it is done by `add_recipients()`.
+    pub fn add_group_members(
+        mut self,
+        group: &str,
+        gpg_key_ids: &[String],
+        sign: SigningMode,
+        signing_key_id: Option<&str>,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let group = group.trim_start_matches(GROUP_PREFIX);
+        let keys = {
+            let partitions = self.partitions_referring_to(group)?;
+            let partition = partitions.first().cloned().unwrap_or(&self);
+            self.keys_of_new_recipients(partition, gpg_key_ids, sign, signing_key_id, output)?
+        };
+        let signing_keys = Vault::recipients_signing_keys(&self.partitions_referring_to(group)?)?;
+        let previously = self.clone();
+        {
+            let members = self
+                .first_in_order_mut()
+                .groups
+                .get_or_insert_with(BTreeMap::new)
+                .entry(group.to_owned())
+                .or_default();
+            for key in keys {
+                if members.contains(&key.fingerprint) {
+                    writeln!(
+                        output,
+                        "{} is a member of group '{}{}' already",
+                        KeyDisplay(&key),
+                        GROUP_PREFIX,
+                        group
+                    )
+                    .ok();
+                    continue;
+                }
+                members.push(key.fingerprint.clone());
+                writeln!(
+                    output,
+                    "Added {} to group '{}{}'",
+                    KeyDisplay(&key),
+                    GROUP_PREFIX,
+                    group
+                )
+                .ok();
+            }
+            members.sort();
+        }
+        self.share_groups()
+            .commit_group_change(&previously, group, &signing_keys, &[], output)
+    }
+
+    /// Remove the recipients identified by `gpg_key_ids` from `group`, and sign the recipients of all partitions
+    /// referring to it before re-encrypting them.
+    /// Like `remove_recipients()` does, their key files are removed unless they are still needed elsewhere.
+    pub fn remove_group_members(
+        mut self,
+        group: &str,
+        gpg_key_ids: &[String],
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let group = group.trim_start_matches(GROUP_PREFIX);
+        let keys = self.backend().find_keys(gpg_key_ids)?;
+        if keys.len() != gpg_key_ids.len() {
+            bail!(
+                "Found {} viable keys for key-ids ({}), for {} given user ids.",
+                keys.len(),
+                KeylistDisplay(&keys),
+                gpg_key_ids.len()
+            );
+        }
+        let signing_keys = Vault::recipients_signing_keys(&self.partitions_referring_to(group)?)?;
+        let previously = self.clone();
+        {
+            let members = self
+                .first_in_order_mut()
+                .groups
+                .as_mut()
+                .and_then(|groups| groups.get_mut(group))
+                .ok_or_else(|| {
+                    format_err!(
+                        "The group '{}{}' is not defined in the vault configuration.",
+                        GROUP_PREFIX,
+                        group
+                    )
+                })?;
+            for key in &keys {
+                if !members.contains(&key.fingerprint) {
+                    bail!(
+                        "{} is not a member of group '{}{}'.",
+                        UserIdFingerprint(key),
+                        GROUP_PREFIX,
+                        group
+                    );
+                }
+                members.retain(|member| *member != key.fingerprint);
+                writeln!(
+                    output,
+                    "Removed {} from group '{}{}'",
+                    KeyDisplay(key),
+                    GROUP_PREFIX,
+                    group
+                )
+                .ok();
+            }
+        }
+        let vault = self.share_groups();
+        for partition in vault.partitions_referring_to(group)? {
+            if partition.recipients_list()?.is_empty() {
+                bail!(
+                    "Cannot remove members of group '{}{}' as '{}' would have no recipients afterwards.",
+                    GROUP_PREFIX,
+                    group,
+                    partition.recipients_path().display()
+                );
+            }
+        }
+        let removed_fingerprints: Vec<String> = keys.into_iter().map(|key| key.fingerprint).collect();
+        vault.commit_group_change(&previously, group, &signing_keys, &removed_fingerprints, output)
+    }
+}
@@ -0,0 +1,50 @@
+use crate::base::Vault;
+use failure::Error;
+
+impl Vault {
+    /// Make the recipients file of the first vault in the configuration file, the leader, known to all partitions
+    /// which inherit its recipients.
+    pub fn share_recipients(mut self) -> Self {
+        let (leader_index, leader_recipients) = {
+            let leader = self.all_in_order()[0];
+            (leader.index, leader.recipients_path())
+        };
+        let share = |vault: &mut Vault| {
+            vault.inherited_recipients = if vault.index != leader_index && vault.inherit_recipients.unwrap_or(false) {
+                Some(leader_recipients.clone())
+            } else {
+                None
+            };
+        };
+        for partition in &mut self.partitions {
+            share(partition);
+        }
+        share(&mut self);
+        self
+    }
+
+    /// The recipients this partition inherits from the leader, with references to groups replaced by their members.
+    pub fn inherited_recipients_list(&self) -> Result<Vec<String>, Error> {
+        self.expand_groups(self.inherited_recipients_entries()?)
+    }
+
+    /// The given `partitions` along with all partitions inheriting the recipients of one of them, in order.
+    pub fn with_inheriting_partitions<'a>(&'a self, partitions: &[&'a Vault]) -> Vec<&'a Vault> {
+        self.all_in_order()
+            .into_iter()
+            .filter(|vault| {
+                partitions.iter().any(|partition| {
+                    partition.index == vault.index || vault.inherited_recipients == Some(partition.recipients_path())
+                })
+            })
+            .collect()
+    }
+
+    /// All partitions inheriting the recipients of one of the given `partitions` without being one of them, in order.
+    pub fn inheriting_partitions<'a>(&'a self, partitions: &[&'a Vault]) -> Vec<&'a Vault> {
+        self.with_inheriting_partitions(partitions)
+            .into_iter()
+            .filter(|vault| partitions.iter().all(|partition| partition.index != vault.index))
+            .collect()
+    }
+}
@@ -4,8 +4,7 @@ use crate::base::Vault;
 use crate::spec::WriteMode;
 use crate::util::export_key_with_progress;
 use crate::util::extract_at_least_one_secret_key;
-use crate::util::new_context;
-use crate::TrustModel;
+use crate::{Encryption, SharedBackend, TrustModel};
 use failure::{Error, ResultExt};
 use std::fs::create_dir_all;
 use std::io::Write;
@@ -42,6 +41,7 @@ impl Vault {
         vault_path: &Path,
         name: Option<String>,
         trust_model: Option<TrustModel>,
+        encryption: Option<Encryption>,
         auto_import: Option<bool>,
         output: &mut dyn Write,
     ) -> Result<Self, Error> {
@@ -52,19 +52,20 @@ impl Vault {
             secrets: secrets.to_owned(),
             auto_import,
             trust_model,
+            encryption,
             ..Default::default()
         }
         .set_resolved_at(vault_path)?;
+        let backend = SharedBackend::for_vault(&vault);
+        let vault = vault.set_backend(backend);
 
-        let mut gpg_ctx = new_context()?;
-        let keys = extract_at_least_one_secret_key(&mut gpg_ctx, gpg_key_ids)?;
+        let keys = extract_at_least_one_secret_key(vault.backend(), gpg_key_ids)?;
         vault.to_file(vault_path, WriteMode::RefuseOverwrite)?;
 
         let gpg_keys_dir = vault.absolute_path(gpg_keys_dir);
         let recipients_file = vault.absolute_path(recipients_file);
         assure_empty_directory_exists(&gpg_keys_dir).with_context(|_| "Cannot create gpg keys directory")?;
 
-        let mut bytes_buf = Vec::new();
         if recipients_file.is_file() {
             return Err(format_err!(
                 "Cannot write recipients into existing file at '{}'",
@@ -74,7 +75,8 @@ impl Vault {
 
         let mut recipients_fprs = Vec::new();
         for key in keys {
-            let (fingerprint, _) = export_key_with_progress(&mut gpg_ctx, &gpg_keys_dir, &key, &mut bytes_buf, output)?;
+            let (fingerprint, key_path) = export_key_with_progress(vault.backend(), &gpg_keys_dir, &key, output)?;
+            vault.changed_paths.record(&key_path);
             recipients_fprs.push(fingerprint);
         }
 
@@ -4,27 +4,50 @@ extern crate conv;
 extern crate failure;
 extern crate failure_derive;
 extern crate glob;
-extern crate gpgme;
+#[cfg(feature = "gpgme")]
+pub extern crate gpgme;
 #[macro_use]
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
 extern crate mktemp;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_yaml;
+extern crate sheesy_tools as tools;
 extern crate yaml_rust;
+extern crate zeroize;
 
+pub mod backend;
 mod base;
 pub mod error;
+mod fsck;
+mod git;
+mod grep;
+mod groups;
+mod inheritance;
 mod init;
+mod listing;
 mod partitions;
 mod recipients;
 mod resource;
+mod rules;
+mod signature;
 mod spec;
+mod transaction;
 mod util;
 
-pub use base::{TrustModel, Vault, VaultExt};
+#[cfg(feature = "gpgme")]
+pub use backend::GpgBackend;
+#[cfg(feature = "sequoia")]
+pub use backend::SequoiaBackend;
+pub use backend::{AgeBackend, Backend, Key, SharedBackend, Validity};
+pub use base::{Encryption, SignaturePolicy, TrustModel, Vault, VaultExt};
+pub use listing::PartitionInfo;
+pub use recipients::{recipients_check_result, KeyProblem, PartitionCheck, RecipientCheck};
+pub use rules::{AccessRule, ResourceKeys};
 pub use spec::*;
-pub use util::print_causes;
+pub use transaction::{ChangedPaths, Transaction};
+pub use util::{print_causes, shell_quote};
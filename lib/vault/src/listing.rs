@@ -0,0 +1,96 @@
+use crate::backend::Key;
+use crate::base::{Vault, GPG_GLOB};
+use crate::util::{strip_ext, ResetCWD};
+use failure::Error;
+use glob::glob;
+use std::io::Write;
+use std::iter::once;
+use std::path::PathBuf;
+
+/// A description of a partition, suitable for serialization.
+///
+/// Resources and recipients are only present if they were requested.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    pub name: Option<String>,
+    pub index: usize,
+    pub secrets: PathBuf,
+    pub recipients_file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<PathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<Key>>,
+}
+
+impl Vault {
+    /// The paths of all resources of this partition, relative to its secrets directory and without their '.gpg' suffix.
+    pub fn resources(&self) -> Result<Vec<PathBuf>, Error> {
+        let dir = self.secrets_path();
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let _change_cwd = ResetCWD::from_path(&dir)?;
+        Ok(glob(GPG_GLOB)
+            .expect("valid pattern")
+            .filter_map(Result::ok)
+            .map(|p| strip_ext(&p))
+            .collect())
+    }
+
+    pub fn info(&self) -> PartitionInfo {
+        PartitionInfo {
+            name: self.name.clone(),
+            index: self.index,
+            secrets: self.secrets_path(),
+            recipients_file: self.recipients_path(),
+            resources: None,
+            recipients: None,
+        }
+    }
+
+    pub fn list_partitions(&self) -> Vec<PartitionInfo> {
+        once(self).chain(self.partitions.iter()).map(Vault::info).collect()
+    }
+
+    pub fn list_resources(&self) -> Result<Vec<PartitionInfo>, Error> {
+        once(self)
+            .chain(self.partitions.iter())
+            .map(|partition| {
+                Ok(PartitionInfo {
+                    resources: Some(partition.resources()?),
+                    ..partition.info()
+                })
+            })
+            .collect()
+    }
+
+    pub fn list_recipients(&self, error: &mut dyn Write) -> Result<Vec<PartitionInfo>, Error> {
+        once(self)
+            .chain(self.partitions.iter())
+            .map(|partition| {
+                let keys = partition.recipient_keys(
+                    self.backend(),
+                    self.gpg_keys_dir_for_auto_import(partition).as_deref(),
+                    error,
+                )?;
+                Ok(PartitionInfo {
+                    recipients: Some(keys),
+                    ..partition.info()
+                })
+            })
+            .collect()
+    }
+
+    pub fn print_partitions(&self, output: &mut dyn Write) -> Result<(), Error> {
+        for partition in once(self).chain(self.partitions.iter()) {
+            writeln!(
+                output,
+                "{} {} (recipients at '{}')",
+                partition.index,
+                partition.url(),
+                partition.recipients_path().display()
+            )?;
+        }
+        Ok(())
+    }
+}
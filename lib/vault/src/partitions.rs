@@ -1,7 +1,7 @@
-use crate::base::{Vault, VaultKind};
+use crate::base::{TrustModel, Vault, VaultKind};
 use crate::init::assure_empty_directory_exists;
 use crate::spec::WriteMode;
-use crate::util::{export_key_with_progress, extract_at_least_one_secret_key, fingerprint_of, new_context};
+use crate::util::{export_key_with_progress, extract_at_least_one_secret_key};
 use failure::{err_msg, Error, ResultExt};
 use std::io::Write;
 use std::iter::once;
@@ -73,6 +73,54 @@ impl Vault {
         Ok(())
     }
 
+    /// Change the settings of the partition matching `selector`, leaving those which are `None` untouched.
+    pub fn update_partition(
+        &mut self,
+        selector: &str,
+        name: Option<&str>,
+        trust_model: Option<TrustModel>,
+        auto_import: Option<bool>,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let index = Vault::partition_index(selector, once(&*self).chain(self.partitions.iter()), None)?;
+        if let Some(name) = name {
+            if let Some(other) = self
+                .all_in_order()
+                .into_iter()
+                .find(|v| v.index != index && v.name.as_deref() == Some(name))
+            {
+                bail!(
+                    "The name '{}' is already used by the partition at '{}'",
+                    name,
+                    other.secrets.display()
+                );
+            }
+        }
+        {
+            let partition = if self.index == index {
+                &mut *self
+            } else {
+                self.partitions
+                    .iter_mut()
+                    .find(|v| v.index == index)
+                    .expect("index of existing partition")
+            };
+            if let Some(name) = name {
+                partition.name = Some(name.to_owned());
+            }
+            if trust_model.is_some() {
+                partition.trust_model = trust_model;
+            }
+            if auto_import.is_some() {
+                partition.auto_import = auto_import;
+            }
+        }
+        self.serialize()?;
+
+        writeln!(output, "Updated partition matching selector '{}'", selector).ok();
+        Ok(())
+    }
+
     pub fn add_partition(
         &mut self,
         path: &Path,
@@ -113,10 +161,22 @@ impl Vault {
             partitions: Vec::new(),
             resolved_at: self.resolved_at.clone(),
             vault_path: self.vault_path.clone(),
+            backend: self.backend.clone(),
+            jobs: self.jobs,
+            changed_paths: self.changed_paths.clone(),
+            verified_recipients: self.verified_recipients.clone(),
             secrets: partition_secrets_dir.clone(),
             gpg_keys: None,
             recipients: recipients_file,
             trust_model: None,
+            encryption: None,
+            auto_commit: None,
+            signatures: None,
+            groups: None,
+            shared_groups: self.shared_groups.clone(),
+            rules: None,
+            inherit_recipients: None,
+            inherited_recipients: None,
             auto_import: None,
         };
 
@@ -125,16 +185,15 @@ impl Vault {
         self.serialize()?;
 
         {
-            let mut gpg_ctx = new_context()?;
-            let keys = extract_at_least_one_secret_key(&mut gpg_ctx, gpg_key_ids)?;
-            let mut fprs: Vec<_> = keys.iter().map(|k| fingerprint_of(k)).collect::<Result<_, _>>()?;
+            let keys = extract_at_least_one_secret_key(self.backend(), gpg_key_ids)?;
+            let mut fprs: Vec<_> = keys.iter().map(|k| k.fingerprint.clone()).collect();
             assure_empty_directory_exists(&partition_secrets_dir).context("Cannot create secrets directory")?;
             partition.write_recipients_list(&mut fprs)?;
 
             if let Ok(gpg_keys_dir) = self.find_gpg_keys_dir() {
-                let mut buf = Vec::new();
                 for key in &keys {
-                    export_key_with_progress(&mut gpg_ctx, &gpg_keys_dir, key, &mut buf, output)?;
+                    let (_, key_path) = export_key_with_progress(self.backend(), &gpg_keys_dir, key, output)?;
+                    self.changed_paths.record(&key_path);
                 }
             }
         }
@@ -1,11 +1,11 @@
+use crate::backend::Key;
 use crate::base::Vault;
 use crate::spec::SigningMode;
-use crate::util::{export_key, fingerprint_of, new_context, KeyDisplay, KeylistDisplay, UserIdFingerprint};
+use crate::util::{export_key, KeyDisplay, KeylistDisplay, UserIdFingerprint};
 use crate::TrustModel;
-use failure::{err_msg, Error, ResultExt};
+use failure::{Error, ResultExt};
 use std::io::Write;
 use std::iter::once;
-use std::path::PathBuf;
 
 impl Vault {
     pub fn add_recipients(
@@ -16,90 +16,89 @@ impl Vault {
         partitions: &[String],
         output: &mut dyn Write,
     ) -> Result<(), Error> {
-        let mut gpg_ctx = new_context()?;
         let partitions: Vec<&Vault> = self.partitions_by_name_or_path(partitions)?;
-        let has_multiple_partitions = !self.partitions.is_empty();
+        let inheriting_signing_keys = Vault::recipients_signing_keys(&self.inheriting_partitions(&partitions))?;
+        let mut transaction = self.transaction()?;
 
-        for partition in partitions {
-            if let SigningMode::Public = sign {
-                let gpg_keys_dir = self.find_gpg_keys_dir().with_context(|_| {
-                    "Adding unverified recipients requires you to use a vault that has the `gpg-keys` directory configured"
-                })?;
-                let imported_gpg_keys_ids = partition.import_keys(&mut gpg_ctx, &gpg_keys_dir, gpg_key_ids, output)?;
-                let signing_key = partition
-                    .find_signing_key(&mut gpg_ctx, signing_key_id)
-                    .with_context(|_| {
-                        "Did not manage to find suitable signing key \
-                         for re-exporting the recipient keys."
-                    })?;
-                gpg_ctx.add_signer(&signing_key)?;
-                for key_fpr_to_sign in imported_gpg_keys_ids {
-                    let key_to_sign = gpg_ctx.get_key(&key_fpr_to_sign)?;
-                    gpg_ctx.sign_key(&key_to_sign, None::<&[u8]>, None).with_context(|_| {
-                        format_err!(
-                            "Could not sign key of recipient {} with signing key {}",
-                            key_fpr_to_sign,
-                            UserIdFingerprint(&signing_key)
-                        )
-                    })?;
-                    writeln!(
-                        output,
-                        "Signed recipients key {} with signing key {}",
-                        UserIdFingerprint(&key_to_sign),
-                        UserIdFingerprint(&signing_key)
-                    )
-                    .ok();
-                }
+        for partition in &partitions {
+            let keys = self.keys_of_new_recipients(partition, gpg_key_ids, sign, signing_key_id, output)?;
+            let mut recipients = partition.recipients_entries()?;
+            for key in keys {
+                recipients.push(key.fingerprint.clone());
+                writeln!(output, "Added recipient {}", KeyDisplay(&key)).ok();
             }
-            let keys = {
-                let mut keys_iter = gpg_ctx.find_keys(gpg_key_ids)?;
-                let keys: Vec<_> = keys_iter.by_ref().collect::<Result<_, _>>()?;
-
-                if keys_iter.finish()?.is_truncated() {
-                    return Err(err_msg("The key list was truncated unexpectedly, while iterating it"));
-                }
-                keys
-            };
-            if keys.len() != gpg_key_ids.len() {
-                return Err(format_err!(
-                    "Found {} viable keys for key-ids ({}), for {} given user ids.",
-                    keys.len(),
-                    KeylistDisplay(&keys),
-                    gpg_key_ids.len()
-                ));
-            };
+            partition.stage_recipients_list(&mut recipients, &mut transaction)?;
+        }
+        self.stage_recipients_signatures_with(&inheriting_signing_keys, &mut transaction)?;
+        for partition in self.with_inheriting_partitions(&partitions) {
+            self.reencrypt_for_staged_recipients(partition, &mut transaction, output)?;
+        }
+        transaction.commit()
+    }
 
-            if let Ok(gpg_keys_dir) = self.find_gpg_keys_dir() {
-                let mut buf = Vec::new();
-                for key in &keys {
-                    let (_fingerprint, file_path) = export_key(&mut gpg_ctx, &gpg_keys_dir, key, &mut buf)?;
-                    writeln!(
-                        output,
-                        "Exported public key for user {} to '{}'",
-                        KeyDisplay(key),
-                        file_path.display()
+    /// Return the keys of the new recipients identified by `gpg_key_ids`. If `sign` is `Public`, they are imported
+    /// from the gpg keys directory first, and signed with our key on the recipients list of `partition`.
+    /// They are exported to the gpg keys directory if there is one.
+    pub fn keys_of_new_recipients(
+        &self,
+        partition: &Vault,
+        gpg_key_ids: &[String],
+        sign: SigningMode,
+        signing_key_id: Option<&str>,
+        output: &mut dyn Write,
+    ) -> Result<Vec<Key>, Error> {
+        let backend = self.backend();
+        if let SigningMode::Public = sign {
+            let gpg_keys_dir = self.find_gpg_keys_dir().with_context(|_| {
+                "Adding unverified recipients requires you to use a vault that has the `gpg-keys` directory configured"
+            })?;
+            let imported_gpg_keys_ids = partition.import_keys(backend, &gpg_keys_dir, gpg_key_ids, output)?;
+            let signing_key = partition.find_signing_key(backend, signing_key_id).with_context(|_| {
+                "Did not manage to find suitable signing key \
+                     for re-exporting the recipient keys."
+            })?;
+            for key_fpr_to_sign in imported_gpg_keys_ids {
+                let key_to_sign = backend.key(&key_fpr_to_sign)?;
+                backend.sign_key(&key_to_sign, &signing_key).with_context(|_| {
+                    format_err!(
+                        "Could not sign key of recipient {} with signing key {}",
+                        key_fpr_to_sign,
+                        UserIdFingerprint(&signing_key)
                     )
-                    .ok();
-                }
+                })?;
+                writeln!(
+                    output,
+                    "Signed recipients key {} with signing key {}",
+                    UserIdFingerprint(&key_to_sign),
+                    UserIdFingerprint(&signing_key)
+                )
+                .ok();
             }
+        }
+        let keys = backend.find_keys(gpg_key_ids)?;
+        if keys.len() != gpg_key_ids.len() {
+            return Err(format_err!(
+                "Found {} viable keys for key-ids ({}), for {} given user ids.",
+                keys.len(),
+                KeylistDisplay(&keys),
+                gpg_key_ids.len()
+            ));
+        };
 
-            let mut recipients = partition.recipients_list()?;
-            for key in keys {
-                recipients.push(fingerprint_of(&key)?);
-                writeln!(output, "Added recipient {}", KeyDisplay(&key)).ok();
+        if let Ok(gpg_keys_dir) = self.find_gpg_keys_dir() {
+            for key in &keys {
+                let (_fingerprint, file_path) = export_key(backend, &gpg_keys_dir, key)?;
+                self.changed_paths.record(&file_path);
+                writeln!(
+                    output,
+                    "Exported public key for user {} to '{}'",
+                    KeyDisplay(key),
+                    file_path.display()
+                )
+                .ok();
             }
-            partition.write_recipients_list(&mut recipients)?;
-            partition.reencrypt(
-                &mut gpg_ctx,
-                &self.find_trust_model(partition),
-                self.gpg_keys_dir_for_auto_import(partition)
-                    .as_ref()
-                    .map(PathBuf::as_ref),
-                has_multiple_partitions,
-                output,
-            )?;
         }
-        Ok(())
+        Ok(keys)
     }
 
     pub fn find_trust_model(&self, partition: &Vault) -> TrustModel {
@@ -0,0 +1,210 @@
+use crate::backend::{Key, Validity};
+use crate::base::{Encryption, TrustModel, Vault};
+use crate::util::FingerprintUserId;
+use failure::Error;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Something about the key of a recipient which prevents encrypting for them, now or soon.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyProblem {
+    Expired,
+    ExpiresSoon { days: u64 },
+    Revoked,
+    Disabled,
+    CannotEncrypt,
+    Untrusted { trust: Validity },
+    Unavailable { reason: String },
+}
+
+impl fmt::Display for KeyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyProblem::Expired => write!(f, "the key has expired"),
+            KeyProblem::ExpiresSoon { days } => write!(f, "the key expires in {} day(s)", days),
+            KeyProblem::Revoked => write!(f, "the key was revoked"),
+            KeyProblem::Disabled => write!(f, "the key was disabled"),
+            KeyProblem::CannotEncrypt => write!(f, "the key has no subkey usable for encryption"),
+            KeyProblem::Untrusted { trust } => write!(
+                f,
+                "the key is not trusted enough to encrypt for it using the web-of-trust, as its validity is {}",
+                format!("{:?}", trust).to_lowercase()
+            ),
+            KeyProblem::Unavailable { reason } => write!(f, "the key could not be obtained: {}", reason),
+        }
+    }
+}
+
+/// The key of a recipient along with all problems found with it, suitable for serialization.
+/// If the key could not be obtained, it only carries the id of the recipient as fingerprint.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecipientCheck {
+    pub key: Key,
+    pub problems: Vec<KeyProblem>,
+}
+
+/// The checked recipients of a partition, suitable for serialization.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartitionCheck {
+    pub name: Option<String>,
+    pub index: usize,
+    pub recipients_file: PathBuf,
+    pub recipients: Vec<RecipientCheck>,
+}
+
+/// Return all problems of `key` at `now`, considering it expired if it does so within `days`.
+/// Its validity only matters if `requires_trust` is set.
+fn problems_of(key: &Key, now: u64, days: u64, requires_trust: bool) -> Vec<KeyProblem> {
+    let mut problems = Vec::new();
+    if key.revoked {
+        problems.push(KeyProblem::Revoked);
+    }
+    if key.disabled {
+        problems.push(KeyProblem::Disabled);
+    }
+    let is_expired = key.expires.is_some_and(|expires| expires <= now);
+    match key.expires {
+        Some(_) if is_expired => problems.push(KeyProblem::Expired),
+        Some(expires) if expires <= now + days * SECONDS_PER_DAY => problems.push(KeyProblem::ExpiresSoon {
+            days: (expires - now) / SECONDS_PER_DAY,
+        }),
+        _ => {}
+    }
+    // Expired and revoked keys cannot be used for encryption anyway, which is not worth mentioning again.
+    if !key.can_encrypt && !key.revoked && !is_expired {
+        problems.push(KeyProblem::CannotEncrypt);
+    }
+    if requires_trust && key.trust < Validity::Full {
+        problems.push(KeyProblem::Untrusted { trust: key.trust });
+    }
+    problems
+}
+
+/// Fail if any of the given `checks` found a problem.
+pub fn recipients_check_result(checks: &[PartitionCheck]) -> Result<(), Error> {
+    let recipients = checks.iter().flat_map(|check| &check.recipients);
+    let num_problems: usize = recipients.clone().map(|recipient| recipient.problems.len()).sum();
+    if num_problems == 0 {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "Found {} problem(s) while checking the keys of {} recipient(s).",
+            num_problems,
+            recipients.count()
+        ))
+    }
+}
+
+impl Vault {
+    /// Check the keys of the recipients of all partitions, including those of their access rules, for anything
+    /// which prevents encrypting for them, or will do so within the next `days`.
+    /// Keys which cannot be found, even after importing them if auto-import is enabled, are reported as problem
+    /// as well.
+    pub fn check_recipients(&self, days: u64, error: &mut dyn Write) -> Result<Vec<PartitionCheck>, Error> {
+        let backend = self.backend();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let uses_trust_model = match self.encryption.unwrap_or_default() {
+            Encryption::Gpg => true,
+            Encryption::Age => false,
+        };
+        self.all_in_order()
+            .into_iter()
+            .map(|partition| {
+                let requires_trust = uses_trust_model
+                    && match self.find_trust_model(partition) {
+                        TrustModel::GpgWebOfTrust => true,
+                        TrustModel::Always => false,
+                    };
+                let mut ids = partition.recipients_list()?;
+                if ids.is_empty() {
+                    bail!(
+                        "No recipients found in recipients file at '{}'.",
+                        partition.recipients.display()
+                    );
+                }
+                partition.verify_recipients_list(backend, error)?;
+                for id in partition.rule_recipients()? {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+                let gpg_keys_dir = self.gpg_keys_dir_for_auto_import(partition);
+                let mut recipients: Vec<RecipientCheck> = Vec::new();
+                for id in ids {
+                    let key = backend.key(&id).or_else(|err| match gpg_keys_dir {
+                        Some(ref dir) if partition.import_keys(backend, dir, slice::from_ref(&id), error).is_ok() => {
+                            backend.key(&id)
+                        }
+                        _ => Err(err),
+                    });
+                    let check = match key {
+                        Ok(key) => RecipientCheck {
+                            problems: problems_of(&key, now, days, requires_trust),
+                            key,
+                        },
+                        Err(err) => RecipientCheck {
+                            key: Key {
+                                fingerprint: id,
+                                ..Default::default()
+                            },
+                            problems: vec![KeyProblem::Unavailable {
+                                reason: err.to_string(),
+                            }],
+                        },
+                    };
+                    if !recipients.iter().any(|r| r.key.fingerprint == check.key.fingerprint) {
+                        recipients.push(check);
+                    }
+                }
+                Ok(PartitionCheck {
+                    name: partition.name.clone(),
+                    index: partition.index,
+                    recipients_file: partition.recipients_path(),
+                    recipients,
+                })
+            })
+            .collect()
+    }
+
+    /// Write all problems found by `check_recipients()` to `output`, and fail if there was one.
+    pub fn print_recipients_check(
+        &self,
+        days: u64,
+        output: &mut dyn Write,
+        error: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let checks = self.check_recipients(days, error)?;
+        let has_multiple_partitions = !self.partitions.is_empty();
+        let mut num_recipients = 0;
+        for check in &checks {
+            for recipient in &check.recipients {
+                num_recipients += 1;
+                let recipient_display = if recipient.key.user_ids.is_empty() {
+                    recipient.key.fingerprint.clone()
+                } else {
+                    FingerprintUserId(&recipient.key).to_string()
+                };
+                for problem in &recipient.problems {
+                    if has_multiple_partitions {
+                        write!(output, "{}: ", check.recipients_file.display()).ok();
+                    }
+                    writeln!(output, "{}: {}", recipient_display, problem).ok();
+                }
+            }
+        }
+        recipients_check_result(&checks)?;
+        writeln!(
+            output,
+            "All {} recipient(s) can be encrypted for, and none of their keys expires within the next {} day(s).",
+            num_recipients, days
+        )
+        .ok();
+        Ok(())
+    }
+}
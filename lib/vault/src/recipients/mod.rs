@@ -1,4 +1,8 @@
 mod add;
+mod check;
 mod other;
 mod remove;
 mod util;
+
+pub use self::check::{recipients_check_result, KeyProblem, PartitionCheck, RecipientCheck};
+pub use self::util::find_signing_key_among;
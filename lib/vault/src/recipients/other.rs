@@ -1,9 +1,11 @@
 use crate::base::Vault;
+use crate::transaction::Transaction;
 use crate::util::export_key;
 use crate::util::extract_at_least_one_secret_key;
-use crate::util::new_context;
 use crate::util::{FingerprintUserId, UserIdFingerprint};
 use failure::Error;
+use std::collections::BTreeSet;
+use std::fs;
 use std::io::Write;
 use std::iter::once;
 use std::path::PathBuf;
@@ -11,33 +13,31 @@ use std::path::PathBuf;
 impl Vault {
     pub fn init_recipients(&self, gpg_key_ids: &[String], output: &mut dyn Write) -> Result<(), Error> {
         let gpg_keys_dir = self.find_gpg_keys_dir()?;
-        let mut gpg_ctx = new_context()?;
-        let keys = extract_at_least_one_secret_key(&mut gpg_ctx, gpg_key_ids)?;
+        let keys = extract_at_least_one_secret_key(self.backend(), gpg_key_ids)?;
 
-        let mut buf = Vec::new();
         for key in keys {
-            export_key(&mut gpg_ctx, &gpg_keys_dir, &key, &mut buf)?;
+            let (_, key_path) = export_key(self.backend(), &gpg_keys_dir, &key)?;
+            self.changed_paths.record(&key_path);
             writeln!(output, "Exported public key for {}.", UserIdFingerprint(&key)).ok();
         }
         Ok(())
     }
 
     pub fn print_recipients(&self, output: &mut dyn Write, error: &mut dyn Write) -> Result<(), Error> {
-        let mut ctx = new_context()?;
         if self.partitions.is_empty() {
             let keys_dir_for_auto_import = if self.auto_import.unwrap_or(false) {
                 self.gpg_keys_dir().ok()
             } else {
                 None
             };
-            for key in self.recipient_keys(&mut ctx, keys_dir_for_auto_import.as_ref().map(PathBuf::as_path), error)? {
+            for key in self.recipient_keys(self.backend(), keys_dir_for_auto_import.as_deref(), error)? {
                 writeln!(output, "{}", FingerprintUserId(&key)).ok();
             }
         } else {
             for partition in once(self).chain(self.partitions.iter()) {
                 writeln!(output, "{}", partition.url())?;
                 for key in partition.recipient_keys(
-                    &mut ctx,
+                    self.backend(),
                     self.gpg_keys_dir_for_auto_import(partition)
                         .as_ref()
                         .map(PathBuf::as_path),
@@ -49,4 +49,166 @@ impl Vault {
         }
         Ok(())
     }
+
+    pub fn reencrypt_partitions(&self, partitions: &[String], output: &mut dyn Write) -> Result<(), Error> {
+        let partitions = self.partitions_by_name_or_path(partitions)?;
+        self.reencrypt_all_of(&partitions, output)
+    }
+
+    /// Re-encrypt all resources of all given `partitions` for their current recipients.
+    pub fn reencrypt_all_of(&self, partitions: &[&Vault], output: &mut dyn Write) -> Result<(), Error> {
+        let mut transaction = self.transaction()?;
+        let num_reencrypted = self.stage_reencryption_of(partitions, &mut transaction, output)?;
+        transaction.commit()?;
+        writeln!(
+            output,
+            "Re-encrypted {} resource(s) in {} partition(s)",
+            num_reencrypted,
+            partitions.len()
+        )
+        .ok();
+        Ok(())
+    }
+
+    /// Stage all resources of all given `partitions` re-encrypted for their current recipients in `transaction`,
+    /// and return how many there are.
+    pub fn stage_reencryption_of(
+        &self,
+        partitions: &[&Vault],
+        transaction: &mut Transaction,
+        output: &mut dyn Write,
+    ) -> Result<usize, Error> {
+        let has_multiple_partitions = !self.partitions.is_empty();
+        let mut num_reencrypted = 0;
+        for partition in partitions {
+            let keys = partition.resource_keys(
+                self.backend(),
+                self.gpg_keys_dir_for_auto_import(partition).as_deref(),
+                output,
+            )?;
+            num_reencrypted += partition.reencrypt(
+                self.backend(),
+                &keys,
+                &self.find_trust_model(partition),
+                has_multiple_partitions,
+                transaction,
+                output,
+            )?;
+        }
+        Ok(num_reencrypted)
+    }
+
+    /// Export the current public key of every recipient of every partition from the keyring to the gpg keys
+    /// directory, so that others import it instead of an outdated copy. Each key file which changed is written
+    /// to `output`, and recipients whose key is not in the keyring are skipped with a note written to `error`.
+    /// If `reencrypt` is set, all resources of all partitions are re-encrypted as well.
+    /// The key files and the re-encrypted resources are written all at once, or not at all.
+    pub fn refresh_recipients(
+        &self,
+        reencrypt: bool,
+        output: &mut dyn Write,
+        error: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let gpg_keys_dir = self.find_gpg_keys_dir()?;
+        let partitions = self.all_in_order();
+        let mut fingerprints = BTreeSet::new();
+        for partition in &partitions {
+            fingerprints.extend(partition.recipients_list()?);
+            fingerprints.extend(partition.rule_recipients()?);
+        }
+
+        let mut transaction = self.transaction()?;
+        let (mut num_refreshed, mut num_changed) = (0, 0);
+        for fingerprint in &fingerprints {
+            let key = match self.backend().key(fingerprint) {
+                Ok(key) => key,
+                Err(_) => {
+                    writeln!(
+                        error,
+                        "Skipped recipient {} as their key is not in your keyring.",
+                        fingerprint
+                    )
+                    .ok();
+                    continue;
+                }
+            };
+            let key_path = gpg_keys_dir.join(&key.fingerprint);
+            let exported_key = self.backend().export_key(&key)?;
+            num_refreshed += 1;
+            if fs::read(&key_path).ok().as_ref() != Some(&exported_key) {
+                transaction.stage_write(&key_path, &exported_key)?;
+                num_changed += 1;
+                writeln!(
+                    output,
+                    "Refreshed public key for {} at '{}'",
+                    UserIdFingerprint(&key),
+                    key_path.display()
+                )
+                .ok();
+            }
+        }
+        writeln!(
+            output,
+            "{} of {} exported public key(s) changed",
+            num_changed, num_refreshed
+        )
+        .ok();
+        let num_reencrypted = if reencrypt {
+            Some(self.stage_reencryption_of(&partitions, &mut transaction, output)?)
+        } else {
+            None
+        };
+        transaction.commit()?;
+        if let Some(num_reencrypted) = num_reencrypted {
+            writeln!(
+                output,
+                "Re-encrypted {} resource(s) in {} partition(s)",
+                num_reencrypted,
+                partitions.len()
+            )
+            .ok();
+        }
+        Ok(())
+    }
+
+    /// Finish the change of recipients or the re-encryption which was interrupted, or discard it if `abort` is set.
+    pub fn resume(&self, abort: bool, output: &mut dyn Write) -> Result<(), Error> {
+        let journal_path = match self.journal_path() {
+            Some(ref journal_path) if journal_path.is_file() => journal_path.to_owned(),
+            _ => {
+                writeln!(output, "There is no interrupted change to resume.").ok();
+                return Ok(());
+            }
+        };
+        let mut transaction = Transaction::resume(&journal_path)?.record_changes_in(self.changed_paths.clone());
+        if abort {
+            transaction.abort()?;
+            writeln!(
+                output,
+                "Discarded the interrupted change recorded at '{}'",
+                journal_path.display()
+            )
+            .ok();
+            return Ok(());
+        }
+
+        if !transaction.is_committing() {
+            for index in transaction.reencrypted_partitions().to_owned() {
+                let partition = self
+                    .all_in_order()
+                    .into_iter()
+                    .find(|p| p.index == index)
+                    .ok_or_else(|| format_err!("The partition with index {} does not exist anymore.", index))?;
+                self.reencrypt_for_staged_recipients(partition, &mut transaction, output)?;
+            }
+        }
+        transaction.commit()?;
+        writeln!(
+            output,
+            "Finished the interrupted change recorded at '{}'",
+            journal_path.display()
+        )
+        .ok();
+        Ok(())
+    }
 }
@@ -1,9 +1,9 @@
+use crate::transaction::Transaction;
 use crate::util::fingerprints_of_keys;
-use crate::util::{new_context, UserIdFingerprint};
+use crate::util::UserIdFingerprint;
 use crate::Vault;
-use failure::{Error, ResultExt};
+use failure::Error;
 use itertools::Itertools;
-use std::fs::remove_file;
 use std::io::Write;
 use std::iter::once;
 use std::path::PathBuf;
@@ -15,26 +15,26 @@ impl Vault {
         partitions: &[String],
         output: &mut dyn Write,
     ) -> Result<(), Error> {
-        let mut ctx = new_context()?;
+        let backend = self.backend();
         let partitions = self.partitions_by_name_or_path(partitions)?;
-        let has_multiple_partitions = !self.partitions.is_empty();
         let gpg_keys_dir_independent_of_auto_import = self.find_gpg_keys_dir().ok();
+        let inheriting_signing_keys = Vault::recipients_signing_keys(&self.inheriting_partitions(&partitions))?;
+        let mut transaction = self.transaction()?;
 
-        for partition in partitions {
+        for partition in &partitions {
             let gpg_keys_dir = self.gpg_keys_dir_for_auto_import(partition);
             let keys_for_ids = partition.keys_by_ids(
-                &mut ctx,
+                backend,
                 gpg_key_ids,
                 "user-id",
                 gpg_keys_dir.as_ref().map(PathBuf::as_path),
                 output,
             )?;
-            let recipients_keys =
-                partition.recipient_keys(&mut ctx, gpg_keys_dir.as_ref().map(PathBuf::as_path), output)?;
+            let recipients_keys = partition.recipient_keys(backend, gpg_keys_dir.as_deref(), output)?;
 
             let (keys_and_fprs_to_remove, mut remaining_recipients_fprs) = {
-                let keys_and_fprs = fingerprints_of_keys(&keys_for_ids)?;
-                let recipient_keys_and_fprs = fingerprints_of_keys(&recipients_keys)?;
+                let keys_and_fprs = fingerprints_of_keys(&keys_for_ids);
+                let recipient_keys_and_fprs = fingerprints_of_keys(&recipients_keys);
 
                 let (keys_and_fprs_to_remove, missing) =
                     keys_and_fprs
@@ -62,7 +62,31 @@ impl Vault {
                 (keys_and_fprs_to_remove, recipient_keys_and_fprs)
             };
 
+            let group_members = partition.group_members()?;
+            let inherited_recipients = partition.inherited_recipients_list()?;
+            let mut removed_fprs = Vec::new();
             for (key, fpr) in keys_and_fprs_to_remove {
+                if group_members.contains(&fpr) {
+                    bail!(
+                        "Cannot remove user {} from '{}' as they are a member of a group it refers to. \
+                         Remove them from the group instead.",
+                        UserIdFingerprint(key),
+                        partition.recipients_path().display()
+                    )
+                }
+                if inherited_recipients.contains(&fpr) {
+                    bail!(
+                        "Cannot remove user {} from '{}' as it inherits them from the recipients file at '{}'. \
+                         Remove them from there instead.",
+                        UserIdFingerprint(key),
+                        partition.recipients_path().display(),
+                        partition
+                            .inherited_recipients
+                            .as_ref()
+                            .expect("inherited recipients")
+                            .display()
+                    )
+                }
                 remaining_recipients_fprs.retain(|rfpr| rfpr != &fpr);
                 if remaining_recipients_fprs.is_empty() {
                     bail!(
@@ -72,14 +96,35 @@ impl Vault {
                     )
                 }
 
+                removed_fprs.push(fpr);
+            }
+
+            let mut remaining_entries: Vec<String> = partition
+                .group_references()?
+                .into_iter()
+                .chain(
+                    remaining_recipients_fprs
+                        .iter()
+                        .filter(|fpr| !group_members.contains(fpr) && !inherited_recipients.contains(fpr))
+                        .cloned(),
+                )
+                .collect();
+            let written_file = partition.stage_recipients_list(&mut remaining_entries, &mut transaction)?;
+            writeln!(
+                output,
+                "Wrote changed recipients to file at '{}'",
+                written_file.display()
+            )
+            .ok();
+
+            for fpr in removed_fprs {
                 if let Some(gpg_keys_dir) = gpg_keys_dir_independent_of_auto_import.as_ref() {
-                    if self.recipient_used_in_other_partitions(&fpr, partition.index)? {
+                    if self.recipient_used_in_other_partitions(&fpr, partition.index, &transaction)? {
                         continue;
                     }
                     let fingerprint_path = gpg_keys_dir.join(fpr);
                     if fingerprint_path.is_file() {
-                        remove_file(&fingerprint_path)
-                            .context(format!("Failed to remove key file at '{}'", fingerprint_path.display(),))?;
+                        transaction.stage_removal(&fingerprint_path)?;
                         writeln!(output, "Removed key file at '{}'", fingerprint_path.display())
                     } else {
                         writeln!(
@@ -91,36 +136,27 @@ impl Vault {
                     .ok();
                 }
             }
-
-            let written_file = partition.write_recipients_list(&mut remaining_recipients_fprs)?;
-            writeln!(
-                output,
-                "Wrote changed recipients to file at '{}'",
-                written_file.display()
-            )
-            .ok();
-
-            partition.reencrypt(
-                &mut ctx,
-                &self.find_trust_model(partition),
-                gpg_keys_dir.as_ref().map(PathBuf::as_path),
-                has_multiple_partitions,
-                output,
-            )?;
         }
-        Ok(())
+        self.stage_recipients_signatures_with(&inheriting_signing_keys, &mut transaction)?;
+        for partition in self.with_inheriting_partitions(&partitions) {
+            self.reencrypt_for_staged_recipients(partition, &mut transaction, output)?;
+        }
+        transaction.commit()
     }
 
-    fn recipient_used_in_other_partitions(&self, fpr: &str, index_to_skip: usize) -> Result<bool, Error> {
+    /// Recipients are considered as they will be once `transaction` is committed.
+    fn recipient_used_in_other_partitions(
+        &self,
+        fpr: &str,
+        index_to_skip: usize,
+        transaction: &Transaction,
+    ) -> Result<bool, Error> {
         for partition in once(self)
             .chain(self.partitions.iter())
             .filter(|p| p.index != index_to_skip)
         {
-            if partition
-                .recipients_list()?
-                .iter()
-                .any(|rfpr| rfpr == fpr || fpr.starts_with(rfpr))
-            {
+            let recipients = partition.staged_recipients_list(transaction)?;
+            if recipients.iter().any(|rfpr| rfpr == fpr || fpr.starts_with(rfpr)) {
                 return Ok(true);
             }
         }
@@ -1,18 +1,18 @@
+use crate::backend::{Backend, Key};
 use crate::base::{Vault, GPG_GLOB};
-use crate::error::EncryptionError;
 use crate::print_causes;
-use crate::util::flags_for_model;
+use crate::rules::ResourceKeys;
+use crate::signature;
+use crate::transaction::Transaction;
+use crate::util::map_in_order;
 use crate::util::strip_ext;
-use crate::util::write_at;
 use crate::util::ResetCWD;
-use crate::util::{fingerprint_of, UserIdFingerprint};
+use crate::util::UserIdFingerprint;
 use crate::TrustModel;
-use failure::{err_msg, Error, Fail, ResultExt};
+use failure::{err_msg, Error, ResultExt};
 use glob::glob;
-use gpgme::{self, Key};
 use itertools::Itertools;
-use mktemp::Temp;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
@@ -38,10 +38,68 @@ fn valid_fingerprint(id: &str) -> Result<&str, Error> {
     }
 }
 
+/// Return our only secret key matching `signing_key_id`, if given, which is among the given `recipients_fprs`.
+pub fn find_signing_key_among(
+    backend: &dyn Backend,
+    signing_key_id: Option<&str>,
+    recipients_fprs: &[String],
+) -> Result<Key, Error> {
+    if recipients_fprs.is_empty() {
+        return Err(err_msg(
+            "The recipients list is empty, but you are expected to be on that list.",
+        ));
+    }
+    let key_is_in_recipients_list = |(k, fpr)| {
+        if recipients_fprs.iter().any(|rfpr| rfpr == &fpr) {
+            Some(k)
+        } else {
+            None
+        }
+    };
+    let signing_key_fpr = match signing_key_id {
+        Some(id) => Some(backend.key(id).map(|k| k.fingerprint).context(format!(
+            "The given signing key named '{}' could not be found in the keychain.",
+            id
+        ))?),
+        None => None,
+    };
+    let only_matching_signing_key = |(k, fpr)| match signing_key_fpr.as_ref() {
+        Some(sk_fpr) => {
+            if &fpr == sk_fpr {
+                Some((k, fpr))
+            } else {
+                None
+            }
+        }
+        None => Some((k, fpr)),
+    };
+    let mut signing_keys: Vec<_> = backend
+        .find_secret_keys(&[])?
+        .into_iter()
+        .map(|k| {
+            let fpr = k.fingerprint.clone();
+            (k, fpr)
+        })
+        .filter_map(only_matching_signing_key)
+        .filter_map(key_is_in_recipients_list)
+        .collect();
+    match signing_keys.len() {
+        0 => Err(err_msg("Didn't find a single secret key suitable to sign keys.")),
+        1 => Ok(signing_keys.pop().expect("one entry")),
+        _ => Err(format_err!(
+            "Multiple keys are suitable for signing, which is ambiguous.\n{}",
+            signing_keys
+                .iter()
+                .map(|sk| format!("{}", UserIdFingerprint(sk)))
+                .join("\n"),
+        )),
+    }
+}
+
 impl Vault {
     pub fn import_keys(
         &self,
-        gpg_ctx: &mut gpgme::Context,
+        backend: &dyn Backend,
         gpg_keys_dir: &Path,
         gpg_key_ids: &[String],
         output: &mut dyn Write,
@@ -52,29 +110,21 @@ impl Vault {
                 let fpr = valid_fingerprint(s)?;
                 self.read_fingerprint_file(fpr, gpg_keys_dir)
                     .and_then(|(fpr_path, kb)| {
-                        let res = gpg_ctx
-                            .import(kb)
-                            .map_err(|e| {
-                                e.context(format!(
-                                    "Could not import key to gpg key database from content of file at '{}'",
-                                    fpr_path.display()
-                                ))
-                                .into()
-                            })
-                            .map(|imports| {
-                                imports
-                                    .imports()
-                                    .filter_map(|i| i.fingerprint().map(ToOwned::to_owned).ok())
-                                    .collect::<Vec<_>>()
-                            });
+                        let res = backend.import_keys(&kb).map_err(|e| {
+                            e.context(format!(
+                                "Could not import key to gpg key database from content of file at '{}'",
+                                fpr_path.display()
+                            ))
+                            .into()
+                        });
                         writeln!(output, "Imported recipient key at path '{}'", fpr_path.display()).ok();
                         res
                     })
                     .or_else(|err| {
-                        gpg_ctx
-                            .get_key(fpr)
+                        backend
+                            .key(fpr)
                             .map(|_key| vec![fpr.to_owned()])
-                            .map_err(|_gpg_err| {
+                            .map_err(|_backend_err| {
                                 err.context(format!(
                                     "Could not find fingerprint '{}', tried local file as well as gpg keychain.",
                                     fpr
@@ -120,62 +170,11 @@ impl Vault {
         Ok(imported_gpg_keys_fprs)
     }
 
-    pub fn find_signing_key(&self, ctx: &mut gpgme::Context, signing_key_id: Option<&str>) -> Result<Key, Error> {
+    pub fn find_signing_key(&self, backend: &dyn Backend, signing_key_id: Option<&str>) -> Result<Key, Error> {
         let recipients_fprs = self
             .recipients_list()
             .context("A recipients list is needed assure the signing key is in the recipients list.")?;
-        if recipients_fprs.is_empty() {
-            return Err(err_msg(
-                "The recipients list is empty, but you are expected to be on that list.",
-            ));
-        }
-        let key_is_in_recipients_list = |(k, fpr)| {
-            if recipients_fprs.iter().any(|rfpr| rfpr == &fpr) {
-                Some(k)
-            } else {
-                None
-            }
-        };
-        let signing_key_fpr = match signing_key_id {
-            Some(id) => Some(
-                ctx.get_key(id)
-                    .map_err(Into::into)
-                    .and_then(|k| fingerprint_of(&k))
-                    .context(format!(
-                        "The given signing key named '{}' could not be found in the keychain.",
-                        id
-                    ))?,
-            ),
-            None => None,
-        };
-        let only_matching_signing_key = |(k, fpr)| match signing_key_fpr.as_ref() {
-            Some(sk_fpr) => {
-                if &fpr == sk_fpr {
-                    Some((k, fpr))
-                } else {
-                    None
-                }
-            }
-            None => Some((k, fpr)),
-        };
-        let mut signing_keys: Vec<_> = ctx
-            .find_secret_keys(None::<String>)?
-            .filter_map(Result::ok)
-            .filter_map(|k| fingerprint_of(&k).map(|fpr| (k, fpr)).ok())
-            .filter_map(only_matching_signing_key)
-            .filter_map(key_is_in_recipients_list)
-            .collect();
-        match signing_keys.len() {
-            0 => Err(err_msg("Didn't find a single secret key suitable to sign keys.")),
-            1 => Ok(signing_keys.pop().expect("one entry")),
-            _ => Err(format_err!(
-                "Multiple keys are suitable for signing, which is ambiguous.\n{}",
-                signing_keys
-                    .iter()
-                    .map(|sk| format!("{}", UserIdFingerprint(sk)))
-                    .join("\n"),
-            )),
-        }
+        find_signing_key_among(backend, signing_key_id, &recipients_fprs)
     }
 
     pub fn read_fingerprint_file(&self, fpr: &str, gpg_keys_dir: &Path) -> Result<(PathBuf, Vec<u8>), Error> {
@@ -213,18 +212,44 @@ impl Vault {
         Ok((fpr_path, buf))
     }
 
+    /// Return the re-encrypted content of the resource at `encrypted_file_path`, along with a warning about
+    /// its signature, if there is one.
+    fn reencrypt_resource(
+        &self,
+        backend: &dyn Backend,
+        keys: &[Key],
+        model: &TrustModel,
+        signing_key: Option<&Key>,
+        encrypted_file_path: &Path,
+        qualified_path: &Path,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        let (plain, warning) = fs::read(self.secrets_path().join(encrypted_file_path))
+            .map_err(Error::from)
+            .and_then(|ciphertext| self.decrypt_verified(backend, &ciphertext, qualified_path))
+            .with_context(|_| {
+                format!(
+                    "Could not decrypt '{}' to re-encrypt for new recipients.",
+                    qualified_path.display()
+                )
+            })?;
+        let ciphertext = signature::encrypt(backend, keys, &plain, model, signing_key)
+            .with_context(|_| format!("Failed to re-encrypt '{}'.", qualified_path.display()))?;
+        Ok((ciphertext, warning))
+    }
+
+    /// Re-encrypt all resources for the given `keys`, staging the new content in `transaction`.
+    /// Resources which are already staged in `transaction` are skipped.
+    ///
+    /// Resources are re-encrypted by `self.jobs()` threads, but staged and reported in order.
     pub fn reencrypt(
         &self,
-        ctx: &mut gpgme::Context,
+        backend: &dyn Backend,
+        keys: &ResourceKeys,
         model: &TrustModel,
-        gpg_keys_dir: Option<&Path>,
         has_multiple_partitions: bool,
+        transaction: &mut Transaction,
         output: &mut dyn Write,
-    ) -> Result<(), Error> {
-        let keys = self.recipient_keys(ctx, gpg_keys_dir, output)?;
-
-        let mut obuf = Vec::new();
-
+    ) -> Result<usize, Error> {
         let secrets_dir = self.secrets_path();
         let qualified = |p: &Path| {
             if has_multiple_partitions {
@@ -237,59 +262,81 @@ impl Vault {
             let _change_cwd = ResetCWD::from_path(&secrets_dir)?;
             glob(GPG_GLOB).expect("valid pattern").filter_map(Result::ok).collect()
         };
-        for encrypted_file_path in files_to_reencrypt {
-            let tempfile = Temp::new_file().with_context(|_| {
-                format!(
-                    "Failed to create temporary file to hold decrypted '{}'",
-                    qualified(&encrypted_file_path).display()
+        let num_files = files_to_reencrypt.len();
+        let signing_key = self.signing_key(backend)?;
+        transaction.record_reencryption(self.index)?;
+        let files_to_reencrypt: Vec<_> = files_to_reencrypt
+            .into_iter()
+            .filter(|p| transaction.staged_path(&secrets_dir.join(p)).is_none())
+            .collect();
+
+        map_in_order(
+            &files_to_reencrypt,
+            self.jobs(),
+            |encrypted_file_path| {
+                self.reencrypt_resource(
+                    backend,
+                    &keys.for_resource(encrypted_file_path),
+                    model,
+                    signing_key.as_ref(),
+                    encrypted_file_path,
+                    &qualified(encrypted_file_path),
                 )
-            })?;
-            {
-                let mut plain_writer = write_at(&tempfile.to_path_buf())?;
-                self.decrypt(&encrypted_file_path, &mut plain_writer)
+            },
+            |encrypted_file_path, (obuf, warning)| {
+                if let Some(warning) = warning {
+                    writeln!(output, "{}", warning).ok();
+                }
+                transaction
+                    .stage_write(&secrets_dir.join(encrypted_file_path), &obuf)
                     .with_context(|_| {
-                        format!(
-                            "Could not decrypt '{}' to re-encrypt for new recipients.",
-                            qualified(&encrypted_file_path).display()
-                        )
-                    })?;
-            }
-            {
-                let mut plain_reader = File::open(tempfile.to_path_buf())?;
-                ctx.encrypt_with_flags(&keys, &mut plain_reader, &mut obuf, flags_for_model(model))
-                    .map_err(|e| {
-                        EncryptionError::caused_by(
-                            e,
-                            format!("Failed to re-encrypt '{}'.", qualified(&encrypted_file_path).display()),
-                            ctx,
-                            &keys,
-                        )
-                    })?;
-            }
-            write_at(&secrets_dir.join(&encrypted_file_path))
-                .with_context(|_| {
-                    format!(
-                        "Could not open '{}' to write encrypted data",
-                        qualified(&encrypted_file_path).display()
-                    )
-                })
-                .and_then(|mut w| {
-                    w.write_all(&obuf).with_context(|_| {
                         format!(
                             "Failed to write out encrypted data to '{}'",
-                            qualified(&encrypted_file_path).display()
+                            qualified(encrypted_file_path).display()
                         )
-                    })
-                })?;
+                    })?;
+                writeln!(
+                    output,
+                    "Re-encrypted '{}' for new recipient(s)",
+                    strip_ext(&qualified(encrypted_file_path)).display()
+                )
+                .ok();
+                Ok(())
+            },
+        )?;
+        Ok(num_files)
+    }
 
-            obuf.clear();
-            writeln!(
-                output,
-                "Re-encrypted '{}' for new recipient(s)",
-                strip_ext(&qualified(&encrypted_file_path)).display()
-            )
-            .ok();
-        }
-        Ok(())
+    /// Re-encrypt all resources of `partition` for its recipients as they will be once `transaction` is committed,
+    /// which includes the groups of a staged configuration file. Resources are still verified as they are now.
+    pub fn reencrypt_for_staged_recipients(
+        &self,
+        partition: &Vault,
+        transaction: &mut Transaction,
+        output: &mut dyn Write,
+    ) -> Result<usize, Error> {
+        let gpg_keys_dir = self.gpg_keys_dir_for_auto_import(partition);
+        let staged_vault = self.with_staged_groups(transaction)?;
+        let staged_partition = staged_vault
+            .all_in_order()
+            .into_iter()
+            .find(|p| p.index == partition.index)
+            .expect("staged vault to have the same partitions");
+        let keys = staged_partition.keys_by_ids(
+            self.backend(),
+            &staged_partition.staged_recipients_list(transaction)?,
+            "recipient",
+            gpg_keys_dir.as_deref(),
+            output,
+        )?;
+        let keys = staged_partition.resource_keys_for(self.backend(), keys, gpg_keys_dir.as_deref(), output)?;
+        partition.reencrypt(
+            self.backend(),
+            &keys,
+            &self.find_trust_model(partition),
+            !self.partitions.is_empty(),
+            transaction,
+            output,
+        )
     }
 }
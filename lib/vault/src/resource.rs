@@ -1,34 +1,73 @@
-use std::fs::{remove_file, File};
-use std::io::{self, Write};
+use std::fs::{self, remove_file, File};
+use std::io::{self, Read, Write};
 use std::mem;
 use std::path::{Path, PathBuf};
 
-use crate::base::Vault;
+use crate::backend::Key;
+use crate::base::{Vault, GPG_GLOB};
 use crate::error::FailExt;
-use crate::error::{DecryptionError, EncryptionError};
+use crate::rules::ResourceKeys;
+use crate::signature;
 use crate::spec::{gpg_output_filename, SpecSourceType, VaultSpec};
 use crate::spec::{CreateMode, Destination, WriteMode};
-use crate::util::flags_for_model;
-use crate::util::run_editor;
-use crate::util::{new_context, strip_ext, write_at};
+use crate::util::{map_in_order, strip_ext, write_at, ResetCWD};
+use crate::util::{run_editor, PlaintextFile};
 use crate::TrustModel;
 use failure::{Error, ResultExt};
-use gpgme;
+use glob::glob;
 use itertools::join;
-use mktemp::Temp;
 use std::iter::once;
 
-fn encrypt_buffer(
-    ctx: &mut gpgme::Context,
-    input: &[u8],
-    keys: &[gpgme::Key],
-    model: &TrustModel,
-) -> Result<Vec<u8>, Error> {
-    let mut encrypted_bytes = Vec::<u8>::new();
-    let flags = flags_for_model(model);
-    ctx.encrypt_with_flags(keys, input, &mut encrypted_bytes, flags)
-        .map_err(|e: gpgme::Error| EncryptionError::caused_by(e, "Failed to encrypt data.".into(), ctx, keys))?;
-    Ok(encrypted_bytes)
+/// Return the path of the existing file backing the resource at the partition-relative `path`.
+pub fn existing_resource_path(partition: &Vault, path: PathBuf) -> Result<PathBuf, Error> {
+    let spec = VaultSpec {
+        src: SpecSourceType::Stdin,
+        dst: path,
+    };
+    let gpg_path = spec.output_in(&partition.secrets_path(), Destination::ReolveAndAppendGpg)?;
+    if gpg_path.exists() {
+        Ok(gpg_path)
+    } else {
+        let new_path = strip_ext(&gpg_path);
+        if !new_path.is_file() {
+            return Err(format_err!("No file present at '{}'", gpg_path.display()));
+        }
+        Ok(new_path)
+    }
+}
+
+/// Like `existing_resource_path()`, but fails if the file is not an encrypted resource, as its content would
+/// otherwise end up unencrypted in a resource.
+fn existing_encrypted_resource_path(partition: &Vault, path: PathBuf) -> Result<PathBuf, Error> {
+    let path = existing_resource_path(partition, path)?;
+    if path.extension().is_some_and(|ext| ext == "gpg") {
+        Ok(path)
+    } else {
+        Err(format_err!(
+            "Refusing to use the file at '{}' as it is not an encrypted resource",
+            path.display()
+        ))
+    }
+}
+
+/// Return `path` as the path of an encrypted resource, appending the '.gpg' suffix if needed.
+fn resource_destination(path: PathBuf) -> Result<PathBuf, Error> {
+    if path.extension().is_some_and(|ext| ext == "gpg") {
+        Ok(path)
+    } else {
+        gpg_output_filename(&path)
+    }
+}
+
+/// The secrets directory of a partition, along with the keys to encrypt its resources for, and to sign them with.
+type PartitionKeys = (PathBuf, ResourceKeys, Option<Key>);
+
+/// A resource to be copied, which needs to be re-encrypted if it is copied into another partition.
+struct ResourceCopy<'a> {
+    partition: &'a Vault,
+    source: PathBuf,
+    destination: PathBuf,
+    reencrypt: bool,
 }
 
 impl Vault {
@@ -40,12 +79,12 @@ impl Vault {
         try_encrypt: bool,
         output: &mut dyn Write,
     ) -> Result<(), Error> {
-        let file = Temp::new_file().context("Could not create temporary file to decrypt to.")?;
-        let tempfile_path = file.to_path_buf();
+        let file = PlaintextFile::new("plaintext").context("Could not create temporary file to decrypt to.")?;
+        let tempfile_path = file.path().to_owned();
         let decrypted_file_path = {
             let mut decrypted_writer =
                 write_at(&tempfile_path).context("Failed to open temporary file for writing decrypted content to.")?;
-            self.decrypt(path, &mut decrypted_writer)
+            self.decrypt(path, &mut decrypted_writer, output)
                 .context(format!("Failed to decrypt file at '{}'.", path.display()))
                 .or_else(|err| match (mode, err.first_cause_of::<io::Error>()) {
                     (CreateMode::Create, Some(_)) => gpg_output_filename(path).and_then(|p| {
@@ -82,8 +121,8 @@ impl Vault {
         Ok(())
     }
 
-    pub fn decrypt(&self, path: &Path, w: &mut dyn Write) -> Result<PathBuf, Error> {
-        let mut ctx = new_context()?;
+    /// Write the plaintext of the resource at `path` to `w`, and warnings about its signature to `error`.
+    pub fn decrypt(&self, path: &Path, w: &mut dyn Write, error: &mut dyn Write) -> Result<PathBuf, Error> {
         let (partition, path) = self.partition_by_owned_path(path.to_owned())?;
         let resolved_absolute_path = partition.secrets_path().join(path);
         let resolved_gpg_path = gpg_output_filename(&resolved_absolute_path)?;
@@ -95,9 +134,15 @@ impl Vault {
                 resolved_gpg_path.display(),
                 resolved_absolute_path.display()
             ))?;
-        let mut output = Vec::new();
-        ctx.decrypt(&mut input, &mut output)
-            .map_err(|e: gpgme::Error| DecryptionError::caused_by(e, "Failed to decrypt data."))?;
+        let mut ciphertext = Vec::new();
+        input.read_to_end(&mut ciphertext).context(format!(
+            "Could not read all encrypted data from '{}'.",
+            path_for_decryption.display()
+        ))?;
+        let (output, warning) = partition.decrypt_verified(self.backend(), &ciphertext, &path_for_decryption)?;
+        if let Some(warning) = warning {
+            writeln!(error, "{}", warning).ok();
+        }
 
         w.write_all(&output)
             .context("Could not write out all decrypted data.")?;
@@ -107,42 +152,191 @@ impl Vault {
     pub fn remove(&self, specs: &[PathBuf], output: &mut dyn Write) -> Result<(), Error> {
         for path_to_remove in specs {
             let (partition, path_to_remove) = self.partition_by_owned_path(path_to_remove.to_owned())?;
-            let path = {
-                let spec = VaultSpec {
-                    src: SpecSourceType::Stdin,
-                    dst: path_to_remove,
-                };
-                let gpg_path = spec.output_in(&partition.secrets_path(), Destination::ReolveAndAppendGpg)?;
-                if gpg_path.exists() {
-                    gpg_path
-                } else {
-                    let new_path = strip_ext(&gpg_path);
-                    if !new_path.exists() {
-                        return Err(format_err!("No file present at '{}'", gpg_path.display()));
-                    }
-                    new_path
-                }
-            };
+            let path = existing_resource_path(partition, path_to_remove)?;
             remove_file(&path).context(format!("Failed to remove file at '{}'.", path.display()))?;
+            self.changed_paths.record(&path);
             writeln!(output, "Removed file at '{}'", path.display()).ok();
         }
         Ok(())
     }
 
+    /// Move the resource at `from` to `to`. Within a partition without access rules its ciphertext is kept, otherwise
+    /// it is re-encrypted for the recipients of its destination without its plaintext touching the disk.
+    pub fn move_resource(&self, from: &Path, to: &Path, output: &mut dyn Write) -> Result<(), Error> {
+        let (source_partition, from_path) = self.partition_by_owned_path(from.to_owned())?;
+        let source = existing_encrypted_resource_path(source_partition, from_path)?;
+        let (destination_partition, to_path) = self.partition_by_owned_path(to.to_owned())?;
+        let destination = resource_destination(destination_partition.secrets_path().join(&to_path))?;
+        if destination.exists() {
+            bail!("Refusing to overwrite existing file at '{}'", destination.display());
+        }
+        let missing_dir = destination.parent().filter(|dir| !dir.is_dir());
+
+        let ciphertext = fs::read(&source).context(format!("Could not read resource at '{}'.", source.display()))?;
+        let encrypted_bytes =
+            if source_partition.index == destination_partition.index && !destination_partition.has_rules() {
+                ciphertext
+            } else {
+                let (plaintext, warning) = source_partition
+                    .decrypt_verified(self.backend(), &ciphertext, &source)
+                    .context(format!("Failed to decrypt resource at '{}'.", source.display()))?;
+                if let Some(warning) = warning {
+                    writeln!(output, "{}", warning).ok();
+                }
+                let keys = destination_partition.resource_keys(
+                    self.backend(),
+                    self.gpg_keys_dir_for_auto_import(destination_partition).as_deref(),
+                    output,
+                )?;
+                signature::encrypt(
+                    self.backend(),
+                    &keys.for_resource(&to_path),
+                    &plaintext,
+                    &self.find_trust_model(destination_partition),
+                    destination_partition.signing_key(self.backend())?.as_ref(),
+                )?
+            };
+
+        let mut transaction = self.transaction()?;
+        transaction.stage_write(&destination, &encrypted_bytes)?;
+        transaction.stage_removal(&source)?;
+        transaction.commit()?;
+        if let Some(dir) = missing_dir {
+            writeln!(output, "Created intermediate directory at '{}'", dir.display()).ok();
+        }
+        writeln!(output, "Moved '{}' to '{}'.", source.display(), destination.display()).ok();
+        Ok(())
+    }
+
+    /// Copy the resources at `sources` to `destination`, re-encrypting them for the recipients of the
+    /// destination partition if it differs from theirs, or if it has access rules.
+    /// A single resource is copied to `destination` itself unless it is an existing directory. Multiple resources
+    /// and the content of directories are copied into the `destination` directory.
+    pub fn copy(
+        &self,
+        sources: &[PathBuf],
+        destination: &Path,
+        mode: WriteMode,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let (destination_partition, destination) = self.partition_by_owned_path(destination.to_owned())?;
+        let destination = destination_partition.secrets_path().join(destination);
+        let copy_into_dir = sources.len() > 1 || destination.is_dir();
+        let mut copies = Vec::new();
+        for source in sources {
+            let (partition, path) = self.partition_by_owned_path(source.to_owned())?;
+            let reencrypt = partition.index != destination_partition.index || destination_partition.has_rules();
+            let dir = partition.secrets_path().join(&path);
+            match existing_encrypted_resource_path(partition, path) {
+                Ok(source) => {
+                    let destination = if copy_into_dir {
+                        resource_destination(destination.join(source.file_name().expect("resource file name")))?
+                    } else {
+                        resource_destination(destination.clone())?
+                    };
+                    copies.push(ResourceCopy {
+                        partition,
+                        source,
+                        destination,
+                        reencrypt,
+                    });
+                }
+                Err(_) if dir.is_dir() => {
+                    let resources: Vec<PathBuf> = {
+                        let _change_cwd = ResetCWD::from_path(&dir)?;
+                        glob(GPG_GLOB).expect("valid pattern").filter_map(Result::ok).collect()
+                    };
+                    copies.extend(resources.into_iter().map(|resource| ResourceCopy {
+                        partition,
+                        source: dir.join(&resource),
+                        destination: destination.join(&resource),
+                        reencrypt,
+                    }));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if mode.refuse_overwrite() {
+            if let Some(copy) = copies.iter().find(|c| c.destination.exists()) {
+                bail!(
+                    "Refusing to overwrite existing file at '{}'",
+                    copy.destination.display()
+                );
+            }
+        }
+
+        let (keys, signing_key) = if copies.iter().any(|c| c.reencrypt) {
+            (
+                Some(destination_partition.resource_keys(
+                    self.backend(),
+                    self.gpg_keys_dir_for_auto_import(destination_partition).as_deref(),
+                    output,
+                )?),
+                destination_partition.signing_key(self.backend())?,
+            )
+        } else {
+            (None, None)
+        };
+        let secrets_dir = destination_partition.secrets_path();
+        let model = self.find_trust_model(destination_partition);
+        let mut transaction = self.transaction()?;
+        map_in_order(
+            &copies,
+            self.jobs(),
+            |copy| {
+                let ciphertext = fs::read(&copy.source)
+                    .context(format!("Could not read resource at '{}'.", copy.source.display()))?;
+                if !copy.reencrypt {
+                    return Ok((ciphertext, None));
+                }
+                let (plaintext, warning) = copy
+                    .partition
+                    .decrypt_verified(self.backend(), &ciphertext, &copy.source)
+                    .context(format!("Failed to decrypt resource at '{}'.", copy.source.display()))?;
+                let keys = keys
+                    .as_ref()
+                    .expect("keys when re-encrypting")
+                    .for_resource(copy.destination.strip_prefix(&secrets_dir).unwrap_or(&copy.destination));
+                let ciphertext = signature::encrypt(self.backend(), &keys, &plaintext, &model, signing_key.as_ref())?;
+                Ok((ciphertext, warning))
+            },
+            |copy, (ciphertext, warning)| {
+                if let Some(warning) = warning {
+                    writeln!(output, "{}", warning).ok();
+                }
+                if let Some(dir) = copy.destination.parent().filter(|dir| !dir.is_dir()) {
+                    writeln!(output, "Created intermediate directory at '{}'", dir.display()).ok();
+                }
+                transaction.stage_write(&copy.destination, &ciphertext)?;
+                writeln!(
+                    output,
+                    "Copied '{}' to '{}'.",
+                    copy.source.display(),
+                    copy.destination.display()
+                )
+                .ok();
+                Ok(())
+            },
+        )?;
+        transaction.commit()?;
+        writeln!(output, "Copied {} resource(s).", copies.len()).ok();
+        Ok(())
+    }
+
     pub fn encrypt_buffer(
         &self,
         input: &[u8],
         gpg_keys_dir: Option<&Path>,
         output: &mut dyn io::Write,
     ) -> Result<Vec<u8>, Error> {
-        let mut ctx = new_context()?;
-        let keys = self.recipient_keys(&mut ctx, gpg_keys_dir, output)?;
+        let keys = self.recipient_keys(self.backend(), gpg_keys_dir, output)?;
 
-        let encrypted_bytes = encrypt_buffer(
-            &mut ctx,
-            input,
+        let encrypted_bytes = signature::encrypt(
+            self.backend(),
             &keys,
+            input,
             &self.trust_model.clone().unwrap_or_else(TrustModel::default),
+            self.signing_key(self.backend())?.as_ref(),
         )?;
         Ok(encrypted_bytes)
     }
@@ -183,31 +377,31 @@ impl Vault {
         dst_mode: Destination,
         output: &mut dyn Write,
     ) -> Result<(), Error> {
-        let mut ctx = new_context()?;
-        let mut lut: Vec<Option<(PathBuf, Vec<gpgme::Key>)>> = vec![None; 1 + self.partitions.len()];
+        let mut lut: Vec<Option<PartitionKeys>> = vec![None; 1 + self.partitions.len()];
         let mut encrypted_destinations = Vec::new();
 
         for spec in specs {
             {
                 let (partition, spec) = self.partition_by_spec(spec)?;
-                let (secrets_dir, keys) = match &mut lut[partition.index] {
-                    &mut Some((ref secrets_dir, ref keys)) => (secrets_dir, keys),
+                let (secrets_dir, keys, signing_key) = match &mut lut[partition.index] {
+                    &mut Some((ref secrets_dir, ref keys, ref signing_key)) => (secrets_dir, keys, signing_key),
                     none => {
                         let gpg_keys_dir = self.gpg_keys_dir_for_auto_import(partition);
                         mem::replace(
                             none,
                             Some((
                                 partition.secrets_path(),
-                                partition.recipient_keys(
-                                    &mut ctx,
+                                partition.resource_keys(
+                                    self.backend(),
                                     gpg_keys_dir.as_ref().map(PathBuf::as_path),
                                     output,
                                 )?,
+                                partition.signing_key(self.backend())?,
                             )),
                         );
                         let some = none;
-                        let &(ref secrets_dir, ref keys) = some.as_ref().expect("the content that was just put in");
-                        (secrets_dir, keys)
+                        let (secrets_dir, keys, signing_key) = some.as_ref().expect("the content that was just put in");
+                        (secrets_dir, keys, signing_key)
                     }
                 };
                 let input = {
@@ -220,13 +414,21 @@ impl Vault {
                     ))?;
                     buf
                 };
-                let encrypted_bytes = encrypt_buffer(&mut ctx, &input, keys, &self.find_trust_model(partition))?;
+                let destination = spec.output_in(secrets_dir, dst_mode)?;
+                let encrypted_bytes = signature::encrypt(
+                    self.backend(),
+                    &keys.for_resource(destination.strip_prefix(secrets_dir).unwrap_or(&destination)),
+                    &input,
+                    &self.find_trust_model(partition),
+                    signing_key.as_ref(),
+                )?;
                 spec.open_output_in(secrets_dir, mode, dst_mode, output)?
                     .write_all(&encrypted_bytes)
                     .context(format!(
                         "Failed to write all encrypted data to '{}'.",
                         spec.destination().display(),
                     ))?;
+                self.changed_paths.record(&destination);
             }
             encrypted_destinations.push(spec.destination());
         }
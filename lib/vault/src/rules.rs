@@ -0,0 +1,190 @@
+use crate::backend::{Backend, Key};
+use crate::base::Vault;
+use crate::util::{strip_ext, FingerprintUserId};
+use failure::{Error, ResultExt};
+use glob::{MatchOptions, Pattern};
+use std::io::Write;
+use std::path::{Component, Path};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Recipients of the resources of a partition matching `pattern`, which is relative to its secrets directory.
+///
+/// If `recipients` are set, they replace the recipients of the partition, and `extra_recipients` are added to them.
+/// Both may refer to groups as `@name`.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Hash)]
+pub struct AccessRule {
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_recipients: Vec<String>,
+}
+
+impl AccessRule {
+    fn compiled_pattern(&self) -> Result<Pattern, Error> {
+        if Path::new(&self.pattern)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!(
+                "The pattern '{}' of an access rule must be relative to the secrets directory of its partition",
+                self.pattern
+            );
+        }
+        Ok(Pattern::new(&self.pattern).context(format!("Invalid pattern '{}' in access rule", self.pattern))?)
+    }
+}
+
+#[derive(Clone)]
+struct ResolvedRule {
+    pattern: Pattern,
+    recipients: Option<Vec<Key>>,
+    extra_recipients: Vec<Key>,
+}
+
+/// The keys to encrypt the resources of a partition for, which depend on their path if it has access rules.
+#[derive(Clone)]
+pub struct ResourceKeys {
+    keys: Vec<Key>,
+    rules: Vec<ResolvedRule>,
+}
+
+impl ResourceKeys {
+    /// The keys of the recipients of the partition, regardless of its access rules.
+    pub fn partition_keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// The keys to encrypt the resource at `path`, relative to the secrets directory of its partition, for.
+    /// All matching rules are applied in order.
+    pub fn for_resource(&self, path: &Path) -> Vec<Key> {
+        let path = if path.extension().is_some_and(|ext| ext == "gpg") {
+            strip_ext(path)
+        } else {
+            path.to_owned()
+        };
+        let mut keys = self.keys.clone();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.matches_path_with(&path, MATCH_OPTIONS))
+        {
+            if let Some(recipients) = rule.recipients.as_ref() {
+                keys = recipients.clone();
+            }
+            for key in &rule.extra_recipients {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        keys
+    }
+
+    /// Return a description of each way the access rules contradict the recipients of their partition.
+    pub fn contradictions(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for rule in &self.rules {
+            let recipients = match rule.recipients.as_ref() {
+                Some(recipients) => recipients,
+                None => continue,
+            };
+            if recipients.is_empty() && rule.extra_recipients.is_empty() {
+                problems.push(format!(
+                    "the access rule for '{}' leaves no recipients",
+                    rule.pattern.as_str()
+                ));
+            }
+            problems.extend(
+                recipients
+                    .iter()
+                    .filter(|key| !self.keys.iter().any(|k| k.fingerprint == key.fingerprint))
+                    .map(|key| {
+                        format!(
+                            "the access rule for '{}' grants access to {}, who is not a recipient of the partition, \
+                             which is what 'extra_recipients' are for",
+                            rule.pattern.as_str(),
+                            FingerprintUserId(key)
+                        )
+                    }),
+            );
+        }
+        problems
+    }
+}
+
+impl Vault {
+    pub fn has_rules(&self) -> bool {
+        self.rules.as_ref().is_some_and(|rules| !rules.is_empty())
+    }
+
+    /// Fail if the pattern of one of the access rules of this partition is invalid.
+    pub fn validate_rules(&self) -> Result<(), Error> {
+        for rule in self.rules.iter().flatten() {
+            rule.compiled_pattern()?;
+        }
+        Ok(())
+    }
+
+    /// The ids of all recipients the access rules of this partition refer to, with groups replaced by their members.
+    pub fn rule_recipients(&self) -> Result<Vec<String>, Error> {
+        self.expand_groups(
+            self.rules
+                .iter()
+                .flatten()
+                .flat_map(|rule| rule.recipients.iter().flatten().chain(&rule.extra_recipients))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// The keys to encrypt the resources of this partition for, given the `keys` of its recipients.
+    pub fn resource_keys_for(
+        &self,
+        backend: &dyn Backend,
+        keys: Vec<Key>,
+        gpg_keys_dir: Option<&Path>,
+        output: &mut dyn Write,
+    ) -> Result<ResourceKeys, Error> {
+        let mut rules = Vec::new();
+        for rule in self.rules.iter().flatten() {
+            let mut keys_of = |ids: &[String]| -> Result<Vec<Key>, Error> {
+                if ids.is_empty() {
+                    return Ok(Vec::new());
+                }
+                self.keys_by_ids(
+                    backend,
+                    &self.expand_groups(ids.to_owned())?,
+                    "recipient",
+                    gpg_keys_dir,
+                    output,
+                )
+            };
+            rules.push(ResolvedRule {
+                pattern: rule.compiled_pattern()?,
+                recipients: match rule.recipients.as_ref() {
+                    Some(ids) => Some(keys_of(ids)?),
+                    None => None,
+                },
+                extra_recipients: keys_of(&rule.extra_recipients)?,
+            });
+        }
+        Ok(ResourceKeys { keys, rules })
+    }
+
+    /// As `resource_keys_for()`, using the keys of the current recipients of this partition.
+    pub fn resource_keys(
+        &self,
+        backend: &dyn Backend,
+        gpg_keys_dir: Option<&Path>,
+        output: &mut dyn Write,
+    ) -> Result<ResourceKeys, Error> {
+        let keys = self.recipient_keys(backend, gpg_keys_dir, output)?;
+        self.resource_keys_for(backend, keys, gpg_keys_dir, output)
+    }
+}
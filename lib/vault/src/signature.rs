@@ -0,0 +1,402 @@
+use crate::backend::{Backend, Key, Validity};
+use crate::base::{SignaturePolicy, Vault};
+use crate::recipients::find_signing_key_among;
+use crate::transaction::{with_suffix, Transaction};
+use crate::util::{absolute, write_at};
+use crate::TrustModel;
+use failure::{Error, ResultExt};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, create_dir_all};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+const SIGNATURE_SUFFIX: &str = ".sig";
+pub const VERIFIED_RECIPIENTS_FILE_ENV: &str = "SY_VERIFIED_RECIPIENTS_FILE";
+
+lazy_static! {
+    /// Serializes updates of the file of verified recipients made by the threads of this process.
+    static ref VERIFIED_RECIPIENTS_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// The recipients of partitions by the absolute path of their recipients file.
+type VerifiedRecipients = BTreeMap<String, Vec<String>>;
+
+/// The file recording verified recipients is `$SY_VERIFIED_RECIPIENTS_FILE`, or
+/// `~/.config/sheesy/verified-recipients.yml` if unset.
+pub fn verified_recipients_default() -> PathBuf {
+    env::var_os(VERIFIED_RECIPIENTS_FILE_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .unwrap_or_default()
+                .join("sheesy")
+                .join("verified-recipients.yml")
+        })
+}
+
+fn read_verified_recipients(path: &Path) -> Result<VerifiedRecipients, Error> {
+    if !path.is_file() {
+        return Ok(VerifiedRecipients::new());
+    }
+    let content = fs::read(path).context(format!("Could not read verified recipients at '{}'", path.display()))?;
+    Ok(serde_yaml::from_slice(&content)
+        .context(format!("Could not parse verified recipients at '{}'", path.display()))?)
+}
+
+/// Encrypt `plaintext` for `keys` using `backend`, and sign it with `signing_key` if there is one.
+pub fn encrypt(
+    backend: &dyn Backend,
+    keys: &[Key],
+    plaintext: &[u8],
+    model: &TrustModel,
+    signing_key: Option<&Key>,
+) -> Result<Vec<u8>, Error> {
+    match signing_key {
+        Some(signing_key) => backend.encrypt_signed(keys, plaintext, model, signing_key),
+        None => backend.encrypt(keys, plaintext, model),
+    }
+}
+
+/// Return a warning about `problem`, or fail because of it, depending on `policy`.
+fn apply_policy(policy: SignaturePolicy, problem: &str) -> Result<String, Error> {
+    match policy {
+        SignaturePolicy::Warn => Ok(format!("Warning: {}.", problem)),
+        SignaturePolicy::Require => bail!("{}.", problem),
+    }
+}
+
+impl Vault {
+    /// Return the key to sign the resources of this partition with, if they are to be signed at all.
+    /// It is our only secret key on the recipients list of this partition.
+    pub fn signing_key(&self, backend: &dyn Backend) -> Result<Option<Key>, Error> {
+        if self.signatures.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.find_signing_key(backend, None).context(
+            "Resources of this partition are signed, but no key to sign them with could be found.",
+        )?))
+    }
+
+    /// Decrypt the `ciphertext` of the resource at `path` of this partition using `backend`.
+    ///
+    /// If signatures are configured, it must have been signed by one of the current recipients of this partition,
+    /// whose recipients file must be signed by someone we trust as well (see `verify_recipients_list()`).
+    /// Otherwise it is an error, or the returned warning explains why its signature is not trusted.
+    pub fn decrypt_verified(
+        &self,
+        backend: &dyn Backend,
+        ciphertext: &[u8],
+        path: &Path,
+    ) -> Result<(Zeroizing<Vec<u8>>, Option<String>), Error> {
+        let (policy, recipients_list_policy) = match (self.signatures, self.recipients_list_policy()?) {
+            (Some(policy), Some(recipients_list_policy)) => (policy, recipients_list_policy),
+            _ => return Ok((backend.decrypt(ciphertext)?, None)),
+        };
+        let (plaintext, signers) = backend.decrypt_verified(ciphertext)?;
+        let mut warnings: Vec<String> = self
+            .recipients_list_warning(backend, recipients_list_policy)?
+            .into_iter()
+            .collect();
+        let recipients = self.recipients_list()?;
+        if !signers.iter().any(|signer| recipients.contains(signer)) {
+            let problem = if signers.is_empty() {
+                format!(
+                    "The resource at '{}' has no valid signature of a known key",
+                    path.display()
+                )
+            } else {
+                format!(
+                    "The resource at '{}' was signed by {}, which is not a recipient of its partition",
+                    path.display(),
+                    signers.join(", ")
+                )
+            };
+            warnings.push(apply_policy(policy, &problem)?);
+        }
+        Ok((
+            plaintext,
+            if warnings.is_empty() {
+                None
+            } else {
+                Some(warnings.join("\n"))
+            },
+        ))
+    }
+
+    /// The path of the detached signature of the recipients file.
+    pub fn recipients_signature_path(&self) -> PathBuf {
+        with_suffix(&self.recipients_path(), SIGNATURE_SUFFIX)
+    }
+
+    /// What the signature of the recipients of this partition covers, given all of its `recipients`, including
+    /// inherited ones, followed by its access rules, with references to groups replaced by their members.
+    /// This way, changing the members of groups, inheriting recipients or changing access rules invalidates the
+    /// signature just like editing the recipients file does.
+    fn recipients_statement(&self, recipients: Vec<String>) -> Result<Vec<u8>, Error> {
+        let canonical = |mut ids: Vec<String>| {
+            ids.sort();
+            ids.dedup();
+            ids
+        };
+        let line = |name: &str, ids: Vec<String>| {
+            format!(
+                "{}{}\n",
+                name,
+                canonical(ids).iter().map(|id| format!(" {}", id)).collect::<String>()
+            )
+        };
+        let mut statement: String = canonical(recipients).iter().map(|r| format!("{}\n", r)).collect();
+        for rule in self.rules.iter().flatten() {
+            statement.push_str(&format!("rule {}\n", rule.pattern));
+            if let Some(recipients) = rule.recipients.as_ref() {
+                statement.push_str(&line("recipients", self.expand_groups(recipients.to_owned())?));
+            }
+            if !rule.extra_recipients.is_empty() {
+                statement.push_str(&line(
+                    "extra_recipients",
+                    self.expand_groups(rule.extra_recipients.to_owned())?,
+                ));
+            }
+        }
+        Ok(statement.into_bytes())
+    }
+
+    /// Return our only secret key among the current recipients of this partition, to sign its recipients with.
+    pub fn recipients_signing_key(&self) -> Result<Key, Error> {
+        Ok(find_signing_key_among(self.backend(), None, &self.recipients_list()?)
+            .context("The recipients file is signed, but none of its current recipients is you.")?)
+    }
+
+    /// Return the keys to sign the recipients of those `partitions` configured to use signatures with, by the
+    /// index of the partition. This has to be done before their recipients are changed.
+    pub fn recipients_signing_keys(partitions: &[&Vault]) -> Result<Vec<(usize, Key)>, Error> {
+        partitions
+            .iter()
+            .filter(|partition| partition.signatures.is_some())
+            .map(|partition| Ok((partition.index, partition.recipients_signing_key()?)))
+            .collect()
+    }
+
+    /// Sign the recipients of the partitions with the given indices as they are now, each with the respective key.
+    pub fn sign_recipients_with(&self, signing_keys: &[(usize, Key)]) -> Result<(), Error> {
+        if signing_keys.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.transaction()?;
+        self.stage_recipients_signatures_with(signing_keys, &mut transaction)?;
+        transaction.commit()
+    }
+
+    /// As `sign_recipients_with()`, but sign the recipients as they will be once `transaction` is committed,
+    /// in `transaction`.
+    pub fn stage_recipients_signatures_with(
+        &self,
+        signing_keys: &[(usize, Key)],
+        transaction: &mut Transaction,
+    ) -> Result<(), Error> {
+        for partition in self.all_in_order() {
+            if let Some((_, signing_key)) = signing_keys.iter().find(|(index, _)| *index == partition.index) {
+                partition.stage_recipients_signature_by(signing_key, transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// If signatures are configured, stage a detached signature of the recipients of this partition as they will
+    /// be once `transaction` is committed, in `transaction`.
+    /// It is made with our only secret key among the current recipients, or among the staged ones if there is no
+    /// recipients file yet.
+    pub fn stage_recipients_signature(&self, transaction: &mut Transaction) -> Result<(), Error> {
+        if self.signatures.is_none() {
+            return Ok(());
+        }
+        let signing_key = if self.recipients_path().is_file() {
+            self.recipients_signing_key()?
+        } else {
+            find_signing_key_among(self.backend(), None, &self.staged_recipients_list(transaction)?)
+                .context("The recipients file is signed, but none of its recipients is you.")?
+        };
+        self.stage_recipients_signature_by(&signing_key, transaction)
+    }
+
+    /// As `stage_recipients_signature()`, but sign with `signing_key`, which is obtained from
+    /// `recipients_signing_key()` before the recipients are changed.
+    pub fn stage_recipients_signature_by(&self, signing_key: &Key, transaction: &mut Transaction) -> Result<(), Error> {
+        if self.signatures.is_none() {
+            return Ok(());
+        }
+        let statement = self.recipients_statement(self.staged_recipients_list(transaction)?)?;
+        let signature = self.backend().sign_detached(&statement, signing_key)?;
+        let signature_path = self.recipients_signature_path();
+        transaction.stage_write(&signature_path, &signature).context(format!(
+            "Failed to write signature of recipients to file at '{}'",
+            signature_path.display()
+        ))?;
+        Ok(())
+    }
+
+    /// If signatures are configured, verify that the recipients file was signed by ourselves, or by one of its
+    /// recipients as they were when we last verified it. If we never did, the signer must be a key we certified.
+    /// Otherwise it is an error, or a warning is written to `output`, depending on the policy.
+    /// Once verified, the current recipients are remembered in the file at `verified_recipients`, and from then on
+    /// they are always verified, see `recipients_list_policy()`.
+    pub fn verify_recipients_list(&self, backend: &dyn Backend, output: &mut dyn Write) -> Result<(), Error> {
+        if let Some(warning) = match self.recipients_list_policy()? {
+            Some(policy) => self.recipients_list_warning(backend, policy)?,
+            None => None,
+        } {
+            writeln!(output, "{}", warning).ok();
+        }
+        Ok(())
+    }
+
+    fn recipients_list_warning(&self, backend: &dyn Backend, policy: SignaturePolicy) -> Result<Option<String>, Error> {
+        let recipients_path = self.recipients_path();
+        let signature_path = self.recipients_signature_path();
+        let problem = if signature_path.is_file() {
+            let recipients = self.recipients_list()?;
+            let statement = self.recipients_statement(recipients.clone())?;
+            let signature = fs::read(&signature_path).context(format!(
+                "Could not read signature of recipients file at '{}'",
+                signature_path.display()
+            ))?;
+            let signers = backend.verify_detached(&statement, &signature)?;
+            let own_keys = backend.find_secret_keys(&signers)?;
+            let previous_recipients = self.previously_verified_recipients()?;
+            let is_trusted = |signer: &String| {
+                own_keys.iter().any(|k| k.fingerprint == *signer)
+                    || match previous_recipients {
+                        Some(ref previous_recipients) => previous_recipients.contains(signer),
+                        None => backend.key(signer).map(|k| k.trust >= Validity::Full).unwrap_or(false),
+                    }
+            };
+            if signers.iter().any(is_trusted) {
+                self.remember_verified_recipients(recipients)?;
+                return Ok(None);
+            }
+            if signers.is_empty() {
+                format!(
+                    "The recipients file at '{}' was changed without a valid signature of a known key",
+                    recipients_path.display()
+                )
+            } else if previous_recipients.is_some() {
+                format!(
+                    "The recipients file at '{}' was signed by {}, who was not one of its recipients when you \
+                     last verified it",
+                    recipients_path.display(),
+                    signers.join(", ")
+                )
+            } else {
+                format!(
+                    "The recipients file at '{}' was signed by {}, whose key is neither yours nor certified by you",
+                    recipients_path.display(),
+                    signers.join(", ")
+                )
+            }
+        } else {
+            format!(
+                "The recipients file at '{}' is not signed, as '{}' is missing",
+                recipients_path.display(),
+                signature_path.display()
+            )
+        };
+        apply_policy(policy, &problem).map(Some)
+    }
+
+    /// The policy to verify the recipients file with, if it is to be verified at all.
+    /// Once its recipients were verified, it always has to be signed by someone we trust, no matter what the
+    /// vault configuration says, as everyone who can change the recipients file can change the configuration, too.
+    /// Thus signatures cannot be disabled anymore unless the partition is forgotten in the file of verified
+    /// recipients.
+    fn recipients_list_policy(&self) -> Result<Option<SignaturePolicy>, Error> {
+        if self.previously_verified_recipients()?.is_none() {
+            return Ok(self.signatures);
+        }
+        match self.signatures {
+            Some(_) => Ok(Some(SignaturePolicy::Require)),
+            None => bail!(
+                "The partition at '{}' is not configured to use signatures, even though its recipients were verified \
+                 before. If signatures were disabled on purpose, please remove the entry for '{}' from '{}'.",
+                self.secrets_path().display(),
+                self.verified_recipients_key(),
+                self.verified_recipients.display()
+            ),
+        }
+    }
+
+    fn verified_recipients_key(&self) -> String {
+        absolute(&self.recipients_path()).to_string_lossy().into_owned()
+    }
+
+    /// The recipients of this partition when its recipients file was last verified, if it ever was.
+    fn previously_verified_recipients(&self) -> Result<Option<Vec<String>>, Error> {
+        Ok(read_verified_recipients(&self.verified_recipients)?.remove(&self.verified_recipients_key()))
+    }
+
+    /// Remember `recipients` as the verified recipients of this partition.
+    fn remember_verified_recipients(&self, mut recipients: Vec<String>) -> Result<(), Error> {
+        let _lock = VERIFIED_RECIPIENTS_LOCK
+            .lock()
+            .expect("no panic while remembering recipients");
+        let path = &self.verified_recipients;
+        let mut verified = read_verified_recipients(path)?;
+        recipients.sort();
+        if verified.get(&self.verified_recipients_key()) == Some(&recipients) {
+            return Ok(());
+        }
+        verified.insert(self.verified_recipients_key(), recipients);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.is_dir()) {
+            create_dir_all(dir).context(format!("Could not create directory at '{}'", dir.display()))?;
+        }
+        let staged = with_suffix(path, &format!(".{}.tmp", process::id()));
+        write_at(&staged)
+            .map_err(Error::from)
+            .and_then(|mut w| Ok(serde_yaml::to_writer(&mut w, &verified)?))
+            .and_then(|_| Ok(fs::rename(&staged, path)?))
+            .context(format!("Could not write verified recipients to '{}'", path.display()))?;
+        Ok(())
+    }
+
+    /// Sign the recipients of the given `partitions`, or of all partitions with signatures configured if there
+    /// are none, with our secret key among their current recipients, and thus approve them as they are.
+    /// This is needed after changing the configuration of a partition by hand.
+    pub fn sign_recipients(&self, partitions: &[String], output: &mut dyn Write) -> Result<(), Error> {
+        let partitions = if partitions.is_empty() {
+            self.all_in_order()
+                .into_iter()
+                .filter(|partition| partition.signatures.is_some())
+                .collect()
+        } else {
+            self.partitions_by_name_or_path(partitions)?
+        };
+        if partitions.is_empty() {
+            bail!("None of the partitions is configured to use signatures.");
+        }
+        let mut transaction = self.transaction()?;
+        for partition in &partitions {
+            if partition.signatures.is_none() {
+                bail!(
+                    "The partition at '{}' is not configured to use signatures.",
+                    partition.secrets_path().display()
+                );
+            }
+            partition.stage_recipients_signature(&mut transaction)?;
+        }
+        transaction.commit()?;
+        for partition in partitions {
+            writeln!(
+                output,
+                "Signed the recipients file at '{}'",
+                partition.recipients_path().display()
+            )
+            .ok();
+        }
+        Ok(())
+    }
+}
@@ -4,10 +4,9 @@ use std::fs::create_dir_all;
 use std::fs::{File, OpenOptions};
 use std::io::{self, stdin, Read, Write};
 
-use crate::util::run_editor;
+use crate::util::{run_editor, PlaintextFile};
 use atty;
 use failure::{Error, ResultExt};
-use mktemp::Temp;
 use std::env;
 use std::ffi::OsString;
 use std::path::Component;
@@ -73,7 +72,7 @@ pub fn gpg_output_filename(path: &Path) -> Result<PathBuf, Error> {
 }
 
 struct TemporaryFile {
-    _tempfile: Temp,
+    _tempfile: PlaintextFile,
     open_file: File,
 }
 
@@ -143,8 +142,9 @@ impl VaultSpec {
             }
             SpecSourceType::Stdin => {
                 if atty::is(atty::Stream::Stdin) {
-                    let tempfile = Temp::new_file().with_context(|_| "Failed to obtain temporary file for editing.")?;
-                    let tempfile_path = tempfile.to_path_buf();
+                    let tempfile =
+                        PlaintextFile::new("plaintext").context("Failed to obtain temporary file for editing.")?;
+                    let tempfile_path = tempfile.path().to_owned();
                     run_editor(EDITOR.as_os_str(), &tempfile_path)?;
                     Box::new(TemporaryFile {
                         _tempfile: tempfile,
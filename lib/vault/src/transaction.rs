@@ -0,0 +1,424 @@
+use crate::util::{absolute, write_at};
+use failure::{err_msg, Error, ResultExt};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const STAGED_SUFFIX: &str = ".sy-staged";
+const BACKUP_SUFFIX: &str = ".sy-backup";
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// True if `path` is a file a transaction stages a change in, or backs up the original in while committing.
+pub fn is_transaction_file(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.ends_with(STAGED_SUFFIX) || path.ends_with(BACKUP_SUFFIX)
+}
+
+/// A cheaply clonable record of the absolute paths of all files which were written or removed, shared by a
+/// vault and all of its partitions. It allows to commit exactly the files a command changed.
+#[derive(Clone, Default)]
+pub struct ChangedPaths(Arc<Mutex<BTreeSet<PathBuf>>>);
+
+impl ChangedPaths {
+    /// Record that the file at `path` was written or removed.
+    pub fn record(&self, path: &Path) {
+        self.0.lock().expect("no panic while recording").insert(absolute(path));
+    }
+
+    /// Record all paths recorded by `other` as well.
+    pub fn extend(&self, other: &ChangedPaths) {
+        for path in other.paths() {
+            self.record(&path);
+        }
+    }
+
+    /// All recorded paths, sorted.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0
+            .lock()
+            .expect("no panic while recording")
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Debug for ChangedPaths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ChangedPaths")
+    }
+}
+
+/// The changed paths are runtime state, and do not contribute to the identity of a vault.
+impl PartialEq for ChangedPaths {
+    fn eq(&self, _other: &ChangedPaths) -> bool {
+        true
+    }
+}
+
+impl Eq for ChangedPaths {}
+
+impl Hash for ChangedPaths {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[derive(Debug)]
+enum Change {
+    Write { staged: PathBuf, destination: PathBuf },
+    Remove(PathBuf),
+}
+
+impl Change {
+    fn destination(&self) -> &Path {
+        match *self {
+            Change::Write { ref destination, .. } | Change::Remove(ref destination) => destination,
+        }
+    }
+}
+
+/// A change of a file which was already applied, and which can be undone as long as the transaction
+/// was not fully committed.
+/// Undoing a write moves the new content back to where it was staged, so the change can still be applied by
+/// resuming the transaction if the rollback does not succeed as a whole.
+#[derive(Debug)]
+struct Applied {
+    destination: PathBuf,
+    backup: Option<PathBuf>,
+    written_from: Option<PathBuf>,
+}
+
+impl Applied {
+    fn undo(&self) -> Result<(), Error> {
+        if let Some(ref staged) = self.written_from {
+            fs::rename(&self.destination, staged).with_context(|_| {
+                format!(
+                    "Failed to move '{}' back to '{}'",
+                    self.destination.display(),
+                    staged.display()
+                )
+            })?;
+        }
+        if let Some(ref backup) = self.backup {
+            fs::rename(backup, &self.destination).with_context(|_| {
+                format!(
+                    "Failed to restore '{}' from '{}'",
+                    self.destination.display(),
+                    backup.display()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// An append-only record of a transaction, which allows to resume it if the process was interrupted.
+///
+/// Each line is a record, and lines which were not fully written are ignored.
+/// * `reencrypt <partition-index>` - all resources of the partition are re-encrypted
+/// * `mkdir <path>` - the directory at `path` was created to stage a write in it
+/// * `write <path>` - the new content of `path` was fully staged
+/// * `remove <path>` - `path` will be removed
+/// * `commit` - staging is done, and changes are being applied
+#[derive(Debug)]
+struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    fn record(&mut self, line: &str) -> Result<(), Error> {
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.write_all(b"\n"))
+            .and_then(|_| self.file.sync_data())
+            .with_context(|_| format!("Failed to write to journal at '{}'", self.path.display()))?;
+        Ok(())
+    }
+}
+
+fn journaled_path(path: &Path) -> Result<&str, Error> {
+    path.to_str()
+        .ok_or_else(|| format_err!("Path '{}' cannot be journaled as it is not valid UTF-8", path.display()))
+}
+
+/// A set of changes to files, which are applied all at once, or not at all.
+///
+/// The content of files to be written is staged right away in a file next to its destination, so that
+/// committing only has to move files into place. Missing directories are created along the way.
+/// A transaction that is dropped without being committed leaves all files untouched, and removes the
+/// directories it created if they are empty.
+/// If it has a journal, an interrupted transaction can be picked up again using `Transaction::resume()`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    changes: Vec<Change>,
+    reencrypted_partitions: Vec<usize>,
+    created_dirs: Vec<PathBuf>,
+    committing: bool,
+    journal: Option<Journal>,
+    changed_paths: ChangedPaths,
+}
+
+impl Transaction {
+    /// Start a new transaction which records its progress in a journal at `journal_path`.
+    /// Fails if there already is a journal, as it belongs to an interrupted transaction.
+    pub fn journaled(journal_path: &Path) -> Result<Self, Error> {
+        if journal_path.is_file() {
+            bail!(
+                "Found the journal of an interrupted change at '{}'. \
+                 Please resume it with 'sy vault resume', or discard it with 'sy vault resume --abort'.",
+                journal_path.display()
+            );
+        }
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(journal_path)
+            .with_context(|_| format!("Failed to create journal at '{}'", journal_path.display()))?;
+        Ok(Transaction {
+            changes: Vec::new(),
+            reencrypted_partitions: Vec::new(),
+            created_dirs: Vec::new(),
+            committing: false,
+            journal: Some(Journal {
+                path: journal_path.to_owned(),
+                file,
+            }),
+            changed_paths: ChangedPaths::default(),
+        })
+    }
+
+    /// Record the destinations of all changes in `changed_paths` once they are committed.
+    pub fn record_changes_in(mut self, changed_paths: ChangedPaths) -> Self {
+        self.changed_paths = changed_paths;
+        self
+    }
+
+    /// Pick up the interrupted transaction recorded in the journal at `journal_path`.
+    pub fn resume(journal_path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(journal_path)
+            .with_context(|_| format!("Failed to read journal at '{}'", journal_path.display()))?;
+        let complete_lines = &content[..content.rfind('\n').map_or(0, |pos| pos + 1)];
+        let mut transaction = Transaction::default();
+        for line in complete_lines.lines() {
+            let mut tokens = line.splitn(2, ' ');
+            match (tokens.next(), tokens.next()) {
+                (Some("reencrypt"), Some(index)) => transaction.reencrypted_partitions.push(index.parse()?),
+                (Some("write"), Some(path)) => transaction.changes.push(Change::Write {
+                    staged: with_suffix(Path::new(path), STAGED_SUFFIX),
+                    destination: path.into(),
+                }),
+                (Some("remove"), Some(path)) => transaction.changes.push(Change::Remove(path.into())),
+                (Some("mkdir"), Some(path)) => transaction.created_dirs.push(path.into()),
+                (Some("commit"), None) => transaction.committing = true,
+                _ => bail!("Invalid line '{}' in journal at '{}'", line, journal_path.display()),
+            }
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .open(journal_path)
+            .with_context(|_| format!("Failed to open journal at '{}'", journal_path.display()))?;
+        transaction.journal = Some(Journal {
+            path: journal_path.to_owned(),
+            file,
+        });
+        Ok(transaction)
+    }
+
+    fn record(&mut self, line: &str) -> Result<(), Error> {
+        match self.journal {
+            Some(ref mut journal) => journal.record(line),
+            None => Ok(()),
+        }
+    }
+
+    /// Create the missing parent directories of `path`, outermost first.
+    fn create_parent_dirs(&mut self, path: &Path) -> Result<(), Error> {
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.is_dir())
+            .map(Path::to_owned)
+            .collect();
+        for dir in missing.into_iter().rev() {
+            self.record(&format!("mkdir {}", journaled_path(&dir)?))?;
+            fs::create_dir(&dir)
+                .with_context(|_| format!("Failed to create intermediate directory at '{}'", dir.display()))?;
+            self.created_dirs.push(dir);
+        }
+        Ok(())
+    }
+
+    /// Stage `content` to be written to `path` once the transaction is committed.
+    pub fn stage_write(&mut self, path: &Path, content: &[u8]) -> Result<(), Error> {
+        self.create_parent_dirs(path)?;
+        let staged = with_suffix(path, STAGED_SUFFIX);
+        self.changes.push(Change::Write {
+            staged: staged.clone(),
+            destination: path.to_owned(),
+        });
+        write_at(&staged)
+            .and_then(|mut w| w.write_all(content).and_then(|_| w.sync_all()))
+            .with_context(|_| format!("Failed to write staged data to '{}'", staged.display()))?;
+        self.record(&format!("write {}", journaled_path(path)?))
+    }
+
+    /// Stage the file at `path` to be removed once the transaction is committed.
+    pub fn stage_removal(&mut self, path: &Path) -> Result<(), Error> {
+        self.changes.push(Change::Remove(path.to_owned()));
+        self.record(&format!("remove {}", journaled_path(path)?))
+    }
+
+    /// Record that all resources of the partition with the given index are about to be re-encrypted.
+    pub fn record_reencryption(&mut self, partition_index: usize) -> Result<(), Error> {
+        if self.reencrypted_partitions.contains(&partition_index) {
+            return Ok(());
+        }
+        self.reencrypted_partitions.push(partition_index);
+        self.record(&format!("reencrypt {}", partition_index))
+    }
+
+    /// The indices of all partitions whose resources are re-encrypted in this transaction.
+    pub fn reencrypted_partitions(&self) -> &[usize] {
+        &self.reencrypted_partitions
+    }
+
+    /// Return the path at which the new content of `path` was staged, if there is one.
+    pub fn staged_path(&self, path: &Path) -> Option<&Path> {
+        self.changes
+            .iter()
+            .rev()
+            .find(|c| c.destination() == path)
+            .and_then(|c| match *c {
+                Change::Write { ref staged, .. } => Some(staged.as_path()),
+                Change::Remove(_) => None,
+            })
+    }
+
+    /// Returns true if this transaction was interrupted while its changes were being applied.
+    pub fn is_committing(&self) -> bool {
+        self.committing
+    }
+
+    /// Discard all staged changes. This is only possible if they are not yet being applied.
+    pub fn abort(self) -> Result<(), Error> {
+        if self.committing {
+            return Err(err_msg(
+                "The changes cannot be discarded as they were already being applied, please resume instead.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Apply all staged changes. If one of them fails, all changes applied so far are undone.
+    /// Changes applied before the transaction was interrupted cannot be undone though, which is why the journal
+    /// is kept if a resumed commit fails, just like when the rollback fails, so that they can be finished later.
+    pub fn commit(mut self) -> Result<(), Error> {
+        let resumes_commit = self.committing;
+        self.record("commit")?;
+        self.committing = true;
+
+        let mut applied = Vec::new();
+        match self
+            .changes
+            .iter()
+            .try_for_each(|change| apply(change, resumes_commit, &mut applied))
+        {
+            Ok(()) => {
+                for change in &self.changes {
+                    fs::remove_file(with_suffix(change.destination(), BACKUP_SUFFIX)).ok();
+                    self.changed_paths.record(change.destination());
+                }
+                self.created_dirs.clear();
+                self.committing = false;
+                Ok(())
+            }
+            Err(err) => {
+                if resumes_commit {
+                    return Err(err
+                        .context(
+                            "Failed to finish applying the changes of an interrupted transaction, which were \
+                             partially applied already. Please resume them again once the problem is fixed.",
+                        )
+                        .into());
+                }
+                let mut first_undo_err = None;
+                for change in applied.iter().rev() {
+                    if let Err(undo_err) = change.undo() {
+                        first_undo_err.get_or_insert(undo_err);
+                    }
+                }
+                if let Some(undo_err) = first_undo_err {
+                    return Err(undo_err
+                        .context(format!("Failed to roll back after failing to commit changes: {}", err))
+                        .into());
+                }
+                self.committing = false;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// If `resumes_commit` is true, changes which were already applied before the commit was interrupted are skipped.
+fn apply(change: &Change, resumes_commit: bool, applied: &mut Vec<Applied>) -> Result<(), Error> {
+    let (staged, destination) = match *change {
+        Change::Write {
+            ref staged,
+            ref destination,
+        } => (Some(staged), destination),
+        Change::Remove(ref destination) => (None, destination),
+    };
+    if resumes_commit && staged.is_some_and(|staged| !staged.exists()) {
+        return Ok(());
+    }
+    let backup = if destination.exists() {
+        let backup = with_suffix(destination, BACKUP_SUFFIX);
+        fs::rename(destination, &backup)
+            .with_context(|_| format!("Failed to move '{}' out of the way", destination.display()))?;
+        Some(backup)
+    } else {
+        None
+    };
+    applied.push(Applied {
+        destination: destination.clone(),
+        backup,
+        written_from: None,
+    });
+    if let Some(staged) = staged {
+        fs::rename(staged, destination)
+            .with_context(|_| format!("Failed to move '{}' into place", destination.display()))?;
+        applied.last_mut().expect("just pushed").written_from = Some(staged.clone());
+    }
+    Ok(())
+}
+
+/// Unless the transaction is left in the middle of applying its changes, its staged files, the empty directories
+/// it created for them, and its journal are removed.
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.committing {
+            return;
+        }
+        for change in &self.changes {
+            if let Change::Write { ref staged, .. } = *change {
+                fs::remove_file(staged).ok();
+            }
+        }
+        for dir in self.created_dirs.iter().rev() {
+            fs::remove_dir(dir).ok();
+        }
+        if let Some(ref journal) = self.journal {
+            fs::remove_file(&journal.path).ok();
+        }
+    }
+}
@@ -1,16 +1,32 @@
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
-use crate::TrustModel;
-use failure::{self, err_msg, Error, ResultExt};
-use gpgme;
+use crate::backend::{Backend, Key};
+use crate::base::normalize;
+use failure::{err_msg, Error, ResultExt};
 use itertools::{join, Itertools};
-use std::env::{current_dir, set_current_dir};
+use std::collections::BTreeMap;
+use std::env::{self, current_dir, set_current_dir};
 use std::ffi::OsStr;
 use std::fmt;
-use std::fs::{self, OpenOptions};
+use std::fs::{self, DirBuilder, OpenOptions};
 use std::process::Command;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Return `path` as absolute path, resolving it relative to the current working directory if needed.
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        normalize(path)
+    } else {
+        current_dir()
+            .map(|cwd| normalize(&cwd.join(path)))
+            .unwrap_or_else(|_| normalize(path))
+    }
+}
 
 pub fn strip_ext(p: &Path) -> PathBuf {
     let mut p = p.to_owned();
@@ -19,81 +35,106 @@ pub fn strip_ext(p: &Path) -> PathBuf {
     p
 }
 
-pub fn fingerprints_of_keys(keys: &[gpgme::Key]) -> Result<Vec<(&gpgme::Key, String)>, Error> {
-    keys.iter()
-        .map(|k| fingerprint_of(k).map(|fpr| (k, fpr)))
-        .collect::<Result<Vec<_>, _>>()
-        .with_context(|_| "Unexpectedly failed to obtain fingerprint")
-        .map_err(Into::into)
+/// Quote `s` so that a POSIX shell passes it on as a single argument, without interpreting any of its characters.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+pub fn fingerprints_of_keys(keys: &[Key]) -> Vec<(&Key, String)> {
+    keys.iter().map(|k| (k, k.fingerprint.clone())).collect()
+}
+
+/// Call `map` on all `items` using up to `jobs` threads, and pass each result to `consume` in the order of `items`.
+///
+/// Processing stops at the first error, which is always the one of the first failing item.
+pub fn map_in_order<T, R, M, C>(items: &[T], jobs: usize, map: M, mut consume: C) -> Result<(), Error>
+where
+    T: Sync,
+    R: Send,
+    M: Fn(&T) -> Result<R, Error> + Sync,
+    C: FnMut(&T, R) -> Result<(), Error>,
+{
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..jobs.min(items.len()) {
+            let (tx, next, failed, map) = (tx.clone(), &next, &failed, &map);
+            scope.spawn(move || {
+                while !failed.load(Ordering::SeqCst) {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let item = match items.get(index) {
+                        Some(item) => item,
+                        None => break,
+                    };
+                    let result = map(item);
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    if tx.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut next_to_consume = 0;
+        for (index, result) in rx {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_to_consume) {
+                if let Err(err) = result.and_then(|r| consume(&items[next_to_consume], r)) {
+                    failed.store(true, Ordering::SeqCst);
+                    return Err(err);
+                }
+                next_to_consume += 1;
+            }
+        }
+        Ok(())
+    })
 }
+
 pub fn write_at(path: &Path) -> io::Result<fs::File> {
     OpenOptions::new().create(true).write(true).truncate(true).open(path)
 }
 
-pub struct UserIdFingerprint<'a>(pub &'a gpgme::Key);
+pub struct UserIdFingerprint<'a>(pub &'a Key);
 impl<'a> fmt::Display for UserIdFingerprint<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} ({})",
-            join(self.0.user_ids().map(|u| u.id().unwrap_or("[none]")), ", "),
-            self.0.fingerprint().unwrap_or("[no fingerprint!]")
-        )
+        write!(f, "{} ({})", join(&self.0.user_ids, ", "), self.0.fingerprint)
     }
 }
 
-pub struct FingerprintUserId<'a>(pub &'a gpgme::Key);
+pub struct FingerprintUserId<'a>(pub &'a Key);
 impl<'a> fmt::Display for FingerprintUserId<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} ({})",
-            self.0.fingerprint().unwrap_or("[no fingerprint!]"),
-            join(self.0.user_ids().map(|u| u.id().unwrap_or("[none]")), ", ")
-        )
+        write!(f, "{} ({})", self.0.fingerprint, join(&self.0.user_ids, ", "))
     }
 }
 
-pub fn fingerprint_of(key: &gpgme::Key) -> Result<String, failure::Error> {
-    key.fingerprint()
-        .map_err(|e| {
-            e.map(Into::into)
-                .unwrap_or_else(|| err_msg("Fingerprint extraction failed"))
-        })
-        .map(ToOwned::to_owned)
-}
-
-pub fn new_context() -> Result<gpgme::Context, gpgme::Error> {
-    gpgme::Context::from_protocol(gpgme::Protocol::OpenPgp)
-}
-
-pub struct KeylistDisplay<'a>(pub &'a [gpgme::Key]);
+pub struct KeylistDisplay<'a>(pub &'a [Key]);
 
 impl<'a> fmt::Display for KeylistDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", join(self.0.iter().map(|k| KeyDisplay(k)), ", "))
     }
 }
-pub struct KeyDisplay<'a>(pub &'a gpgme::Key);
+pub struct KeyDisplay<'a>(pub &'a Key);
 
 impl<'a> fmt::Display for KeyDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            join(self.0.user_ids().map(|u| u.id().unwrap_or("[none]")), ", ")
-        )
+        write!(f, "{}", join(&self.0.user_ids, ", "))
     }
 }
 
 pub fn export_key_with_progress(
-    ctx: &mut gpgme::Context,
+    backend: &dyn Backend,
     gpg_keys_dir: &Path,
-    key: &gpgme::Key,
-    buf: &mut Vec<u8>,
+    key: &Key,
     output: &mut dyn Write,
 ) -> Result<(String, PathBuf), Error> {
-    let (fingerprint, key_path) = export_key(ctx, gpg_keys_dir, key, buf)?;
+    let (fingerprint, key_path) = export_key(backend, gpg_keys_dir, key)?;
     writeln!(
         output,
         "Exported public key for user {} to '{}'",
@@ -104,37 +145,17 @@ pub fn export_key_with_progress(
     Ok((fingerprint, key_path))
 }
 
-pub fn export_key(
-    ctx: &mut gpgme::Context,
-    gpg_keys_dir: &Path,
-    key: &gpgme::Key,
-    buf: &mut Vec<u8>,
-) -> Result<(String, PathBuf), Error> {
-    let fingerprint = fingerprint_of(key)?;
-    let key_path = gpg_keys_dir.join(&fingerprint);
-    ctx.set_armor(true);
-    ctx.export_keys([key].iter().cloned(), gpgme::ExportMode::empty(), &mut *buf)
-        .with_context(|_| err_msg("Failed to export at least one public key with signatures."))?;
+pub fn export_key(backend: &dyn Backend, gpg_keys_dir: &Path, key: &Key) -> Result<(String, PathBuf), Error> {
+    let key_path = gpg_keys_dir.join(&key.fingerprint);
+    let buf = backend.export_key(key)?;
     write_at(&key_path)
-        .and_then(|mut f| f.write_all(buf))
+        .and_then(|mut f| f.write_all(&buf))
         .with_context(|_| format!("Could not write public key file at '{}'", key_path.display()))?;
-    buf.clear();
-    Ok((fingerprint, key_path))
+    Ok((key.fingerprint.clone(), key_path))
 }
 
-pub fn extract_at_least_one_secret_key(
-    ctx: &mut gpgme::Context,
-    gpg_key_ids: &[String],
-) -> Result<Vec<gpgme::Key>, Error> {
-    let keys = {
-        let mut keys_iter = ctx.find_secret_keys(gpg_key_ids)?;
-        let keys: Vec<_> = keys_iter.by_ref().collect::<Result<_, _>>()?;
-
-        if keys_iter.finish()?.is_truncated() {
-            return Err(err_msg("The key list was truncated unexpectedly, while iterating it"));
-        }
-        keys
-    };
+pub fn extract_at_least_one_secret_key(backend: &dyn Backend, gpg_key_ids: &[String]) -> Result<Vec<Key>, Error> {
+    let keys = backend.find_secret_keys(gpg_key_ids)?;
 
     if keys.is_empty() {
         return Err(if gpg_key_ids.is_empty() {
@@ -205,6 +226,103 @@ pub fn run_editor(editor: &OsStr, path_to_edit: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// A file to hold plaintext, placed in a directory which is private to this invocation.
+///
+/// Memory-backed locations like `$XDG_RUNTIME_DIR` or `/dev/shm` are preferred over the system's temporary directory.
+/// When dropped, all files in the directory are overwritten with zeros before the directory is removed,
+/// which includes any backup or swap files an editor might have left behind.
+pub struct PlaintextFile {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+fn plaintext_dir_base() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute() && p.is_dir())
+        .or_else(|| Some(PathBuf::from("/dev/shm")).filter(|p| p.is_dir()))
+        .unwrap_or_else(env::temp_dir)
+}
+
+fn create_private_dir(base: &Path) -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    let mut attempts = 0;
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let dir = base.join(format!(
+            "sy-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+            nanos
+        ));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => attempts += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn overwrite_with_zeros(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 4096];
+    let mut remaining = file.metadata()?.len();
+    while remaining > 0 {
+        let len = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..len])?;
+        remaining -= len as u64;
+    }
+    file.sync_all()
+}
+
+impl PlaintextFile {
+    /// Create an empty file named `file_name`, readable and writable only by the current user.
+    pub fn new(file_name: &str) -> Result<Self, Error> {
+        let base = plaintext_dir_base();
+        let dir = create_private_dir(&base)
+            .with_context(|_| format!("Could not create private temporary directory in '{}'.", base.display()))?;
+        let path = dir.join(file_name);
+        let plaintext = PlaintextFile { dir, path };
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&plaintext.path)
+            .with_context(|_| format!("Could not create temporary file at '{}'.", plaintext.path.display()))?;
+        Ok(plaintext)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PlaintextFile {
+    fn drop(&mut self) {
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.filter_map(Result::ok) {
+                if entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                    overwrite_with_zeros(&entry.path()).ok();
+                }
+            }
+        }
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
 pub fn print_causes<E, W>(e: E, mut w: W)
 where
     E: Into<Error>,
@@ -224,15 +342,3 @@ where
         }
     }
 }
-
-pub fn flags_for_model(model: &TrustModel) -> gpgme::EncryptFlags {
-    let mut flags = gpgme::EncryptFlags::empty();
-    flags.set(
-        gpgme::EncryptFlags::ALWAYS_TRUST,
-        match *model {
-            TrustModel::Always => true,
-            TrustModel::GpgWebOfTrust => false,
-        },
-    );
-    flags
-}
@@ -0,0 +1,62 @@
+extern crate age;
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use age::secrecy::ExposeSecret;
+use common::{add_resource, show, vault_at};
+use mktemp::Temp;
+use sheesy_vault::error::{first_cause_of_type, DecryptionError};
+use sheesy_vault::{AgeBackend, Encryption, SharedBackend, SigningMode};
+use std::fs;
+use std::path::Path;
+
+fn age_identity_at(path: &Path) -> String {
+    let identity = age::x25519::Identity::generate();
+    fs::write(path, format!("# a comment\n{}\n", identity.to_string().expose_secret())).unwrap();
+    identity.to_public().to_string()
+}
+
+#[test]
+fn it_encrypts_and_decrypts_resources_for_age_recipients() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let identities = Temp::new_dir().unwrap();
+    let identities = identities.to_path_buf();
+    let ours = age_identity_at(&identities.join("ours.txt"));
+    let theirs = age_identity_at(&identities.join("theirs.txt"));
+    let vault = vault_at(&dir, &[&ours], AgeBackend::with_identities(identities.join("ours.txt")));
+    add_resource(&vault, &dir, "secret", "hello");
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+
+    let vault = vault.set_backend(SharedBackend::new(AgeBackend::with_identities(
+        identities.join("theirs.txt"),
+    )));
+    let err = show(&vault, "secret").unwrap_err();
+    assert!(first_cause_of_type::<DecryptionError>(&err).is_some());
+
+    let vault = vault.set_backend(SharedBackend::new(AgeBackend::with_identities(
+        identities.join("ours.txt"),
+    )));
+    vault
+        .add_recipients(&[theirs], SigningMode::None, None, &[], &mut Vec::new())
+        .unwrap();
+    let mut vault = vault.set_backend(SharedBackend::new(AgeBackend::with_identities(
+        identities.join("theirs.txt"),
+    )));
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+
+    vault.encryption = Some(Encryption::Age);
+    let mut output = Vec::new();
+    vault.fsck(&mut output, &mut Vec::new()).unwrap();
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .starts_with("All 1 resource(s) are encrypted. Their recipients were not checked"));
+
+    fs::write(dir.join("plain.gpg"), "hello").unwrap();
+    let mut output = Vec::new();
+    let err = vault.fsck(&mut output, &mut Vec::new()).unwrap_err();
+    assert_eq!(format!("{}", err), "Found 1 problem(s) while checking 2 resource(s).");
+    assert_eq!(String::from_utf8(output).unwrap(), "plain.gpg: not encrypted\n");
+}
@@ -0,0 +1,44 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::error::{first_cause_of_type, DecryptionError};
+use sheesy_vault::{SharedBackend, SigningMode};
+
+#[test]
+fn it_encrypts_and_decrypts_resources_using_the_given_backend() {
+    let dir = Temp::new_dir().unwrap();
+    let vault = vault_at(&dir.to_path_buf(), &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir.to_path_buf(), "secret", "hello");
+
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+}
+
+#[test]
+fn it_reports_a_decryption_error_if_the_content_was_not_encrypted_for_us() {
+    let dir = Temp::new_dir().unwrap();
+    let vault = vault_at(&dir.to_path_buf(), &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir.to_path_buf(), "secret", "hello");
+
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    let err = show(&vault, "secret").unwrap_err();
+    assert!(first_cause_of_type::<DecryptionError>(&err).is_some());
+}
+
+#[test]
+fn it_reencrypts_all_resources_when_adding_a_recipient() {
+    let dir = Temp::new_dir().unwrap();
+    let vault = vault_at(&dir.to_path_buf(), &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir.to_path_buf(), "secret", "hello");
+
+    vault
+        .add_recipients(&[BOB.to_owned()], SigningMode::None, None, &[], &mut Vec::new())
+        .unwrap();
+
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    assert_eq!(vault.recipients_list().unwrap(), vec![ALICE.to_owned(), BOB.to_owned()]);
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+}
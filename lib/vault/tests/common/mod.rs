@@ -0,0 +1,239 @@
+//! A backend for testing and the vaults using it, shared by all tests of the vault.
+#![allow(dead_code)]
+
+use failure::{err_msg, Error};
+use sheesy_vault::error::DecryptionError;
+use sheesy_vault::{Backend, Destination, Key, SharedBackend, SpecSourceType, TrustModel, Vault, VaultSpec, WriteMode};
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+pub const ALICE: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+pub const BOB: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+/// A backend which 'encrypts' by prefixing the content with the fingerprints of all recipients,
+/// followed by the fingerprint of the signer after a '|', if it was signed.
+pub struct InMemory {
+    pub keys: Vec<Key>,
+    pub secret_keys: Vec<String>,
+}
+
+impl InMemory {
+    pub fn with_secret_key_of(fingerprint: &str) -> Self {
+        InMemory {
+            keys: [ALICE, BOB]
+                .iter()
+                .map(|fpr| Key {
+                    fingerprint: fpr.to_string(),
+                    user_ids: vec![format!("user {}", &fpr[..1])],
+                    can_encrypt: true,
+                    ..Default::default()
+                })
+                .collect(),
+            secret_keys: vec![fingerprint.to_owned()],
+        }
+    }
+}
+
+fn header_of(ciphertext: &[u8]) -> Option<(String, usize)> {
+    ciphertext.iter().position(|b| *b == b'\n').map(|header_end| {
+        (
+            String::from_utf8_lossy(&ciphertext[..header_end]).into_owned(),
+            header_end,
+        )
+    })
+}
+
+impl Backend for InMemory {
+    fn encrypt(&self, keys: &[Key], plaintext: &[u8], _model: &TrustModel) -> Result<Vec<u8>, Error> {
+        let mut ciphertext = keys
+            .iter()
+            .map(|k| k.fingerprint.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+            .into_bytes();
+        ciphertext.push(b'\n');
+        ciphertext.extend_from_slice(plaintext);
+        Ok(ciphertext)
+    }
+
+    fn encrypt_signed(
+        &self,
+        keys: &[Key],
+        plaintext: &[u8],
+        model: &TrustModel,
+        signing_key: &Key,
+    ) -> Result<Vec<u8>, Error> {
+        if !self.secret_keys.contains(&signing_key.fingerprint) {
+            return Err(err_msg("No secret key to sign with"));
+        }
+        let ciphertext = self.encrypt(keys, plaintext, model)?;
+        let (header, header_end) = header_of(&ciphertext).expect("header");
+        Ok(format!("{}|{}", header, signing_key.fingerprint)
+            .into_bytes()
+            .into_iter()
+            .chain(ciphertext[header_end..].iter().cloned())
+            .collect())
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.decrypt_verified(ciphertext).map(|(plaintext, _)| plaintext)
+    }
+
+    fn decrypt_verified(&self, ciphertext: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), Error> {
+        let (header, header_end) = header_of(ciphertext).ok_or_else(|| err_msg("not encrypted"))?;
+        let mut header = header.splitn(2, '|');
+        let recipients = header.next().expect("at least one item");
+        if !recipients
+            .split(',')
+            .any(|fpr| self.secret_keys.iter().any(|s| s == fpr))
+        {
+            return Err(DecryptionError {
+                cause: err_msg("No secret key"),
+            }
+            .into());
+        }
+        let signers = header
+            .next()
+            .filter(|signer| self.keys.iter().any(|k| k.fingerprint == *signer))
+            .map(ToOwned::to_owned)
+            .into_iter()
+            .collect();
+        Ok((Zeroizing::new(ciphertext[header_end + 1..].to_owned()), signers))
+    }
+
+    fn sign_detached(&self, data: &[u8], signing_key: &Key) -> Result<Vec<u8>, Error> {
+        if !self.secret_keys.contains(&signing_key.fingerprint) {
+            return Err(err_msg("No secret key to sign with"));
+        }
+        Ok(format!("{}\n", signing_key.fingerprint)
+            .into_bytes()
+            .into_iter()
+            .chain(data.iter().cloned())
+            .collect())
+    }
+
+    fn verify_detached(&self, data: &[u8], signature: &[u8]) -> Result<Vec<String>, Error> {
+        Ok(match header_of(signature) {
+            Some((signer, header_end))
+                if &signature[header_end + 1..] == data && self.keys.iter().any(|k| k.fingerprint == signer) =>
+            {
+                vec![signer]
+            }
+            _ => Vec::new(),
+        })
+    }
+
+    fn recipients_of(&self, ciphertext: &[u8]) -> Result<Vec<String>, Error> {
+        Ok(match header_of(ciphertext) {
+            Some((header, _)) => header
+                .split('|')
+                .next()
+                .expect("at least one item")
+                .split(',')
+                .map(ToOwned::to_owned)
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn recipient_ids(&self, key: &Key) -> Result<Vec<String>, Error> {
+        Ok(vec![key.fingerprint.clone()])
+    }
+
+    fn key(&self, id: &str) -> Result<Key, Error> {
+        self.keys
+            .iter()
+            .find(|k| k.fingerprint.ends_with(id) || k.user_ids.iter().any(|u| u == id))
+            .cloned()
+            .ok_or_else(|| err_msg("End of file"))
+    }
+
+    fn find_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        if ids.is_empty() {
+            return Ok(self.keys.clone());
+        }
+        Ok(ids.iter().filter_map(|id| self.key(id).ok()).collect())
+    }
+
+    fn find_secret_keys(&self, ids: &[String]) -> Result<Vec<Key>, Error> {
+        Ok(self
+            .find_keys(ids)?
+            .into_iter()
+            .filter(|k| self.secret_keys.contains(&k.fingerprint))
+            .collect())
+    }
+
+    fn import_keys(&self, _key_data: &[u8]) -> Result<Vec<String>, Error> {
+        Err(err_msg("import is not supported"))
+    }
+
+    fn export_key(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        Ok(key.fingerprint.clone().into_bytes())
+    }
+
+    fn sign_key(&self, _key: &Key, _signing_key: &Key) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub fn vault_at<B: Backend + 'static>(dir: &Path, recipients: &[&str], backend: B) -> Vault {
+    let vault = Vault {
+        secrets: dir.to_owned(),
+        recipients: dir.join(".gpg-id"),
+        verified_recipients: dir.join(".verified-recipients.yml"),
+        ..Default::default()
+    }
+    .set_backend(SharedBackend::new(backend));
+    vault
+        .write_recipients_list(&mut recipients.iter().map(|s| s.to_string()).collect())
+        .unwrap();
+    vault
+}
+
+pub fn add_resource(vault: &Vault, dir: &Path, name: &str, content: &str) {
+    let source = dir.join(format!("{}.plain", name.replace('/', "-")));
+    fs::write(&source, content).unwrap();
+    vault
+        .encrypt(
+            &[VaultSpec {
+                src: SpecSourceType::Path(source.clone()),
+                dst: name.into(),
+            }],
+            WriteMode::RefuseOverwrite,
+            Destination::ReolveAndAppendGpg,
+            &mut Vec::new(),
+        )
+        .unwrap();
+    fs::remove_file(&source).unwrap();
+}
+
+pub fn show(vault: &Vault, name: &str) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    vault.decrypt(Path::new(name), &mut buf, &mut Vec::new())?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+/// A vault with partition 'p1' for ALICE and 'p2' for BOB, whose resources can all be decrypted.
+pub fn partitioned_vault_at(dir: &Path) -> Vault {
+    let partition = |index, name: &str| Vault {
+        index,
+        resolved_at: dir.to_owned(),
+        secrets: name.into(),
+        recipients: Path::new(name).join(".gpg-id"),
+        verified_recipients: dir.join(".verified-recipients.yml"),
+        ..Default::default()
+    };
+    let mut vault = partition(0, "p1");
+    vault.partitions.push(partition(1, "p2"));
+    let mut backend = InMemory::with_secret_key_of(ALICE);
+    backend.secret_keys.push(BOB.to_owned());
+    let vault = vault.set_backend(SharedBackend::new(backend));
+    for (partition, recipient) in vault.all_in_order().into_iter().zip(&[ALICE, BOB]) {
+        fs::create_dir(partition.secrets_path()).unwrap();
+        partition
+            .write_recipients_list(&mut vec![recipient.to_string()])
+            .unwrap();
+    }
+    vault
+}
@@ -0,0 +1,70 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, show, BOB};
+use mktemp::Temp;
+use sheesy_vault::WriteMode;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn it_copies_directories_across_partitions_without_overwriting_unless_asked_to() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    add_resource(&vault, &dir, "p1/prod/a", "a");
+    add_resource(&vault, &dir, "p1/prod/sub/b", "b");
+    add_resource(&vault, &dir, "p2/staging/a", "old a");
+
+    let copy = |mode| vault.copy(&["p1/prod".into()], Path::new("p2/staging"), mode, &mut Vec::new());
+    assert!(copy(WriteMode::RefuseOverwrite).is_err());
+    assert!(!dir.join("p2/staging/sub/b.gpg").exists());
+    assert_eq!(show(&vault, "p2/staging/a").unwrap(), "old a");
+
+    copy(WriteMode::AllowOverwrite).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/staging/sub/b.gpg")).unwrap(),
+        format!("{}\nb", BOB)
+    );
+    assert_eq!(show(&vault, "p2/staging/a").unwrap(), "a");
+    assert_eq!(show(&vault, "p1/prod/a").unwrap(), "a");
+}
+
+#[test]
+fn it_leaves_no_directories_behind_if_copying_fails() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    add_resource(&vault, &dir, "p1/prod/sub/a", "a");
+    fs::write(dir.join("p1/prod/z-broken.gpg"), "not encrypted for anyone").unwrap();
+
+    assert!(vault
+        .copy(
+            &["p1/prod".into()],
+            Path::new("p2/new"),
+            WriteMode::AllowOverwrite,
+            &mut Vec::new()
+        )
+        .is_err());
+    assert!(!dir.join("p2/new").exists());
+}
+
+#[test]
+fn it_refuses_to_copy_files_which_are_not_encrypted() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    fs::write(dir.join("p1/notes"), "plaintext").unwrap();
+
+    assert!(vault
+        .copy(
+            &["p1/notes".into()],
+            Path::new("p1/copied"),
+            WriteMode::AllowOverwrite,
+            &mut Vec::new()
+        )
+        .is_err());
+    assert!(!dir.join("p1/copied.gpg").exists());
+}
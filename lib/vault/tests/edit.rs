@@ -0,0 +1,58 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE};
+use mktemp::Temp;
+use sheesy_vault::CreateMode;
+use std::fs;
+use std::path::Path;
+
+/// Write an editor script into `dir` which records the listing of the file to edit and its directory,
+/// replaces the file's content, and exits with `exit_code`.
+#[cfg(unix)]
+fn editor_at(dir: &Path, exit_code: i32) -> (std::path::PathBuf, std::path::PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+    let (editor, log) = (dir.join("editor.sh"), dir.join("editor.log"));
+    fs::write(
+        &editor,
+        format!(
+            "#!/bin/sh\necho \"$1\" > '{log}'\nls -ld \"$(dirname \"$1\")\" \"$1\" >> '{log}'\necho edited > \"$1\"\nexit {}\n",
+            exit_code,
+            log = log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    (editor, log)
+}
+
+#[cfg(unix)]
+#[test]
+fn it_edits_resources_in_a_private_temporary_file_which_is_removed_afterwards() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir, "secret", "hello");
+
+    for &exit_code in &[0, 1] {
+        let (editor, log) = editor_at(&dir, exit_code);
+        let result = vault.edit(
+            Path::new("secret"),
+            &editor,
+            CreateMode::NoCreate,
+            false,
+            &mut Vec::new(),
+        );
+        assert_eq!(result.is_ok(), exit_code == 0);
+
+        let log = fs::read_to_string(&log).unwrap();
+        let mut lines = log.lines();
+        let plaintext_path = Path::new(lines.next().unwrap());
+        assert!(lines.next().unwrap().starts_with("drwx------"));
+        assert!(lines.next().unwrap().starts_with("-rw-------"));
+        assert!(!plaintext_path.parent().unwrap().exists());
+    }
+    assert_eq!(show(&vault, "secret").unwrap(), "edited\n");
+}
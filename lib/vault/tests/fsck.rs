@@ -0,0 +1,65 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use std::fs;
+
+#[test]
+fn it_reports_resources_not_encrypted_for_exactly_the_current_recipients() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = vault_at(&dir, &[ALICE, BOB], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir, "secret", "hello");
+
+    let mut output = Vec::new();
+    vault.fsck(&mut output, &mut Vec::new()).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "All 1 resource(s) are encrypted for exactly their recipients.\n"
+    );
+
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    add_resource(&vault, &dir, "other", "world");
+    fs::write(dir.join("notes.txt"), "plaintext").unwrap();
+    fs::write(dir.join("plain.gpg"), "").unwrap();
+    vault.write_recipients_list(&mut vec![BOB.to_owned()]).unwrap();
+
+    let mut output = Vec::new();
+    let err = vault.fsck(&mut output, &mut Vec::new()).unwrap_err();
+    assert_eq!(format!("{}", err), "Found 5 problem(s) while checking 4 resource(s).");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "notes.txt: not encrypted\n\
+             other.gpg: encrypted for {alice} (user A), who is not a recipient\n\
+             other.gpg: not encrypted for recipient {bob} (user B)\n\
+             plain.gpg: not encrypted\n\
+             secret.gpg: encrypted for {alice} (user A), who is not a recipient\n",
+            alice = ALICE,
+            bob = BOB
+        )
+    );
+}
+
+#[test]
+fn it_keeps_checking_the_encryption_of_resources_if_the_keys_of_recipients_cannot_be_found() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir, "secret", "hello");
+    fs::write(dir.join("plain.gpg"), "").unwrap();
+    vault.write_recipients_list(&mut vec!["unknown".to_owned()]).unwrap();
+
+    let mut output = Vec::new();
+    let err = vault.fsck(&mut output, &mut Vec::new()).unwrap_err();
+    assert_eq!(format!("{}", err), "Found 2 problem(s) while checking 2 resource(s).");
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with(&format!(
+        "{}: could not determine the keys of its recipients: Didn't find the key for 1 recipient(s)",
+        dir.join(".gpg-id").display()
+    )));
+    assert!(output.ends_with("\nplain.gpg: not encrypted\n"));
+}
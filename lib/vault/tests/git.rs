@@ -0,0 +1,183 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{SharedBackend, Vault, VaultExt, WriteMode};
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn it_sets_up_git_to_show_decrypted_resources_in_diffs() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let config = dir.join("sy-vault.yml");
+    let vault = partitioned_vault_at(&dir);
+    vault.to_file(&config, WriteMode::RefuseOverwrite).unwrap();
+    add_resource(&vault, &dir, "p1/secret", "hello");
+    add_resource(&vault, &dir, "p2/other", "for bob");
+
+    let blob = dir.join("blob");
+    fs::copy(dir.join("p1/secret.gpg"), &blob).unwrap();
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(ALICE)));
+    let mut output = Vec::new();
+    vault.git_textconv(&blob, &mut output).unwrap();
+    assert_eq!(output, b"hello");
+    let mut output = Vec::new();
+    vault.git_textconv(&dir.join("p2/other.gpg"), &mut output).unwrap();
+    assert_eq!(output, b"<This resource was not encrypted for you.>\n");
+
+    assert!(std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(&dir)
+        .status()
+        .unwrap()
+        .success());
+    let vault = Vault::from_file(&config).unwrap().select("0").unwrap();
+    vault.git_setup("sy vault", &mut Vec::new()).unwrap();
+    vault.git_setup("sy vault", &mut Vec::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join(".gitattributes")).unwrap(),
+        "p1/**/*.gpg diff=sheesy merge=sheesy\np2/**/*.gpg diff=sheesy merge=sheesy\n"
+    );
+    let git_config = fs::read_to_string(dir.join(".git/config")).unwrap();
+    assert!(git_config.contains("[diff \"sheesy\"]"));
+    assert!(git_config.contains("git-textconv"));
+    assert!(git_config.contains("git-merge-driver %O %A %B %P"));
+}
+
+#[test]
+fn it_quotes_the_vault_configuration_path_in_git_commands() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf().join("it's a vault");
+    fs::create_dir(&dir).unwrap();
+    let config = dir.join("sy-vault.yml");
+    let vault = partitioned_vault_at(&dir);
+    vault.to_file(&config, WriteMode::RefuseOverwrite).unwrap();
+    assert!(std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(&dir)
+        .status()
+        .unwrap()
+        .success());
+    let vault = Vault::from_file(&config).unwrap().select("0").unwrap();
+    vault.git_setup("printf '%s\\n'", &mut Vec::new()).unwrap();
+
+    let textconv = std::process::Command::new("git")
+        .args(["config", "diff.sheesy.textconv"])
+        .current_dir(&dir)
+        .output()
+        .unwrap()
+        .stdout;
+    let args = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(String::from_utf8(textconv).unwrap().trim_end())
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(
+        String::from_utf8(args).unwrap(),
+        format!(
+            "--config-file={}\ngit-textconv\n",
+            fs::canonicalize(&config).unwrap().display()
+        )
+    );
+}
+
+#[test]
+fn it_merges_resources_line_by_line_or_structurally_and_reencrypts_the_result() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    let merge = |name: &str, base: &str, ours: &str, theirs: &str| {
+        for (version, content) in &[("base", base), ("ours", ours), ("theirs", theirs)] {
+            add_resource(&vault, &dir, &format!("p2/{}.{}", name, version), content);
+        }
+        let version = |v: &str| dir.join(format!("p2/{}.{}.gpg", name, v));
+        let result = vault.git_merge_driver(
+            &version("base"),
+            &version("ours"),
+            &version("theirs"),
+            Some(&dir.join(format!("p2/{}.gpg", name))),
+            &mut Vec::new(),
+        );
+        (result, fs::read_to_string(version("ours")).unwrap())
+    };
+
+    let (result, merged) = merge("notes", "a\nb\nc\nd\n", "a\nB\nc\nd\n", "a\nb\nc\nD\n");
+    result.unwrap();
+    assert_eq!(merged, format!("{}\na\nB\nc\nD\n", BOB));
+
+    let (result, merged) = merge(
+        "conf.yml",
+        "user: admin\npassword: old\n",
+        "user: admin\npassword: new\n",
+        "user: root\npassword: old\n",
+    );
+    result.unwrap();
+    assert_eq!(merged, format!("{}\n---\npassword: new\nuser: root\n", BOB));
+
+    let (result, merged) = merge("conflict", "old\n", "ours\n", "theirs\n");
+    assert!(result.is_err());
+    assert_eq!(
+        merged,
+        format!("{}\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n", BOB)
+    );
+}
+
+#[test]
+fn it_commits_only_the_files_it_changed_to_git_if_configured_to_do_so() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    git(&["init", "-q"]);
+    git(&["config", "user.name", "tester"]);
+    git(&["config", "user.email", "tester@example.com"]);
+
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.resolved_at = dir.clone();
+    add_resource(&vault, &dir, "secret", "hello");
+    vault.commit_changes(|_| "unused".into(), &mut Vec::new()).unwrap();
+    assert!(std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(&dir)
+        .output()
+        .map(|o| !o.status.success())
+        .unwrap());
+
+    fs::write(dir.join("untracked"), "").unwrap();
+    fs::write(dir.join("unrelated"), "staged before").unwrap();
+    git(&["add", "unrelated"]);
+    vault.auto_commit = Some(true);
+    let mut output = Vec::new();
+    vault
+        .commit_changes(
+            |num_resources| format!("Added {} resource(s)", num_resources),
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(git(&["log", "--format=%s"]), "Added 1 resource(s)\n");
+    assert_eq!(git(&["show", "--name-only", "--format="]), ".gpg-id\nsecret.gpg\n");
+    assert_eq!(git(&["status", "--porcelain"]), "A  unrelated\n?? untracked\n");
+    vault
+        .commit_changes(|_| "nothing changed".into(), &mut Vec::new())
+        .unwrap();
+    assert_eq!(git(&["log", "--format=%s"]), "Added 1 resource(s)\n");
+
+    vault.remove(&[PathBuf::from("secret")], &mut Vec::new()).unwrap();
+    vault
+        .commit_changes(|_| "Removed secret".into(), &mut Vec::new())
+        .unwrap();
+    assert_eq!(git(&["log", "--format=%s"]), "Removed secret\nAdded 1 resource(s)\n");
+    assert_eq!(git(&["status", "--porcelain"]), "A  unrelated\n?? untracked\n");
+}
@@ -0,0 +1,39 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, InMemory, ALICE};
+use mktemp::Temp;
+use sheesy_vault::SharedBackend;
+
+#[test]
+fn it_greps_decrypted_resources_and_skips_those_not_encrypted_for_us() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    add_resource(&vault, &dir, "p1/db", "host: db.example.com\npassword: hunter2\n");
+    add_resource(&vault, &dir, "p1/sub/web", "host: web.example.com");
+    add_resource(&vault, &dir, "p2/ops", "host: ops.example.com");
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(ALICE)));
+
+    let (mut output, mut error) = (Vec::new(), Vec::new());
+    vault.grep(r"host: \w+\.example", &[], &mut output, &mut error).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "p1/db:host: db.example.com\np1/sub/web:host: web.example.com\n"
+    );
+    assert_eq!(
+        String::from_utf8(error).unwrap(),
+        "Skipped 'p2/ops' as it was not encrypted for you.\n"
+    );
+
+    let mut output = Vec::new();
+    vault
+        .grep("hunter", &["p1/db".into()], &mut output, &mut Vec::new())
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "p1/db:password: hunter2\n");
+    assert!(vault
+        .grep("hunter", &["p1/sub".into()], &mut Vec::new(), &mut Vec::new())
+        .is_err());
+}
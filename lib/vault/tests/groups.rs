@@ -0,0 +1,177 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, show, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{SharedBackend, SignaturePolicy, SigningMode};
+use std::fs;
+
+#[test]
+fn it_reencrypts_exactly_the_partitions_referring_to_a_group_when_its_members_change() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    vault.groups = Some(vec![("ops".to_owned(), vec![BOB.to_owned()])].into_iter().collect());
+    let vault = vault.share_groups();
+    fs::write(dir.join("p2/.gpg-id"), "@ops\n").unwrap();
+    add_resource(&vault, &dir, "p1/secret", "one");
+    add_resource(&vault, &dir, "p2/secret", "two");
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{}\ntwo", BOB)
+    );
+
+    vault
+        .clone()
+        .add_group_members("@ops", &[ALICE.to_owned()], SigningMode::None, None, &mut Vec::new())
+        .unwrap();
+    assert!(fs::read_to_string(dir.join("sy-vault.yml"))
+        .unwrap()
+        .contains(&format!("groups:\n  ops:\n    - {}\n    - {}\n", ALICE, BOB)));
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{},{}\ntwo", ALICE, BOB)
+    );
+    assert_eq!(fs::read_to_string(dir.join("p2/.gpg-id")).unwrap(), "@ops\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("p1/secret.gpg")).unwrap(),
+        format!("{}\none", ALICE)
+    );
+
+    let mut vault = vault;
+    vault.groups = Some(
+        vec![("ops".to_owned(), vec![ALICE.to_owned(), BOB.to_owned()])]
+            .into_iter()
+            .collect(),
+    );
+    let vault = vault.share_groups();
+    let err = vault
+        .remove_recipients(&[BOB.to_owned()], &["p2".to_owned()], &mut Vec::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("member of a group it refers to"));
+
+    vault
+        .remove_group_members("ops", &[BOB.to_owned()], &mut Vec::new())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{}\ntwo", ALICE)
+    );
+}
+
+#[test]
+fn it_signs_the_members_of_groups_along_with_the_recipients_file() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    vault.groups = Some(vec![("ops".to_owned(), vec![BOB.to_owned()])].into_iter().collect());
+    vault.partitions[0].signatures = Some(SignaturePolicy::Require);
+    let vault = vault
+        .share_groups()
+        .set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    fs::write(dir.join("p2/.gpg-id"), "@ops\n").unwrap();
+    vault.sign_recipients(&["p2".to_owned()], &mut Vec::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/.gpg-id.sig")).unwrap(),
+        format!("{}\n{}\n", BOB, BOB)
+    );
+    add_resource(&vault, &dir, "p2/secret", "two");
+
+    let mut tampered = vault.clone();
+    tampered.groups = Some(
+        vec![("ops".to_owned(), vec![ALICE.to_owned(), BOB.to_owned()])]
+            .into_iter()
+            .collect(),
+    );
+    let tampered = tampered.share_groups();
+    let err = show(&tampered, "p2/secret").unwrap_err();
+    assert!(err.to_string().contains("was changed without a valid signature"));
+
+    vault
+        .clone()
+        .add_group_members("ops", &[ALICE.to_owned()], SigningMode::None, None, &mut Vec::new())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/.gpg-id.sig")).unwrap(),
+        format!("{}\n{}\n{}\n", BOB, ALICE, BOB)
+    );
+    assert_eq!(show(&tampered, "p2/secret").unwrap(), "two");
+}
+
+#[test]
+fn it_changes_groups_all_at_once_and_removes_key_files_no_longer_needed() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    vault.gpg_keys = Some("keys".into());
+    vault.groups = Some(
+        vec![("ops".to_owned(), vec![ALICE.to_owned(), BOB.to_owned()])]
+            .into_iter()
+            .collect(),
+    );
+    let vault = vault.share_groups();
+    vault.serialize().unwrap();
+    fs::create_dir(dir.join("keys")).unwrap();
+    fs::write(dir.join("keys").join(ALICE), ALICE).unwrap();
+    fs::write(dir.join("keys").join(BOB), BOB).unwrap();
+    fs::write(dir.join("p2/.gpg-id"), "@ops\n").unwrap();
+    add_resource(&vault, &dir, "p2/secret", "two");
+    let config = fs::read_to_string(dir.join("sy-vault.yml")).unwrap();
+
+    fs::write(dir.join("p2/broken.gpg"), "not encrypted for anyone").unwrap();
+    assert!(vault
+        .clone()
+        .remove_group_members("ops", &[BOB.to_owned()], &mut Vec::new())
+        .is_err());
+    assert_eq!(fs::read_to_string(dir.join("sy-vault.yml")).unwrap(), config);
+    assert!(dir.join("keys").join(BOB).is_file());
+
+    fs::remove_file(dir.join("p2/broken.gpg")).unwrap();
+    vault
+        .remove_group_members("ops", &[BOB.to_owned()], &mut Vec::new())
+        .unwrap();
+    assert!(!fs::read_to_string(dir.join("sy-vault.yml")).unwrap().contains(BOB));
+    assert!(!dir.join("keys").join(BOB).exists());
+    assert!(dir.join("keys").join(ALICE).is_file());
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{}\ntwo", ALICE)
+    );
+}
+
+#[test]
+fn it_resumes_an_interrupted_group_change_with_the_changed_groups() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    vault.groups = Some(vec![("ops".to_owned(), vec![BOB.to_owned()])].into_iter().collect());
+    let vault = vault.share_groups();
+    vault.serialize().unwrap();
+    fs::write(dir.join("p2/.gpg-id"), "@ops\n").unwrap();
+    add_resource(&vault, &dir, "p2/secret", "two");
+
+    let mut changed = vault.clone();
+    changed.groups = Some(
+        vec![("ops".to_owned(), vec![ALICE.to_owned(), BOB.to_owned()])]
+            .into_iter()
+            .collect(),
+    );
+    let changed = changed.share_groups();
+    let mut transaction = changed.transaction().unwrap();
+    changed.stage_serialization(&mut transaction).unwrap();
+    transaction.record_reencryption(1).unwrap();
+    std::mem::forget(transaction);
+
+    vault.resume(false, &mut Vec::new()).unwrap();
+    assert!(fs::read_to_string(dir.join("sy-vault.yml")).unwrap().contains(ALICE));
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{},{}\ntwo", ALICE, BOB)
+    );
+}
@@ -0,0 +1,96 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, show, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{SharedBackend, SignaturePolicy, SigningMode};
+use std::fs;
+
+#[test]
+fn it_reencrypts_partitions_inheriting_the_recipients_of_the_leader_when_they_change() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.partitions[0].inherit_recipients = Some(true);
+    let vault = vault.share_recipients();
+    add_resource(&vault, &dir, "p2/secret", "two");
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{},{}\ntwo", BOB, ALICE)
+    );
+
+    let err = vault
+        .remove_recipients(&[ALICE.to_owned()], &["p2".to_owned()], &mut Vec::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("as it inherits them from the recipients file"));
+
+    let mut output = Vec::new();
+    vault
+        .add_recipients(
+            &[BOB.to_owned()],
+            SigningMode::None,
+            None,
+            &["p1".to_owned()],
+            &mut output,
+        )
+        .unwrap();
+    assert!(String::from_utf8(output).unwrap().contains(&format!(
+        "Re-encrypted '{}' for new recipient(s)",
+        dir.join("p2/secret").display()
+    )));
+
+    vault
+        .remove_recipients(&[ALICE.to_owned()], &["p1".to_owned()], &mut Vec::new())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/.gpg-id")).unwrap(),
+        format!("{}\n", BOB)
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/secret.gpg")).unwrap(),
+        format!("{}\ntwo", BOB)
+    );
+}
+
+#[test]
+fn it_signs_the_inherited_recipients_along_with_the_recipients_file() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = partitioned_vault_at(&dir);
+    vault.partitions[0].inherit_recipients = Some(true);
+    vault.partitions[0].signatures = Some(SignaturePolicy::Require);
+    let vault = vault
+        .share_recipients()
+        .set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    vault.sign_recipients(&["p2".to_owned()], &mut Vec::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/.gpg-id.sig")).unwrap(),
+        format!("{}\n{}\n{}\n", BOB, ALICE, BOB)
+    );
+    add_resource(&vault, &dir, "p2/secret", "two");
+
+    fs::write(dir.join("p1/.gpg-id"), format!("{}\n", BOB)).unwrap();
+    let err = show(&vault, "p2/secret").unwrap_err();
+    assert!(err.to_string().contains("was changed without a valid signature"));
+    fs::write(dir.join("p1/.gpg-id"), format!("{}\n", ALICE)).unwrap();
+
+    vault
+        .add_recipients(
+            &[BOB.to_owned()],
+            SigningMode::None,
+            None,
+            &["p1".to_owned()],
+            &mut Vec::new(),
+        )
+        .unwrap();
+    vault
+        .remove_recipients(&[ALICE.to_owned()], &["p1".to_owned()], &mut Vec::new())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/.gpg-id.sig")).unwrap(),
+        format!("{}\n{}\n", BOB, BOB)
+    );
+    assert_eq!(show(&vault, "p2/secret").unwrap(), "two");
+}
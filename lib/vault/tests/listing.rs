@@ -0,0 +1,30 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at};
+use mktemp::Temp;
+use std::path::Path;
+
+#[test]
+fn it_lists_the_resources_of_all_partitions() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    add_resource(&vault, &dir, "p1/sub/a", "a");
+    add_resource(&vault, &dir, "p2/b", "b");
+
+    let partitions = vault.list_resources().unwrap();
+    assert_eq!(
+        partitions
+            .iter()
+            .map(|p| (p.index, p.secrets.clone(), p.resources.clone().unwrap()))
+            .collect::<Vec<_>>(),
+        vec![
+            (0, dir.join("p1"), vec![Path::new("sub/a").to_owned()]),
+            (1, dir.join("p2"), vec![Path::new("b").to_owned()])
+        ]
+    );
+    assert!(partitions.iter().all(|p| p.recipients.is_none()));
+}
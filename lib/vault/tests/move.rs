@@ -0,0 +1,67 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, partitioned_vault_at, show, BOB};
+use mktemp::Temp;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn it_moves_resources_within_and_across_partitions() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    add_resource(&vault, &dir, "p1/secret", "hello");
+    let ciphertext = fs::read(dir.join("p1/secret.gpg")).unwrap();
+
+    vault
+        .move_resource(Path::new("p1/secret"), Path::new("p1/sub/renamed"), &mut Vec::new())
+        .unwrap();
+    assert!(!dir.join("p1/secret.gpg").exists());
+    assert_eq!(fs::read(dir.join("p1/sub/renamed.gpg")).unwrap(), ciphertext);
+
+    vault
+        .move_resource(Path::new("p1/sub/renamed.gpg"), Path::new("p2/moved"), &mut Vec::new())
+        .unwrap();
+    assert!(!dir.join("p1/sub/renamed.gpg").exists());
+    assert_eq!(
+        fs::read_to_string(dir.join("p2/moved.gpg")).unwrap(),
+        format!("{}\nhello", BOB)
+    );
+
+    add_resource(&vault, &dir, "p1/other", "other");
+    assert!(vault
+        .move_resource(Path::new("p1/other"), Path::new("p2/moved"), &mut Vec::new())
+        .is_err());
+    assert_eq!(show(&vault, "p2/moved").unwrap(), "hello");
+}
+
+#[test]
+fn it_refuses_to_move_files_which_are_not_encrypted() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    fs::write(dir.join("p1/notes"), "plaintext").unwrap();
+
+    assert!(vault
+        .move_resource(Path::new("p1/notes"), Path::new("p1/notes-moved"), &mut Vec::new())
+        .is_err());
+    assert!(dir.join("p1/notes").is_file());
+    assert!(!dir.join("p1/notes-moved.gpg").exists());
+}
+
+#[test]
+fn it_does_not_create_directories_if_moving_fails() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = partitioned_vault_at(&dir);
+    fs::write(dir.join("p1/broken.gpg"), "not encrypted for anyone").unwrap();
+
+    assert!(vault
+        .move_resource(Path::new("p1/broken"), Path::new("p2/new/dir/broken"), &mut Vec::new())
+        .is_err());
+    assert!(dir.join("p1/broken.gpg").is_file());
+    assert!(!dir.join("p2/new").exists());
+}
@@ -0,0 +1,46 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::partitioned_vault_at;
+use mktemp::Temp;
+use sheesy_vault::{TrustModel, Vault, VaultExt, WriteMode};
+
+#[test]
+fn it_updates_the_settings_of_a_partition_but_refuses_duplicate_names() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let config = dir.join("sy-vault.yml");
+    partitioned_vault_at(&dir)
+        .to_file(&config, WriteMode::RefuseOverwrite)
+        .unwrap();
+
+    let mut vault = Vault::from_file(&config).unwrap().select("0").unwrap();
+    vault
+        .update_partition(
+            "p2",
+            Some("ops"),
+            Some(TrustModel::Always),
+            Some(false),
+            &mut Vec::new(),
+        )
+        .unwrap();
+    let vault = Vault::from_file(&config).unwrap().select("0").unwrap();
+    let partition = &vault.partitions[0];
+    assert_eq!(partition.name.as_deref(), Some("ops"));
+    assert_eq!(partition.trust_model, Some(TrustModel::Always));
+    assert_eq!(partition.auto_import, Some(false));
+    assert_eq!(vault.name, None);
+
+    let mut vault = vault;
+    vault
+        .update_partition("0", Some("dev"), None, None, &mut Vec::new())
+        .unwrap();
+    assert!(vault
+        .update_partition("ops", Some("dev"), None, None, &mut Vec::new())
+        .is_err());
+    let vault = Vault::from_file(&config).unwrap().select("0").unwrap();
+    assert_eq!(vault.name.as_deref(), Some("dev"));
+    assert_eq!(vault.partitions[0].name.as_deref(), Some("ops"));
+}
@@ -0,0 +1,129 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{AccessRule, KeyProblem, TrustModel, Validity, Vault};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[test]
+fn it_reports_recipients_whose_keys_cannot_be_encrypted_for_now_or_soon() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let mut backend = InMemory::with_secret_key_of(ALICE);
+    backend.keys[0].trust = Validity::Ultimate;
+    backend.keys[1].expires = Some(now + 3 * 24 * 60 * 60 + 60);
+    let mut vault = vault_at(&dir, &[ALICE, BOB], backend);
+
+    let problems_of = |vault: &Vault, days: u64, fingerprint: &str| {
+        vault.check_recipients(days, &mut Vec::new()).unwrap()[0]
+            .recipients
+            .iter()
+            .find(|recipient| recipient.key.fingerprint == fingerprint)
+            .map(|recipient| recipient.problems.clone())
+            .unwrap()
+    };
+    assert_eq!(problems_of(&vault, 30, ALICE), vec![]);
+    assert_eq!(
+        problems_of(&vault, 30, BOB),
+        vec![
+            KeyProblem::ExpiresSoon { days: 3 },
+            KeyProblem::Untrusted {
+                trust: Validity::Unknown
+            }
+        ]
+    );
+
+    let mut output = Vec::new();
+    let err = vault
+        .print_recipients_check(30, &mut output, &mut Vec::new())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Found 2 problem(s) while checking the keys of 2 recipient(s)."
+    );
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains(&format!("{} (user B): the key expires in 3 day(s)", BOB)));
+
+    vault.trust_model = Some(TrustModel::Always);
+    assert!(vault
+        .print_recipients_check(2, &mut Vec::new(), &mut Vec::new())
+        .is_ok());
+}
+
+#[test]
+fn it_reports_missing_keys_and_checks_the_recipients_of_access_rules_too() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.trust_model = Some(TrustModel::Always);
+    vault.rules = Some(vec![AccessRule {
+        pattern: "shared/**".into(),
+        recipients: None,
+        extra_recipients: vec![BOB.to_owned()],
+    }]);
+    let missing = "0123456789ABCDEF0123456789ABCDEF01234567";
+    fs::write(dir.join(".gpg-id"), format!("{}\n{}\n", ALICE, missing)).unwrap();
+
+    let checks = vault.check_recipients(30, &mut Vec::new()).unwrap();
+    let checked: Vec<_> = checks[0]
+        .recipients
+        .iter()
+        .map(|recipient| (recipient.key.fingerprint.as_str(), recipient.problems.len()))
+        .collect();
+    assert_eq!(checked, vec![(ALICE, 0), (missing, 1), (BOB, 0)]);
+    match checks[0].recipients[1].problems[0] {
+        KeyProblem::Unavailable { .. } => {}
+        ref problem => panic!("unexpected problem: {:?}", problem),
+    }
+
+    let mut output = Vec::new();
+    let err = vault
+        .print_recipients_check(30, &mut output, &mut Vec::new())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Found 1 problem(s) while checking the keys of 3 recipient(s)."
+    );
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains(&format!("{}: the key could not be obtained", missing)));
+}
+
+#[test]
+fn it_refreshes_outdated_public_keys_of_all_recipients_and_reencrypts_if_asked_to() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE, BOB], InMemory::with_secret_key_of(ALICE));
+    vault.gpg_keys = Some(dir.join("keys"));
+    fs::create_dir(dir.join("keys")).unwrap();
+    fs::write(dir.join("keys").join(ALICE), ALICE).unwrap();
+    fs::write(dir.join("keys").join(BOB), "outdated").unwrap();
+    add_resource(&vault, &dir, "secret", "content");
+
+    let mut output = Vec::new();
+    vault.refresh_recipients(false, &mut output, &mut Vec::new()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(&format!("Refreshed public key for user B ({})", BOB)));
+    assert!(!output.contains(&format!("user A ({})", ALICE)));
+    assert!(output.ends_with("1 of 2 exported public key(s) changed\n"));
+    assert_eq!(fs::read_to_string(dir.join("keys").join(BOB)).unwrap(), BOB);
+
+    let mut output = Vec::new();
+    vault.refresh_recipients(true, &mut output, &mut Vec::new()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("0 of 2 exported public key(s) changed\n"));
+    assert!(output.contains("Re-encrypted 1 resource(s) in 1 partition(s)"));
+
+    fs::write(dir.join("keys").join(BOB), "outdated").unwrap();
+    fs::write(dir.join("broken.gpg"), "not encrypted for anyone").unwrap();
+    assert!(vault
+        .refresh_recipients(true, &mut Vec::new(), &mut Vec::new())
+        .is_err());
+    assert_eq!(fs::read_to_string(dir.join("keys").join(BOB)).unwrap(), "outdated");
+}
@@ -0,0 +1,64 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::SharedBackend;
+use std::fs;
+
+#[test]
+fn it_reencrypts_all_resources_after_the_recipients_file_was_edited() {
+    let dir = Temp::new_dir().unwrap();
+    let vault = vault_at(&dir.to_path_buf(), &[ALICE], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir.to_path_buf(), "secret", "hello");
+    add_resource(&vault, &dir.to_path_buf(), "sub/other", "world");
+    vault
+        .write_recipients_list(&mut vec![ALICE.to_owned(), BOB.to_owned()])
+        .unwrap();
+
+    let mut output = Vec::new();
+    vault.reencrypt_partitions(&[], &mut output).unwrap();
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .ends_with("Re-encrypted 2 resource(s) in 1 partition(s)\n"));
+
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+    assert_eq!(show(&vault, "sub/other").unwrap(), "world");
+}
+
+#[test]
+fn it_reencrypts_in_parallel_while_reporting_resources_and_failures_in_order() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE)).set_jobs(Some(4));
+    let names: Vec<_> = (0..20).map(|i| format!("r{:02}", i)).collect();
+    for name in &names {
+        add_resource(&vault, &dir, name, name);
+    }
+
+    let mut output = Vec::new();
+    vault.reencrypt_partitions(&[], &mut output).unwrap();
+    let expected: Vec<_> = names
+        .iter()
+        .map(|n| format!("Re-encrypted '{}' for new recipient(s)", n))
+        .collect();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.lines().take(names.len()).collect::<Vec<_>>(), expected);
+
+    vault.write_recipients_list(&mut vec![BOB.to_owned()]).unwrap();
+    for name in &["r05", "r12"] {
+        fs::remove_file(dir.join(format!("{}.gpg", name))).unwrap();
+        add_resource(&vault, &dir, name, name);
+    }
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    let mut output = Vec::new();
+    let err = vault.reencrypt_partitions(&[], &mut output).unwrap_err();
+    assert_eq!(
+        format!("{}", err),
+        "Could not decrypt 'r05.gpg' to re-encrypt for new recipients."
+    );
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 5);
+}
@@ -0,0 +1,107 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{AccessRule, SignaturePolicy};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn it_encrypts_resources_for_the_recipients_given_by_access_rules_and_reports_contradictions() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE, BOB], InMemory::with_secret_key_of(ALICE));
+    vault.rules = Some(vec![
+        AccessRule {
+            pattern: "db/prod/**".into(),
+            recipients: Some(vec![ALICE.to_owned()]),
+            extra_recipients: Vec::new(),
+        },
+        AccessRule {
+            pattern: "db/prod/shared".into(),
+            recipients: None,
+            extra_recipients: vec![BOB.to_owned()],
+        },
+    ]);
+    add_resource(&vault, &dir, "public", "one");
+    add_resource(&vault, &dir, "db/prod/password", "two");
+    add_resource(&vault, &dir, "db/prod/shared", "three");
+    let encrypted_for = |name: &str| {
+        let content = fs::read_to_string(dir.join(name)).unwrap();
+        content.lines().next().unwrap().to_owned()
+    };
+    assert_eq!(encrypted_for("public.gpg"), format!("{},{}", ALICE, BOB));
+    assert_eq!(encrypted_for("db/prod/password.gpg"), ALICE);
+    assert_eq!(encrypted_for("db/prod/shared.gpg"), format!("{},{}", ALICE, BOB));
+
+    vault
+        .move_resource(Path::new("public"), Path::new("db/prod/public"), &mut Vec::new())
+        .unwrap();
+    assert_eq!(encrypted_for("db/prod/public.gpg"), ALICE);
+
+    let mut output = Vec::new();
+    vault.fsck(&mut output, &mut Vec::new()).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "All 3 resource(s) are encrypted for exactly their recipients.\n"
+    );
+
+    vault.write_recipients_list(&mut vec![BOB.to_owned()]).unwrap();
+    let mut output = Vec::new();
+    vault.fsck(&mut output, &mut Vec::new()).unwrap_err();
+    assert!(String::from_utf8(output).unwrap().starts_with(&format!(
+        "{}: the access rule for 'db/prod/**' grants access to {} (user A), who is not a recipient of the partition",
+        dir.join(".gpg-id").display(),
+        ALICE
+    )));
+}
+
+#[test]
+fn it_signs_the_access_rules_along_with_the_recipients_file() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    vault.signatures = Some(SignaturePolicy::Require);
+    vault.groups = Some(
+        vec![("auditors".to_owned(), vec![BOB.to_owned()])]
+            .into_iter()
+            .collect(),
+    );
+    vault.rules = Some(vec![AccessRule {
+        pattern: "audit/*".into(),
+        recipients: None,
+        extra_recipients: vec!["@auditors".to_owned()],
+    }]);
+    let vault = vault.share_groups();
+    vault.sign_recipients(&[], &mut Vec::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join(".gpg-id.sig")).unwrap(),
+        format!("{}\n{}\nrule audit/*\nextra_recipients {}\n", ALICE, ALICE, BOB)
+    );
+    add_resource(&vault, &dir, "audit/log", "one");
+    let encrypted_for = |name: &str| {
+        let content = fs::read_to_string(dir.join(name)).unwrap();
+        let header = content.lines().next().unwrap();
+        header.split('|').next().unwrap().to_owned()
+    };
+    assert_eq!(encrypted_for("audit/log.gpg"), format!("{},{}", ALICE, BOB));
+
+    let mut tampered = vault.clone();
+    tampered.rules.as_mut().unwrap()[0].pattern = "**".into();
+    let err = show(&tampered, "audit/log").unwrap_err();
+    assert!(err.to_string().contains("was changed without a valid signature"));
+
+    vault
+        .clone()
+        .remove_group_members("auditors", &[BOB.to_owned()], &mut Vec::new())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join(".gpg-id.sig")).unwrap(),
+        format!("{}\n{}\nrule audit/*\nextra_recipients\n", ALICE, ALICE)
+    );
+    assert_eq!(encrypted_for("audit/log.gpg"), ALICE);
+}
@@ -0,0 +1,296 @@
+#![cfg(feature = "sequoia")]
+extern crate failure;
+extern crate mktemp;
+extern crate sequoia_openpgp;
+extern crate sheesy_vault;
+
+use failure::Error;
+use mktemp::Temp;
+use sequoia_openpgp::armor;
+use sequoia_openpgp::cert::CertBuilder;
+use sequoia_openpgp::serialize::Serialize;
+use sequoia_openpgp::Cert;
+use sheesy_vault::error::{first_cause_of_type, DecryptionError, EncryptionError};
+use sheesy_vault::{
+    Backend, Destination, SequoiaBackend, SharedBackend, SignaturePolicy, SpecSourceType, TrustModel, Validity, Vault,
+    VaultSpec, WriteMode,
+};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn generate(user_id: &str) -> Cert {
+    CertBuilder::general_purpose(Some(user_id)).generate().unwrap().0
+}
+
+/// Writes the secret key of `cert` into its own keyring, and its public key into the `keys` directory.
+fn keyring_for(dir: &Path, cert: &Cert) -> PathBuf {
+    let fingerprint = cert.fingerprint().to_hex();
+    let mut buf = Vec::new();
+    cert.as_tsk().serialize(&mut buf).unwrap();
+    let keyring = dir.join(format!("{}.pgp", fingerprint));
+    fs::write(&keyring, buf).unwrap();
+
+    let mut buf = Vec::new();
+    cert.armored().serialize(&mut buf).unwrap();
+    fs::create_dir_all(dir.join("keys")).unwrap();
+    fs::write(dir.join("keys").join(&fingerprint), buf).unwrap();
+    keyring
+}
+
+fn vault_at(dir: &Path, recipients: &[&Cert], trust_model: TrustModel, keyring: &Path) -> Vault {
+    let vault = Vault {
+        secrets: dir.join("secrets"),
+        recipients: dir.join(".gpg-id"),
+        trust_model: Some(trust_model),
+        verified_recipients: keyring.with_extension("verified.yml"),
+        ..Default::default()
+    }
+    .set_backend(SharedBackend::new(SequoiaBackend::new(keyring, vec![dir.join("keys")])));
+    vault
+        .write_recipients_list(&mut recipients.iter().map(|c| c.fingerprint().to_hex()).collect())
+        .unwrap();
+    vault
+}
+
+fn add_resource(vault: &Vault, dir: &Path, name: &str, content: &str) -> Result<(), Error> {
+    let source = dir.join(format!("{}.plain", name));
+    fs::write(&source, content).unwrap();
+    vault.encrypt(
+        &[VaultSpec {
+            src: SpecSourceType::Path(source),
+            dst: name.into(),
+        }],
+        WriteMode::RefuseOverwrite,
+        Destination::ReolveAndAppendGpg,
+        &mut Vec::new(),
+    )
+}
+
+fn show(vault: &Vault, name: &str) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    vault.decrypt(Path::new(name), &mut buf, &mut Vec::new())?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[test]
+fn it_encrypts_for_all_recipients_using_keys_from_the_keys_directory() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    let bob_keyring = keyring_for(&dir, &bob);
+
+    let vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &alice_keyring);
+    add_resource(&vault, &dir, "secret", "hello").unwrap();
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+
+    let vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &bob_keyring);
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+}
+
+#[test]
+fn it_verifies_that_resources_are_signed_by_a_current_recipient() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    let bob_keyring = keyring_for(&dir, &bob);
+
+    let mut vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &bob_keyring);
+    add_resource(&vault, &dir, "unsigned", "hello").unwrap();
+    vault.signatures = Some(SignaturePolicy::Require);
+    vault
+        .write_recipients_list(&mut vec![alice.fingerprint().to_hex(), bob.fingerprint().to_hex()])
+        .unwrap();
+    add_resource(&vault, &dir, "signed", "hello").unwrap();
+
+    let mut vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &alice_keyring);
+    vault.signatures = Some(SignaturePolicy::Require);
+    let err = show(&vault, "signed").unwrap_err();
+    assert!(err.to_string().contains("neither yours nor certified by you"));
+
+    let backend = vault.backend();
+    backend
+        .sign_key(
+            &backend.key("bob@example.com").unwrap(),
+            &backend.key("alice@example.com").unwrap(),
+        )
+        .unwrap();
+    assert_eq!(show(&vault, "signed").unwrap(), "hello");
+    let err = show(&vault, "unsigned").unwrap_err();
+    assert!(err.to_string().contains("has no valid signature of a known key"));
+
+    vault
+        .write_recipients_list(&mut vec![alice.fingerprint().to_hex()])
+        .unwrap();
+    let err = show(&vault, "signed").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(&format!("was signed by {}", bob.fingerprint().to_hex())));
+}
+
+#[test]
+fn it_reports_a_decryption_error_if_the_content_was_not_encrypted_for_us() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    let bob_keyring = keyring_for(&dir, &bob);
+
+    let vault = vault_at(&dir, &[&alice], TrustModel::Always, &alice_keyring);
+    add_resource(&vault, &dir, "secret", "hello").unwrap();
+
+    let vault = vault_at(&dir, &[&alice], TrustModel::Always, &bob_keyring);
+    let err = show(&vault, "secret").unwrap_err();
+    assert!(first_cause_of_type::<DecryptionError>(&err).is_some());
+}
+
+#[test]
+fn it_requires_recipients_to_be_certified_in_the_web_of_trust() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    keyring_for(&dir, &bob);
+
+    let vault = vault_at(&dir, &[&alice, &bob], TrustModel::GpgWebOfTrust, &alice_keyring);
+    let err = add_resource(&vault, &dir, "secret", "hello").unwrap_err();
+    let err = first_cause_of_type::<EncryptionError>(&err).expect("encryption error");
+    assert_eq!(err.offending_recipients.len(), 1);
+
+    let backend = vault.backend();
+    backend
+        .sign_key(
+            &backend.key("bob@example.com").unwrap(),
+            &backend.key("alice@example.com").unwrap(),
+        )
+        .unwrap();
+    add_resource(&vault, &dir, "secret", "hello").unwrap();
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+}
+
+#[test]
+fn it_reads_the_recipients_of_resources_without_decrypting_them() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    keyring_for(&dir, &bob);
+
+    let vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &alice_keyring);
+    add_resource(&vault, &dir, "secret", "hello").unwrap();
+    vault.fsck(&mut Vec::new(), &mut Vec::new()).unwrap();
+
+    let vault = vault_at(&dir, &[&alice], TrustModel::Always, &alice_keyring);
+    let mut output = Vec::new();
+    assert!(vault.fsck(&mut output, &mut Vec::new()).is_err());
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "secret.gpg: encrypted for {} (bob@example.com), who is not a recipient\n",
+            bob.fingerprint().to_hex()
+        )
+    );
+}
+
+#[test]
+fn it_lists_recipients_along_with_their_expiry_and_trust() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, bob) = (generate("alice@example.com"), generate("bob@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    keyring_for(&dir, &bob);
+    let vault = vault_at(&dir, &[&alice, &bob], TrustModel::Always, &alice_keyring);
+
+    let partitions = vault.list_recipients(&mut Vec::new()).unwrap();
+    assert_eq!(partitions.len(), 1);
+    let recipients = partitions[0].recipients.as_ref().unwrap();
+    let key_of = |cert: &Cert| {
+        recipients
+            .iter()
+            .find(|k| k.fingerprint == cert.fingerprint().to_hex())
+            .unwrap()
+    };
+    assert_eq!(key_of(&alice).trust, Validity::Ultimate);
+    assert_eq!(key_of(&bob).trust, Validity::Unknown);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!(key_of(&bob).expires.unwrap() > now);
+}
+
+#[test]
+fn it_refuses_to_pick_one_of_several_keys_matching_an_id() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, mallory) = (generate("alice@example.com"), generate("alice@example.com.evil"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    keyring_for(&dir, &mallory);
+    let vault = vault_at(&dir, &[&alice], TrustModel::Always, &alice_keyring);
+    let backend = vault.backend();
+
+    let err = backend.key("alice@example.com").unwrap_err();
+    assert!(format!("{}", err).starts_with("The id 'alice@example.com' is ambiguous"));
+    assert_eq!(
+        backend.key(&alice.fingerprint().to_hex()).unwrap().fingerprint,
+        alice.fingerprint().to_hex()
+    );
+}
+
+#[test]
+fn it_never_treats_secret_keys_from_key_directories_as_our_own() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let (alice, mallory) = (generate("alice@example.com"), generate("mallory@example.com"));
+    let alice_keyring = keyring_for(&dir, &alice);
+    let mut buf = Vec::new();
+    mallory.as_tsk().serialize(&mut buf).unwrap();
+    fs::write(dir.join("keys").join(mallory.fingerprint().to_hex()), &buf).unwrap();
+    let vault = vault_at(&dir, &[&alice, &mallory], TrustModel::Always, &alice_keyring);
+    let backend = vault.backend();
+
+    let own_keys = |backend: &dyn Backend| -> Vec<String> {
+        backend
+            .find_secret_keys(&[])
+            .unwrap()
+            .into_iter()
+            .map(|k| k.fingerprint)
+            .collect()
+    };
+    assert_eq!(own_keys(backend), vec![alice.fingerprint().to_hex()]);
+    assert_eq!(
+        backend.key(&mallory.fingerprint().to_hex()).unwrap().trust,
+        Validity::Unknown
+    );
+
+    backend.import_keys(&buf).unwrap();
+    assert_eq!(own_keys(backend), vec![alice.fingerprint().to_hex()]);
+    assert!(!dir.join(format!("{}.pgp.tmp", alice.fingerprint().to_hex())).exists());
+}
+
+#[test]
+fn it_reads_the_recipients_of_armored_resources_and_reports_those_it_cannot_read() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let alice = generate("alice@example.com");
+    let alice_keyring = keyring_for(&dir, &alice);
+    let vault = vault_at(&dir, &[&alice], TrustModel::Always, &alice_keyring);
+    add_resource(&vault, &dir, "secret", "hello").unwrap();
+
+    let mut writer = armor::Writer::new(Vec::new(), armor::Kind::Message).unwrap();
+    writer
+        .write_all(&fs::read(dir.join("secrets").join("secret.gpg")).unwrap())
+        .unwrap();
+    fs::write(dir.join("secrets").join("armored.gpg"), writer.finalize().unwrap()).unwrap();
+    // A version 6 public-key encrypted session key packet, which is not supported yet.
+    fs::write(dir.join("secrets").join("future.gpg"), [0xc1, 0x02, 0x06, 0x00]).unwrap();
+
+    let mut output = Vec::new();
+    let err = vault.fsck(&mut output, &mut Vec::new()).unwrap_err();
+    assert_eq!(format!("{}", err), "Found 1 problem(s) while checking 3 resource(s).");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "future.gpg: could not determine its recipients: \
+         Unsupported public-key encrypted session key packet version 6\n"
+    );
+}
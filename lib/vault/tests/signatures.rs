@@ -0,0 +1,182 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{SignaturePolicy, Vault};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn it_signs_resources_and_verifies_their_signer_is_a_recipient_if_configured_to_do_so() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE, BOB], InMemory::with_secret_key_of(ALICE));
+    vault.signatures = Some(SignaturePolicy::Require);
+    vault
+        .write_recipients_list(&mut vec![ALICE.to_owned(), BOB.to_owned()])
+        .unwrap();
+    add_resource(&vault, &dir, "signed", "by alice");
+    assert_eq!(
+        fs::read_to_string(dir.join("signed.gpg")).unwrap(),
+        format!("{},{}|{}\nby alice", ALICE, BOB, ALICE)
+    );
+    assert_eq!(show(&vault, "signed").unwrap(), "by alice");
+
+    fs::write(dir.join("forged.gpg"), format!("{}\nplanted", ALICE)).unwrap();
+    let err = show(&vault, "forged").unwrap_err();
+    assert!(err.to_string().contains("has no valid signature of a known key"));
+
+    vault.signatures = Some(SignaturePolicy::Warn);
+    let mut warnings = Vec::new();
+    let mut buf = Vec::new();
+    vault.decrypt(Path::new("forged"), &mut buf, &mut warnings).unwrap();
+    assert_eq!(buf, b"planted");
+    assert!(String::from_utf8(warnings)
+        .unwrap()
+        .starts_with("Warning: The resource at"));
+
+    vault.signatures = Some(SignaturePolicy::Require);
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    fs::write(dir.join("forged.gpg"), format!("{}|{}\nplanted", ALICE, BOB)).unwrap();
+    let err = show(&vault, "forged").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "The resource at '{}' was signed by {}, which is not a recipient of its partition.",
+            dir.join("forged.gpg").display(),
+            BOB
+        )
+    );
+
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(BOB));
+    vault.signatures = Some(SignaturePolicy::Warn);
+    let err = vault.encrypt_buffer(b"", None, &mut Vec::new()).unwrap_err();
+    assert!(err.to_string().contains("no key to sign them with could be found"));
+}
+
+#[test]
+fn it_signs_the_recipients_file_and_verifies_it_was_signed_by_a_previously_verified_recipient() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.signatures = Some(SignaturePolicy::Require);
+    let recipient_keys = |vault: &Vault, output: &mut Vec<u8>| vault.recipient_keys(vault.backend(), None, output);
+    let err = recipient_keys(&vault, &mut Vec::new()).unwrap_err();
+    assert!(err.to_string().contains("is not signed"));
+
+    vault
+        .write_recipients_list(&mut vec![BOB.to_owned(), ALICE.to_owned()])
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join(".gpg-id.sig")).unwrap(),
+        format!("{}\n{}\n{}\n", ALICE, ALICE, BOB)
+    );
+    assert_eq!(recipient_keys(&vault, &mut Vec::new()).unwrap().len(), 2);
+
+    let mut bobs_vault = vault_at(&dir, &[], InMemory::with_secret_key_of(BOB));
+    bobs_vault.signatures = Some(SignaturePolicy::Require);
+    fs::write(dir.join(".gpg-id"), format!("{}\n", ALICE)).unwrap();
+    let err = bobs_vault
+        .write_recipients_list(&mut vec![ALICE.to_owned(), BOB.to_owned()])
+        .unwrap_err();
+    assert!(err.to_string().contains("none of its current recipients is you"));
+
+    let err = recipient_keys(&vault, &mut Vec::new()).unwrap_err();
+    assert!(err.to_string().contains("was changed without a valid signature"));
+
+    let signed_by_bob = |recipients: &[&str]| {
+        let content: String = recipients.iter().map(|r| format!("{}\n", r)).collect();
+        fs::write(dir.join(".gpg-id"), &content).unwrap();
+        fs::write(dir.join(".gpg-id.sig"), format!("{}\n{}", BOB, content)).unwrap();
+    };
+    signed_by_bob(&[ALICE, BOB]);
+    assert_eq!(recipient_keys(&vault, &mut Vec::new()).unwrap().len(), 2);
+
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    assert_eq!(recipient_keys(&vault, &mut Vec::new()).unwrap().len(), 1);
+    signed_by_bob(&[ALICE, BOB]);
+    let err = recipient_keys(&vault, &mut Vec::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "The recipients file at '{}' was signed by {}, who was not one of its recipients when you last \
+             verified it.",
+            dir.join(".gpg-id").display(),
+            BOB
+        )
+    );
+
+    let mut new_vault = vault.clone();
+    new_vault.verified_recipients = dir.join(".never-verified.yml");
+    let err = recipient_keys(&new_vault, &mut Vec::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "The recipients file at '{}' was signed by {}, whose key is neither yours nor certified by you.",
+            dir.join(".gpg-id").display(),
+            BOB
+        )
+    );
+
+    new_vault.signatures = Some(SignaturePolicy::Warn);
+    let mut output = Vec::new();
+    assert_eq!(recipient_keys(&new_vault, &mut output).unwrap().len(), 2);
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .starts_with("Warning: The recipients file at"));
+
+    vault.signatures = Some(SignaturePolicy::Warn);
+    let err = recipient_keys(&vault, &mut Vec::new()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("who was not one of its recipients when you last verified it"));
+
+    vault.signatures = Some(SignaturePolicy::Require);
+    let mut output = Vec::new();
+    vault.sign_recipients(&[], &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!("Signed the recipients file at '{}'\n", dir.join(".gpg-id").display())
+    );
+    assert_eq!(recipient_keys(&vault, &mut Vec::new()).unwrap().len(), 2);
+}
+
+#[test]
+fn it_keeps_verifying_the_recipients_file_once_verified_even_if_signatures_are_disabled() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.signatures = Some(SignaturePolicy::Require);
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    add_resource(&vault, &dir, "secret", "for alice");
+
+    vault.signatures = None;
+    fs::remove_file(dir.join(".gpg-id.sig")).unwrap();
+    fs::write(dir.join(".gpg-id"), format!("{}\n{}\n", ALICE, BOB)).unwrap();
+
+    let err = vault
+        .recipient_keys(vault.backend(), None, &mut Vec::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("is not configured to use signatures"));
+    let err = show(&vault, "secret").unwrap_err();
+    assert!(err.to_string().contains("is not configured to use signatures"));
+
+    vault.signatures = Some(SignaturePolicy::Warn);
+    let err = vault
+        .recipient_keys(vault.backend(), None, &mut Vec::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("is not signed"));
+
+    fs::remove_file(dir.join(".verified-recipients.yml")).unwrap();
+    vault.signatures = None;
+    assert_eq!(
+        vault
+            .recipient_keys(vault.backend(), None, &mut Vec::new())
+            .unwrap()
+            .len(),
+        2
+    );
+}
@@ -0,0 +1,183 @@
+extern crate mktemp;
+extern crate sheesy_vault;
+
+mod common;
+
+use common::{add_resource, show, vault_at, InMemory, ALICE, BOB};
+use mktemp::Temp;
+use sheesy_vault::{SharedBackend, SigningMode, TrustModel};
+use std::fs;
+
+#[test]
+fn it_leaves_the_vault_untouched_if_reencryption_fails_for_one_resource() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let vault = vault_at(&dir, &[BOB], InMemory::with_secret_key_of(ALICE));
+    add_resource(&vault, &dir, "b-unreadable", "for bob");
+    vault.write_recipients_list(&mut vec![ALICE.to_owned()]).unwrap();
+    add_resource(&vault, &dir, "a-readable", "for alice");
+    let readable_before = fs::read(dir.join("a-readable.gpg")).unwrap();
+
+    assert!(vault
+        .add_recipients(&[BOB.to_owned()], SigningMode::None, None, &[], &mut Vec::new())
+        .is_err());
+
+    assert_eq!(vault.recipients_list().unwrap(), vec![ALICE.to_owned()]);
+    assert_eq!(fs::read(dir.join("a-readable.gpg")).unwrap(), readable_before);
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec![".gpg-id", "a-readable.gpg", "b-unreadable.gpg"]);
+}
+
+#[test]
+fn it_resumes_an_interrupted_recipient_change_from_its_journal() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    add_resource(&vault, &dir, "secret", "hello");
+
+    let mut transaction = vault.transaction().unwrap();
+    let mut recipients = vec![ALICE.to_owned(), BOB.to_owned()];
+    vault.stage_recipients_list(&mut recipients, &mut transaction).unwrap();
+    let keys = vault.backend().find_keys(&recipients).unwrap();
+    let keys = vault
+        .resource_keys_for(vault.backend(), keys, None, &mut Vec::new())
+        .unwrap();
+    vault
+        .reencrypt(
+            vault.backend(),
+            &keys,
+            &TrustModel::Always,
+            false,
+            &mut transaction,
+            &mut Vec::new(),
+        )
+        .unwrap();
+    std::mem::forget(transaction);
+
+    assert_eq!(vault.recipients_list().unwrap(), vec![ALICE.to_owned()]);
+    let err = vault
+        .add_recipients(&[BOB.to_owned()], SigningMode::None, None, &[], &mut Vec::new())
+        .unwrap_err();
+    assert!(format!("{}", err).starts_with("Found the journal of an interrupted change"));
+
+    vault.resume(false, &mut Vec::new()).unwrap();
+    assert!(!dir.join("sy-vault.yml.journal").exists());
+    assert_eq!(vault.recipients_list().unwrap(), recipients);
+    let vault = vault.set_backend(SharedBackend::new(InMemory::with_secret_key_of(BOB)));
+    assert_eq!(show(&vault, "secret").unwrap(), "hello");
+}
+
+#[test]
+fn it_discards_an_interrupted_recipient_change_when_aborting() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+
+    let mut transaction = vault.transaction().unwrap();
+    vault
+        .stage_recipients_list(&mut vec![BOB.to_owned()], &mut transaction)
+        .unwrap();
+    std::mem::forget(transaction);
+
+    vault.resume(true, &mut Vec::new()).unwrap();
+    assert!(!dir.join("sy-vault.yml.journal").exists());
+    assert!(!dir.join(".gpg-id.sy-staged").exists());
+    assert_eq!(vault.recipients_list().unwrap(), vec![ALICE.to_owned()]);
+}
+
+#[test]
+fn it_finishes_applying_changes_if_it_was_interrupted_while_doing_so() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    let (applied, unapplied) = (dir.join("applied.gpg"), dir.join("unapplied.gpg"));
+    fs::write(&applied, "new").unwrap();
+    fs::write(dir.join("applied.gpg.sy-backup"), "old").unwrap();
+    fs::write(dir.join("unapplied.gpg.sy-backup"), "old").unwrap();
+    fs::write(dir.join("unapplied.gpg.sy-staged"), "new").unwrap();
+    fs::write(
+        dir.join("sy-vault.yml.journal"),
+        format!(
+            "write {}\nwrite {}\ncommit\nincomplete line",
+            applied.display(),
+            unapplied.display()
+        ),
+    )
+    .unwrap();
+
+    vault.resume(true, &mut Vec::new()).unwrap_err();
+    vault.resume(false, &mut Vec::new()).unwrap();
+    assert_eq!(fs::read_to_string(&applied).unwrap(), "new");
+    assert_eq!(fs::read_to_string(&unapplied).unwrap(), "new");
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec![".gpg-id", "applied.gpg", "unapplied.gpg"]);
+}
+
+#[test]
+fn it_keeps_the_journal_if_finishing_to_apply_changes_fails_again() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+    let (first, second) = (dir.join("first.gpg"), dir.join("second.gpg"));
+    for path in &[&first, &second] {
+        fs::write(path, "old").unwrap();
+        fs::write(dir.join(format!("{}.sy-staged", path.display())), "new").unwrap();
+    }
+    let blocking_dir = dir.join("second.gpg.sy-backup");
+    fs::create_dir(&blocking_dir).unwrap();
+    fs::write(blocking_dir.join("file"), "").unwrap();
+    fs::write(
+        dir.join("sy-vault.yml.journal"),
+        format!("write {}\nwrite {}\ncommit\n", first.display(), second.display()),
+    )
+    .unwrap();
+
+    let err = vault.resume(false, &mut Vec::new()).unwrap_err();
+    assert!(format!("{}", err).starts_with("Failed to finish applying the changes of an interrupted transaction"));
+    assert!(dir.join("sy-vault.yml.journal").exists());
+    assert_eq!(fs::read_to_string(&first).unwrap(), "new");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "old");
+
+    fs::remove_dir_all(&blocking_dir).unwrap();
+    vault.resume(false, &mut Vec::new()).unwrap();
+    assert_eq!(fs::read_to_string(&first).unwrap(), "new");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "new");
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec![".gpg-id", "first.gpg", "second.gpg"]);
+}
+
+#[test]
+fn it_removes_the_directories_it_created_unless_committed() {
+    let dir = Temp::new_dir().unwrap();
+    let dir = dir.to_path_buf();
+    let mut vault = vault_at(&dir, &[ALICE], InMemory::with_secret_key_of(ALICE));
+    vault.vault_path = Some(dir.join("sy-vault.yml"));
+
+    let mut transaction = vault.transaction().unwrap();
+    transaction.stage_write(&dir.join("a/b/c.gpg"), b"data").unwrap();
+    assert!(dir.join("a/b").is_dir());
+    std::mem::forget(transaction);
+    vault.resume(true, &mut Vec::new()).unwrap();
+    assert!(!dir.join("a").exists());
+
+    let mut transaction = vault.transaction().unwrap();
+    transaction.stage_write(&dir.join("a/b/c.gpg"), b"data").unwrap();
+    transaction.commit().unwrap();
+    assert_eq!(fs::read(dir.join("a/b/c.gpg")).unwrap(), b"data");
+}
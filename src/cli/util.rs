@@ -1,4 +1,4 @@
-#[cfg(any(feature = "process", feature = "extract"))]
+#[cfg(any(feature = "process", feature = "extract", feature = "vault"))]
 pub fn output_formats() -> &'static [&'static str] {
     &["json", "yaml"]
 }
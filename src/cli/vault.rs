@@ -1,6 +1,7 @@
+use crate::cli::util::output_formats;
 use clap::AppSettings;
 use clap::ArgSettings;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgGroup};
 use std::env;
 
 fn mk_help(kind: &str, prefix: &str) -> String {
@@ -103,6 +104,20 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
                      need to sign a key prior to be able to encrypt for it.",
                 ),
         )
+        .arg(
+            Arg::with_name("encryption")
+                .long("encryption")
+                .required(false)
+                .takes_value(true)
+                .value_name("kind")
+                .possible_values(&["gpg", "age"])
+                .help(
+                    "The kind of encryption to use for all resources of the vault. If unset, it defaults to 'gpg'. \
+                     'gpg': resources are encrypted using GnuPG, and recipients are identified by their key fingerprint. \
+                     'age': resources are encrypted using age, and recipients are age public keys (age1...). \
+                     Your own age identities are read from the file at $SY_AGE_KEY_FILE, or ~/.config/age/keys.txt.",
+                ),
+        )
         .arg(
             Arg::with_name("secrets-dir")
                 .long("secrets-dir")
@@ -130,7 +145,23 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
         )
         .arg(optional_gpg_key_id(gpg_key_id.clone()));
 
-    let list = App::new("list").alias("ls").about("List the vault's content.");
+    let output = Arg::with_name("output")
+        .set(ArgSettings::RequireEquals)
+        .short("o")
+        .long("output")
+        .takes_value(true)
+        .required(false)
+        .value_name("mode")
+        .possible_values(output_formats())
+        .case_insensitive(true)
+        .help(
+            "If set, the listing will be serialized in the given format to be read by programs. \
+             Otherwise it is printed in a human-readable form.",
+        );
+    let list = App::new("list")
+        .alias("ls")
+        .about("List the vault's content.")
+        .arg(output.clone());
     let resource_path = Arg::with_name("path")
         .required(true)
         .multiple(false)
@@ -192,6 +223,66 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
              If standard input is a TTY, it will open the editor as defined by the \
              EDITOR environment variable.",
         ));
+    let move_resource = App::new("move")
+        .alias("mv")
+        .about(
+            "Move a resource to another location in the vault. If it is moved to another partition, \
+             it will be re-encrypted for the recipients of that partition.",
+        )
+        .arg(
+            resource_path
+                .clone()
+                .multiple(false)
+                .help("The vault-relative path of the resource to move"),
+        )
+        .arg(
+            Arg::with_name("destination")
+                .required(true)
+                .takes_value(true)
+                .value_name("destination")
+                .help(
+                    "The vault-relative path the resource should be moved to. \
+                     It must not exist yet.",
+                ),
+        );
+    let copy_resource = App::new("copy")
+        .alias("cp")
+        .about(
+            "Copy resources to another location in the vault. Resources copied to another partition \
+             will be re-encrypted for the recipients of that partition.",
+        )
+        .arg(Arg::with_name("overwrite").long("overwrite").required(false).help(
+            "If set, existing resources at the destination will be overwritten. \
+             Otherwise the copy fails without changing anything.",
+        ))
+        .arg(resource_path.clone().multiple(true).help(
+            "The vault-relative path of a resource or a directory of resources to copy. \
+             The resources of a directory are copied into the destination directory, \
+             as well as all resources if there are multiple.",
+        ))
+        .arg(
+            Arg::with_name("destination")
+                .required(true)
+                .takes_value(true)
+                .value_name("destination")
+                .help("The vault-relative path of the copy, or of the directory to copy to."),
+        );
+    let grep = App::new("grep")
+        .about(
+            "Print all lines of resources matching a regular expression, prefixed with the path of their resource. \
+             Resources are decrypted in memory only, and those which were not encrypted for you are skipped.",
+        )
+        .arg(
+            Arg::with_name("pattern")
+                .required(true)
+                .takes_value(true)
+                .value_name("regex")
+                .help("The regular expression to match each line of a resource against."),
+        )
+        .arg(resource_path.clone().required(false).multiple(true).help(
+            "The vault-relative path of a resource or a directory of resources to search. \
+             If unset, all resources of all partitions are searched.",
+        ));
     let remove_resource = App::new("remove")
         .alias("delete")
         .about("Delete a resource from the vault.")
@@ -206,6 +297,18 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
          Afterwards someone able to decrypt the vault contents can re-encrypt the content for \
          you.",
     );
+    let group = Arg::with_name("group")
+        .set(ArgSettings::RequireEquals)
+        .long("group")
+        .short("g")
+        .required(false)
+        .takes_value(true)
+        .value_name("name")
+        .conflicts_with("partition")
+        .help(
+            "The group of recipients in the vault configuration to change instead of a recipients file. \
+             All partitions whose recipients file refers to it as '@<name>' will be re-encrypted.",
+        );
     let add_recipient = App::new("add")
         .alias("insert")
         .arg(
@@ -242,6 +345,7 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
              You have used `gpg --sign-key <recipient>` or have set the owner trust to ultimate so that you \
              can encrypt for the recipient.",
         ))
+        .arg(group.clone())
         .arg(gpg_key_id.clone().required(true))
         .about(
             "Add a new recipient. This will re-encrypt all the vaults content.\
@@ -281,10 +385,71 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
                      If unset, the recipient will be added to naturally selected vault, see the --select flag.",
                 ),
         )
+        .arg(group)
         .arg(gpg_key_id.clone().required(true));
     let list_recipient = App::new("list")
         .alias("ls")
-        .about("List the vaults recipients as identified by the recipients file.");
+        .about("List the vaults recipients as identified by the recipients file.")
+        .arg(output.clone());
+    let check_recipient = App::new("check")
+        .about(
+            "Check the keys of the recipients of all partitions and their access rules for anything that \
+             prevents encrypting for them, like being missing, expired, revoked or disabled, lacking a subkey for \
+             encryption, or not being trusted enough under the configured trust model. Keys expiring soon are \
+             reported as well. \
+             Fails if a problem was found.",
+        )
+        .arg(
+            Arg::with_name("days")
+                .set(ArgSettings::RequireEquals)
+                .long("days")
+                .short("d")
+                .required(false)
+                .takes_value(true)
+                .value_name("count")
+                .default_value("30")
+                .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|err| format!("{}", err)))
+                .help("Report keys which expire within the given amount of days."),
+        )
+        .arg(output.clone());
+    let refresh_recipient = App::new("refresh")
+        .about(
+            "Export the current public key of every recipient of every partition from your keyring into the \
+             vaults key directory, replacing outdated copies. This is useful after recipients extended the expiry \
+             of their key or added subkeys, and you imported their updated key.",
+        )
+        .arg(
+            Arg::with_name("reencrypt")
+                .long("reencrypt")
+                .short("r")
+                .required(false)
+                .help(
+                    "If set, all resources of all partitions will be re-encrypted as well. \
+                     The key files are only written if that succeeds.",
+                ),
+        );
+    let sign_recipient = App::new("sign")
+        .about(
+            "Sign the recipients of partitions configured to use signatures with your key, which must be one of \
+             their current recipients. This approves them as they are, which is needed after changing the \
+             recipients file, groups, access rules or inheritance of recipients by hand, or after enabling \
+             signatures for an existing partition.",
+        )
+        .arg(
+            Arg::with_name("partition")
+                .set(ArgSettings::RequireEquals)
+                .long("partition")
+                .short("p")
+                .required(false)
+                .value_name("partition")
+                .multiple(true)
+                .takes_value(true)
+                .help(
+                    "Identifies the partition whose recipients to sign. This can be done either using its name \
+                     or its secrets directory. \
+                     If unset, all partitions configured to use signatures will be signed.",
+                ),
+        );
     let recipients = App::new("recipients")
         .alias("recipient")
         .setting(AppSettings::VersionlessSubcommands)
@@ -293,6 +458,9 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
         .subcommand(init_recipient)
         .subcommand(add_recipient)
         .subcommand(list_recipient)
+        .subcommand(check_recipient)
+        .subcommand(refresh_recipient)
+        .subcommand(sign_recipient)
         .subcommand(remove_recipient);
     let add_partition = App::new("add")
         .alias("insert")
@@ -334,6 +502,158 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .help(&PARTITION_HELP),
         );
+    let update_partition = App::new("update")
+        .alias("rename")
+        .about(
+            "Change the name, trust model or key import settings of an existing partition. \
+             Settings which are not provided remain unchanged.",
+        )
+        .arg(
+            Arg::with_name("partition-selector")
+                .required(true)
+                .takes_value(true)
+                .help(&PARTITION_HELP),
+        )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .short("n")
+                .takes_value(true)
+                .value_name("name")
+                .help("The new name of the partition. It must not be used by any other partition."),
+        )
+        .arg(
+            Arg::with_name("trust-model")
+                .long("trust-model")
+                .takes_value(true)
+                .value_name("model")
+                .possible_values(&["web-of-trust", "always"])
+                .help("The model by which keys to encrypt for are verified to truly belong to the person."),
+        )
+        .arg(
+            Arg::with_name("auto-import")
+                .long("auto-import")
+                .takes_value(false)
+                .help("If set, missing keys will automatically be imported to your keychain."),
+        )
+        .arg(
+            Arg::with_name("no-auto-import")
+                .long("no-auto-import")
+                .takes_value(false)
+                .help("If set, missing keys will not automatically be imported to your keychain."),
+        )
+        .group(
+            ArgGroup::with_name("settings")
+                .args(&["name", "trust-model", "auto-import", "no-auto-import"])
+                .multiple(true)
+                .required(true),
+        )
+        .group(ArgGroup::with_name("auto-import-setting").args(&["auto-import", "no-auto-import"]));
+    let reencrypt = App::new("reencrypt")
+        .about(
+            "Re-encrypt all resources for the current recipients. \
+             This is useful after a recipient changed their key, after changing the trust model, \
+             or after editing the recipients file by hand.",
+        )
+        .arg(
+            Arg::with_name("partition")
+                .set(ArgSettings::RequireEquals)
+                .long("partition")
+                .short("p")
+                .required(false)
+                .value_name("partition")
+                .multiple(true)
+                .takes_value(true)
+                .help(
+                    "Identifies the partition to re-encrypt. This can be done either using its name \
+                     or its secrets directory. \
+                     If unset, the naturally selected vault will be re-encrypted, see the --select flag.",
+                ),
+        );
+    let fsck = App::new("fsck").about(
+        "Verify that all resources are encrypted for exactly the recipients of their partition, \
+         without decrypting them. \
+         Resources which can still be read by removed recipients, which are not readable by all recipients \
+         or which are not encrypted at all are reported, and cause a non-zero exit code. \
+         Files without the '.gpg' extension among the resources count as not encrypted, except for hidden ones. \
+         In vaults using age, only whether resources are encrypted can be verified.",
+    );
+    let resume = App::new("resume")
+        .about(
+            "Finish a change of recipients or a re-encryption which was interrupted. \
+             \
+             Progress is recorded in a journal next to the vault configuration file, \
+             which allows to continue where it left off.",
+        )
+        .arg(
+            Arg::with_name("abort")
+                .long("abort")
+                .required(false)
+                .help("Discard the interrupted change instead of finishing it, leaving the vault unchanged."),
+        );
+    let git_textconv = App::new("git-textconv")
+        .about(
+            "Write the decrypted content of the given resource file to stdout. \
+             This is meant to be invoked by git to show the plaintext of resources in diffs, \
+             see 'git-setup'.",
+        )
+        .arg(
+            Arg::with_name("path")
+                .required(true)
+                .takes_value(true)
+                .value_name("path")
+                .help("The path to the encrypted file, which does not have to be within the vault."),
+        );
+    let git_merge_driver = App::new("git-merge-driver")
+        .about(
+            "Merge the changes of two versions of a resource relative to their common ancestor, \
+             and write the result re-encrypted for the recipients of its partition to the file of our version. \
+             Resources are merged line by line, and JSON or YAML resources are merged structurally if that fails. \
+             Remaining conflicts are marked only within the encrypted content. \
+             This is meant to be invoked by git as merge driver, see 'git-setup'.",
+        )
+        .arg(
+            Arg::with_name("base")
+                .required(true)
+                .takes_value(true)
+                .value_name("base")
+                .help("The path to the common ancestor of both versions, as passed by git with '%O'."),
+        )
+        .arg(
+            Arg::with_name("ours")
+                .required(true)
+                .takes_value(true)
+                .value_name("ours")
+                .help("The path to our version, which receives the merge result, as passed by git with '%A'."),
+        )
+        .arg(
+            Arg::with_name("theirs")
+                .required(true)
+                .takes_value(true)
+                .value_name("theirs")
+                .help("The path to their version, as passed by git with '%B'."),
+        )
+        .arg(
+            Arg::with_name("path")
+                .required(false)
+                .takes_value(true)
+                .value_name("path")
+                .help(
+                    "The path of the resource in the work tree, as passed by git with '%P'. \
+                     It is needed to find the partition of the resource if there are multiple.",
+                ),
+        );
+    let git_setup = App::new("git-setup").about(
+        "Configure the git repository containing the vault to show the plaintext of resources \
+         in 'git diff' and 'git log -p', and to merge resources changed on different branches, \
+         for everyone who can decrypt them. \
+         This adds the 'sheesy' diff and merge drivers for all resources to the '.gitattributes' file next to the \
+         vault configuration, and configures git to use 'git-textconv' and 'git-merge-driver' for them.",
+    );
+    let list_partitions = App::new("list")
+        .alias("ls")
+        .about("List all partitions of the vault, along with their resources directory and recipients file.")
+        .arg(output);
     let partitions = App::new("partitions")
         .alias("partition")
         .setting(AppSettings::VersionlessSubcommands)
@@ -347,7 +667,9 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
              effectively emulating simple access control lists.",
         )
         .subcommand(add_partition)
-        .subcommand(remove_partition);
+        .subcommand(remove_partition)
+        .subcommand(update_partition)
+        .subcommand(list_partitions);
 
     App::new("vault")
         .version(crate_version!())
@@ -361,8 +683,17 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
         .subcommand(show_resource)
         .subcommand(list)
         .subcommand(remove_resource)
+        .subcommand(move_resource)
+        .subcommand(copy_resource)
+        .subcommand(grep)
         .subcommand(recipients)
         .subcommand(partitions)
+        .subcommand(reencrypt)
+        .subcommand(fsck)
+        .subcommand(resume)
+        .subcommand(git_setup)
+        .subcommand(git_textconv)
+        .subcommand(git_merge_driver)
         .arg(
             Arg::with_name("vault-selector")
                 .short("s")
@@ -372,6 +703,27 @@ pub fn new<'a, 'b>() -> App<'a, 'b> {
                 .help(&VAULT_HELP)
                 .default_value("0"),
         )
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .short("j")
+                .required(false)
+                .takes_value(true)
+                .value_name("count")
+                .validator(|v| {
+                    v.parse::<usize>().map_err(|err| format!("{}", err)).and_then(|n| {
+                        if n == 0 {
+                            Err("Must be at least 1".into())
+                        } else {
+                            Ok(())
+                        }
+                    })
+                })
+                .help(
+                    "The amount of threads to use when re-encrypting resources. \
+                     Defaults to the number of CPUs.",
+                ),
+        )
         .arg(
             Arg::with_name("config-file")
                 .long("config-file")
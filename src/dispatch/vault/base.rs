@@ -1,5 +1,6 @@
 use std::path::PathBuf;
-use vault::{CreateMode, SigningMode, TrustModel, VaultSpec};
+use tools::process::OutputMode;
+use vault::{CreateMode, Encryption, SigningMode, TrustModel, VaultSpec, WriteMode};
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Command {
@@ -18,44 +19,103 @@ pub enum Command {
     ResourceRemove {
         specs: Vec<PathBuf>,
     },
+    ResourceMove {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    ResourceCopy {
+        from: Vec<PathBuf>,
+        to: PathBuf,
+        mode: WriteMode,
+    },
+    Grep {
+        pattern: String,
+        paths: Vec<PathBuf>,
+    },
     Init {
         name: Option<String>,
         trust_model: Option<TrustModel>,
+        encryption: Option<Encryption>,
         auto_import: Option<bool>,
         gpg_key_ids: Vec<String>,
         gpg_keys_dir: PathBuf,
         secrets: PathBuf,
         recipients_file: PathBuf,
     },
-    RecipientsList,
+    RecipientsList {
+        output: Option<OutputMode>,
+    },
+    RecipientsCheck {
+        days: u64,
+        output: Option<OutputMode>,
+    },
+    RecipientsRefresh {
+        reencrypt: bool,
+    },
+    RecipientsSign {
+        partitions: Vec<String>,
+    },
     RecipientsInit {
         gpg_key_ids: Vec<String>,
     },
     RecipientsRemove {
         gpg_key_ids: Vec<String>,
         partitions: Vec<String>,
+        group: Option<String>,
     },
     RecipientsAdd {
         partitions: Vec<String>,
+        group: Option<String>,
         gpg_key_ids: Vec<String>,
         signing_key_id: Option<String>,
         sign: SigningMode,
     },
+    Reencrypt {
+        partitions: Vec<String>,
+    },
+    Fsck,
+    GitSetup {
+        program: String,
+    },
+    GitTextconv {
+        path: PathBuf,
+    },
+    GitMergeDriver {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        path: Option<PathBuf>,
+    },
+    Resume {
+        abort: bool,
+    },
     PartitionsRemove {
         selector: String,
     },
+    PartitionsUpdate {
+        selector: String,
+        name: Option<String>,
+        trust_model: Option<TrustModel>,
+        auto_import: Option<bool>,
+    },
     PartitionsAdd {
         recipients_file: Option<PathBuf>,
         gpg_key_ids: Vec<String>,
         name: Option<String>,
         path: PathBuf,
     },
-    List,
+    PartitionsList {
+        output: Option<OutputMode>,
+    },
+    List {
+        output: Option<OutputMode>,
+    },
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Context {
     pub vault_path: PathBuf,
     pub vault_selector: String,
+    pub jobs: Option<usize>,
     pub command: Command,
 }
@@ -1,52 +1,133 @@
+use crate::dispatch::vault::Command;
 use crate::dispatch::vault::Context;
+use crate::tools::process::show;
+#[cfg(feature = "gpgme")]
 use crate::vault::error::first_cause_of_type;
+#[cfg(feature = "gpgme")]
+use crate::vault::gpgme;
+use crate::vault::recipients_check_result;
 use crate::vault::Destination;
 use crate::vault::WriteMode;
-use crate::vault::{Vault, VaultExt};
-use failure::Error;
-use gpgme;
+use crate::vault::{ChangedPaths, Vault, VaultExt};
+use failure::{Error, ResultExt};
+use std::fmt::Display;
 use std::io::Write;
 
-fn vault_from(ctx: &Context) -> Result<Vault, Error> {
-    Vault::from_file(&ctx.vault_path)?.select(&ctx.vault_selector)
+fn vault_from(ctx: &Context, changed_paths: &ChangedPaths) -> Result<Vault, Error> {
+    Ok(Vault::from_file(&ctx.vault_path)?
+        .select(&ctx.vault_selector)?
+        .set_jobs(ctx.jobs)
+        .set_changed_paths(changed_paths.clone()))
 }
 
-fn inner_do_it(ctx: &Context, output: &mut dyn Write, error: &mut dyn Write) -> Result<(), Error> {
+fn inner_do_it(
+    ctx: &Context,
+    changed_paths: &ChangedPaths,
+    output: &mut dyn Write,
+    error: &mut dyn Write,
+) -> Result<(), Error> {
     use crate::dispatch::vault::Command::*;
     match ctx.command {
-        PartitionsRemove { ref selector } => vault_from(&ctx)?.remove_partition(selector, output),
+        PartitionsRemove { ref selector } => vault_from(ctx, changed_paths)?.remove_partition(selector, output),
+        PartitionsUpdate {
+            ref selector,
+            ref name,
+            ref trust_model,
+            auto_import,
+        } => vault_from(ctx, changed_paths)?.update_partition(
+            selector,
+            name.as_ref().map(|s| s.as_str()),
+            trust_model.clone(),
+            auto_import,
+            output,
+        ),
         PartitionsAdd {
             ref recipients_file,
             ref path,
             ref name,
             ref gpg_key_ids,
-        } => vault_from(&ctx)?.add_partition(
+        } => vault_from(ctx, changed_paths)?.add_partition(
             path,
             name.as_ref().map(|s| s.as_str()),
             gpg_key_ids,
             recipients_file.as_ref().map(|f| f.as_path()),
             output,
         ),
+        RecipientsRemove {
+            group: Some(ref group),
+            ref gpg_key_ids,
+            ..
+        } => vault_from(ctx, changed_paths)?.remove_group_members(group, gpg_key_ids, output),
         RecipientsRemove {
             ref partitions,
             ref gpg_key_ids,
-        } => vault_from(&ctx)?.remove_recipients(gpg_key_ids, partitions, output),
+            group: None,
+        } => vault_from(ctx, changed_paths)?.remove_recipients(gpg_key_ids, partitions, output),
+        RecipientsAdd {
+            group: Some(ref group),
+            ref gpg_key_ids,
+            ref sign,
+            ref signing_key_id,
+            ..
+        } => vault_from(ctx, changed_paths)?.add_group_members(
+            group,
+            gpg_key_ids,
+            *sign,
+            signing_key_id.as_ref().map(String::as_str),
+            output,
+        ),
         RecipientsAdd {
             ref partitions,
             ref gpg_key_ids,
             ref sign,
             ref signing_key_id,
-        } => vault_from(&ctx)?.add_recipients(
+            group: None,
+        } => vault_from(ctx, changed_paths)?.add_recipients(
             gpg_key_ids,
             *sign,
             signing_key_id.as_ref().map(String::as_str),
             partitions,
             output,
         ),
-        RecipientsList => vault_from(&ctx)?.print_recipients(output, error),
-        RecipientsInit { ref gpg_key_ids } => vault_from(&ctx)?.init_recipients(gpg_key_ids, output),
+        RecipientsList { output: None } => vault_from(ctx, changed_paths)?.print_recipients(output, error),
+        RecipientsList { output: Some(mode) } => {
+            show(
+                Some(&mode),
+                vault_from(ctx, changed_paths)?.list_recipients(error)?,
+                &mut *output,
+            )?;
+            Ok(writeln!(output)?)
+        }
+        RecipientsCheck { days, output: None } => {
+            vault_from(ctx, changed_paths)?.print_recipients_check(days, output, error)
+        }
+        RecipientsCheck {
+            days,
+            output: Some(mode),
+        } => {
+            let checks = vault_from(ctx, changed_paths)?.check_recipients(days, error)?;
+            show(Some(&mode), &checks, &mut *output)?;
+            writeln!(output)?;
+            recipients_check_result(&checks)
+        }
+        RecipientsSign { ref partitions } => vault_from(ctx, changed_paths)?.sign_recipients(partitions, output),
+        RecipientsRefresh { reencrypt } => vault_from(ctx, changed_paths)?.refresh_recipients(reencrypt, output, error),
+        PartitionsList { output: None } => vault_from(ctx, changed_paths)?.print_partitions(output),
+        PartitionsList { output: Some(mode) } => {
+            show(
+                Some(&mode),
+                vault_from(ctx, changed_paths)?.list_partitions(),
+                &mut *output,
+            )?;
+            Ok(writeln!(output)?)
+        }
+        Reencrypt { ref partitions } => vault_from(ctx, changed_paths)?.reencrypt_partitions(partitions, output),
+        Fsck => vault_from(ctx, changed_paths)?.fsck(output, error),
+        Resume { abort } => vault_from(ctx, changed_paths)?.resume(abort, output),
+        RecipientsInit { ref gpg_key_ids } => vault_from(ctx, changed_paths)?.init_recipients(gpg_key_ids, output),
         Init {
             ref trust_model,
+            encryption,
             auto_import,
             ref name,
             ref gpg_key_ids,
@@ -54,7 +135,7 @@ fn inner_do_it(ctx: &Context, output: &mut dyn Write, error: &mut dyn Write) ->
             ref recipients_file,
             ref secrets,
         } => {
-            Vault::init(
+            let vault = Vault::init(
                 secrets,
                 gpg_key_ids,
                 gpg_keys_dir,
@@ -62,13 +143,26 @@ fn inner_do_it(ctx: &Context, output: &mut dyn Write, error: &mut dyn Write) ->
                 &ctx.vault_path,
                 name.clone(),
                 trust_model.clone(),
+                encryption,
                 auto_import,
                 output,
             )?;
+            changed_paths.extend(&vault.changed_paths);
             Ok(())
         }
-        ResourceRemove { ref specs } => vault_from(&ctx)?.remove(specs, output),
-        ResourceAdd { ref specs } => vault_from(&ctx)?.encrypt(
+        ResourceRemove { ref specs } => vault_from(ctx, changed_paths)?.remove(specs, output),
+        ResourceMove { ref from, ref to } => vault_from(ctx, changed_paths)?.move_resource(from, to, output),
+        ResourceCopy { ref from, ref to, mode } => vault_from(ctx, changed_paths)?.copy(from, to, mode, output),
+        Grep { ref pattern, ref paths } => vault_from(ctx, changed_paths)?.grep(pattern, paths, output, error),
+        GitSetup { ref program } => vault_from(ctx, changed_paths)?.git_setup(program, output),
+        GitTextconv { ref path } => vault_from(ctx, changed_paths)?.git_textconv(path, output),
+        GitMergeDriver {
+            ref base,
+            ref ours,
+            ref theirs,
+            ref path,
+        } => vault_from(ctx, changed_paths)?.git_merge_driver(base, ours, theirs, path.as_deref(), output),
+        ResourceAdd { ref specs } => vault_from(ctx, changed_paths)?.encrypt(
             specs,
             WriteMode::RefuseOverwrite,
             Destination::ReolveAndAppendGpg,
@@ -79,36 +173,142 @@ fn inner_do_it(ctx: &Context, output: &mut dyn Write, error: &mut dyn Write) ->
             try_encrypt,
             ref editor,
             mode,
-        } => vault_from(&ctx)?.edit(spec, editor, mode, try_encrypt, output),
-        List => vault_from(&ctx)?.print_resources(output),
-        ResourceShow { ref spec } => vault_from(&ctx)?.decrypt(spec, output).map(|_| ()),
+        } => vault_from(ctx, changed_paths)?.edit(spec, editor, mode, try_encrypt, output),
+        List { output: None } => vault_from(ctx, changed_paths)?.print_resources(output),
+        List { output: Some(mode) } => {
+            show(
+                Some(&mode),
+                vault_from(ctx, changed_paths)?.list_resources()?,
+                &mut *output,
+            )?;
+            Ok(writeln!(output)?)
+        }
+        ResourceShow { ref spec } => vault_from(ctx, changed_paths)?.decrypt(spec, output, error).map(|_| ()),
     }
 }
 
+fn join<T: Display>(items: &[T]) -> String {
+    items.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Describe the change `command` made to the vault, given the amount of resources it changed, for use as
+/// commit message. `None` is returned for commands which do not change the vault.
+fn describe_change(command: &Command, num_resources: usize) -> Option<String> {
+    use crate::dispatch::vault::Command::*;
+    Some(match *command {
+        Init { ref secrets, .. } => format!("Initialized vault at '{}'", secrets.display()),
+        ResourceAdd { ref specs } => format!(
+            "Added {}",
+            join(&specs.iter().map(|s| s.dst.display()).collect::<Vec<_>>())
+        ),
+        ResourceEdit { ref spec, .. } => format!("Edited {}", spec.display()),
+        ResourceRemove { ref specs } => format!(
+            "Removed {}",
+            join(&specs.iter().map(|s| s.display()).collect::<Vec<_>>())
+        ),
+        ResourceMove { ref from, ref to } => format!("Moved {} to {}", from.display(), to.display()),
+        ResourceCopy { ref to, .. } => format!("Copied {} resource(s) to {}", num_resources, to.display()),
+        RecipientsAdd {
+            group: Some(ref group),
+            ref gpg_key_ids,
+            ..
+        } => format!(
+            "Re-encrypted {} resource(s) after adding {} to group {}",
+            num_resources,
+            join(gpg_key_ids),
+            group
+        ),
+        RecipientsRemove {
+            group: Some(ref group),
+            ref gpg_key_ids,
+            ..
+        } => format!(
+            "Re-encrypted {} resource(s) after removing {} from group {}",
+            num_resources,
+            join(gpg_key_ids),
+            group
+        ),
+        RecipientsAdd { ref gpg_key_ids, .. } => format!(
+            "Re-encrypted {} resource(s) for new recipient(s) {}",
+            num_resources,
+            join(gpg_key_ids)
+        ),
+        RecipientsRemove { ref gpg_key_ids, .. } => format!(
+            "Re-encrypted {} resource(s) after removing recipient(s) {}",
+            num_resources,
+            join(gpg_key_ids)
+        ),
+        RecipientsInit { ref gpg_key_ids } if gpg_key_ids.is_empty() => "Exported own key to request access".into(),
+        RecipientsInit { ref gpg_key_ids } => format!("Exported key(s) {} to request access", join(gpg_key_ids)),
+        Reencrypt { .. } => format!("Re-encrypted {} resource(s)", num_resources),
+        RecipientsSign { ref partitions } if partitions.is_empty() => "Signed recipients".into(),
+        RecipientsSign { ref partitions } => format!("Signed recipients of {}", join(partitions)),
+        RecipientsRefresh { reencrypt: false } => "Refreshed public keys of recipients".into(),
+        RecipientsRefresh { reencrypt: true } => format!(
+            "Refreshed public keys of recipients and re-encrypted {} resource(s)",
+            num_resources
+        ),
+        Resume { abort: false } => format!("Finished interrupted change of {} resource(s)", num_resources),
+        PartitionsAdd { ref path, .. } => format!("Added partition at '{}'", path.display()),
+        PartitionsRemove { ref selector } => format!("Removed partition '{}'", selector),
+        PartitionsUpdate { ref selector, .. } => format!("Updated partition '{}'", selector),
+        GitSetup { .. } => "Set up git to diff and merge resources".into(),
+        Resume { abort: true }
+        | ResourceShow { .. }
+        | List { .. }
+        | Grep { .. }
+        | Fsck
+        | RecipientsList { .. }
+        | RecipientsCheck { .. }
+        | PartitionsList { .. }
+        | GitTextconv { .. }
+        | GitMergeDriver { .. } => return None,
+    })
+}
+
 /// A universal handler which delegates all functionality based on the provided Context
 /// The latter is usually provided by the user interface.
+/// If enabled in the vault configuration, the files changed by the command are committed to git afterwards.
 pub fn do_it(ctx: &Context, output: &mut dyn Write, error: &mut dyn Write) -> Result<(), Error> {
-    inner_do_it(&ctx, output, error).map_err(|failure| {
-        let gpg_error_code = match first_cause_of_type::<gpgme::Error>(&failure) {
-            Some(gpg_err) => Some(gpg_err.code()),
-            None => None, // failure.into(),
-        };
-        match gpg_error_code {
-            Some(code) if code == gpgme::Error::NOT_SUPPORTED.code() => failure
-                .context(
-                    "The GNU Privacy Guard (GPG) does not supported the attempted operation.\n\
-                     GPG v2 is known to work, and you can install it here:\n\
-                     https://www.gnupg.org for more information.",
-                )
-                .into(),
-            Some(code) if code == gpgme::Error::UNSUPPORTED_PROTOCOL.code() => failure
-                .context(
-                    "The GNU Privacy Guard (GPG) is not available on your system.\n\
-                     Please install it and try again.\n\
-                     See https://www.gnupg.org for more information.",
-                )
-                .into(),
-            _ => failure,
-        }
-    })
+    let changed_paths = ChangedPaths::default();
+    inner_do_it(ctx, &changed_paths, output, error).map_err(explain_backend_error)?;
+    if describe_change(&ctx.command, 0).is_some() {
+        vault_from(ctx, &changed_paths)?
+            .commit_changes(
+                |num_resources| describe_change(&ctx.command, num_resources).expect("a change"),
+                output,
+            )
+            .context("The change was made, but could not be committed")?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gpgme")]
+fn explain_backend_error(failure: Error) -> Error {
+    let gpg_error_code = match first_cause_of_type::<gpgme::Error>(&failure) {
+        Some(gpg_err) => Some(gpg_err.code()),
+        None => None, // failure.into(),
+    };
+    match gpg_error_code {
+        Some(code) if code == gpgme::Error::NOT_SUPPORTED.code() => failure
+            .context(
+                "The GNU Privacy Guard (GPG) does not supported the attempted operation.\n\
+                 GPG v2 is known to work, and you can install it here:\n\
+                 https://www.gnupg.org for more information.",
+            )
+            .into(),
+        Some(code) if code == gpgme::Error::UNSUPPORTED_PROTOCOL.code() => failure
+            .context(
+                "The GNU Privacy Guard (GPG) is not available on your system.\n\
+                 Please install it and try again.\n\
+                 See https://www.gnupg.org for more information.",
+            )
+            .into(),
+        _ => failure,
+    }
+}
+
+#[cfg(not(feature = "gpgme"))]
+fn explain_backend_error(failure: Error) -> Error {
+    failure
 }
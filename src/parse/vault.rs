@@ -1,13 +1,15 @@
 use clap::ArgMatches;
 use conv::TryInto;
-use failure::Error;
+use failure::{Error, ResultExt};
 
 use std::convert::Into;
+use std::env;
 use std::path::{Path, PathBuf};
 
 use crate::dispatch::vault::{Command, Context};
+use tools::process::OutputMode;
 use vault::error::{first_cause_of_type, DecryptionError};
-use vault::{CreateMode, SigningMode};
+use vault::{shell_quote, CreateMode, SigningMode, WriteMode};
 
 use super::util::{optional_args, required_arg, required_os_arg};
 use crate::dispatch;
@@ -40,13 +42,60 @@ pub fn context_from(args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
         vault_path: required_os_arg(args, "config-file")?,
         vault_selector: required_arg(args, "vault-selector")?,
-        command: Command::List,
+        jobs: args.value_of("jobs").map(|v| v.parse().expect("clap to work")),
+        command: Command::List { output: None },
     })
 }
 
-pub fn recipients_list(ctx: Context, _args: &ArgMatches) -> Result<Context, Error> {
+fn output_mode(args: &ArgMatches) -> Option<OutputMode> {
+    args.value_of("output").map(|v| v.parse().expect("clap to work"))
+}
+
+pub fn recipients_list(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::RecipientsList {
+            output: output_mode(args),
+        },
+        ..ctx
+    })
+}
+
+pub fn recipients_check(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::RecipientsCheck {
+            days: args
+                .value_of("days")
+                .map(|v| v.parse().expect("clap to work"))
+                .expect("default value"),
+            output: output_mode(args),
+        },
+        ..ctx
+    })
+}
+
+pub fn recipients_refresh(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::RecipientsRefresh {
+            reencrypt: args.is_present("reencrypt"),
+        },
+        ..ctx
+    })
+}
+
+pub fn recipients_sign(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::RecipientsSign {
+            partitions: optional_args(args, "partition"),
+        },
+        ..ctx
+    })
+}
+
+pub fn partitions_list(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
-        command: Command::RecipientsList,
+        command: Command::PartitionsList {
+            output: output_mode(args),
+        },
         ..ctx
     })
 }
@@ -64,6 +113,7 @@ pub fn recipients_remove(ctx: Context, args: &ArgMatches) -> Result<Context, Err
     Ok(Context {
         command: Command::RecipientsRemove {
             partitions: optional_args(args, "partition"),
+            group: args.value_of("group").map(ToOwned::to_owned),
             gpg_key_ids: args
                 .values_of("gpg-key-id")
                 .expect("Clap to assure this is a required arg")
@@ -96,6 +146,24 @@ pub fn partitions_remove(ctx: Context, args: &ArgMatches) -> Result<Context, Err
     })
 }
 
+pub fn partitions_update(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::PartitionsUpdate {
+            selector: required_arg(args, "partition-selector")?,
+            name: args.value_of("name").map(ToOwned::to_owned),
+            trust_model: args.value_of("trust-model").map(|v| v.parse().expect("clap to work")),
+            auto_import: if args.is_present("auto-import") {
+                Some(true)
+            } else if args.is_present("no-auto-import") {
+                Some(false)
+            } else {
+                None
+            },
+        },
+        ..ctx
+    })
+}
+
 pub fn recipients_add(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
         command: Command::RecipientsAdd {
@@ -105,6 +173,7 @@ pub fn recipients_add(ctx: Context, args: &ArgMatches) -> Result<Context, Error>
                 SigningMode::Public
             },
             partitions: optional_args(args, "partition"),
+            group: args.value_of("group").map(ToOwned::to_owned),
             signing_key_id: args.value_of("signing-key").map(ToOwned::to_owned),
             gpg_key_ids: args
                 .values_of("gpg-key-id")
@@ -116,6 +185,31 @@ pub fn recipients_add(ctx: Context, args: &ArgMatches) -> Result<Context, Error>
     })
 }
 
+pub fn reencrypt(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::Reencrypt {
+            partitions: optional_args(args, "partition"),
+        },
+        ..ctx
+    })
+}
+
+pub fn fsck(ctx: Context, _args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::Fsck,
+        ..ctx
+    })
+}
+
+pub fn resume(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::Resume {
+            abort: args.is_present("abort"),
+        },
+        ..ctx
+    })
+}
+
 pub fn resource_show(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
         command: Command::ResourceShow {
@@ -125,9 +219,11 @@ pub fn resource_show(ctx: Context, args: &ArgMatches) -> Result<Context, Error>
     })
 }
 
-pub fn resource_list(ctx: Context, _args: &ArgMatches) -> Result<Context, Error> {
+pub fn resource_list(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
-        command: Command::List,
+        command: Command::List {
+            output: output_mode(args),
+        },
         ..ctx
     })
 }
@@ -160,6 +256,94 @@ pub fn vault_resource_remove(ctx: Context, args: &ArgMatches) -> Result<Context,
     })
 }
 
+pub fn resource_move(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::ResourceMove {
+            from: required_os_arg(args, "path")?,
+            to: required_os_arg(args, "destination")?,
+        },
+        ..ctx
+    })
+}
+
+pub fn resource_copy(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::ResourceCopy {
+            from: match args.values_of("path") {
+                Some(v) => v.map(PathBuf::from).collect(),
+                None => Vec::new(),
+            },
+            to: required_os_arg(args, "destination")?,
+            mode: if args.is_present("overwrite") {
+                WriteMode::AllowOverwrite
+            } else {
+                WriteMode::RefuseOverwrite
+            },
+        },
+        ..ctx
+    })
+}
+
+pub fn grep(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::Grep {
+            pattern: required_arg(args, "pattern")?,
+            paths: match args.values_of("path") {
+                Some(v) => v.map(PathBuf::from).collect(),
+                None => Vec::new(),
+            },
+        },
+        ..ctx
+    })
+}
+
+pub fn git_textconv(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::GitTextconv {
+            path: required_os_arg(args, "path")?,
+        },
+        ..ctx
+    })
+}
+
+pub fn git_merge_driver(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::GitMergeDriver {
+            base: required_os_arg(args, "base")?,
+            ours: required_os_arg(args, "ours")?,
+            theirs: required_os_arg(args, "theirs")?,
+            path: args.value_of_os("path").map(Into::into),
+        },
+        ..ctx
+    })
+}
+
+/// Return the shell command invoking the vault command line of the currently running program.
+fn vault_program() -> Result<String, Error> {
+    let exe = env::current_exe().context("Could not determine the path of the running program")?;
+    let subcommand = if exe.file_stem().is_some_and(|stem| stem == "syv") {
+        ""
+    } else {
+        " vault"
+    };
+    let exe = exe.to_str().ok_or_else(|| {
+        format_err!(
+            "Cannot configure git to invoke '{}' as its path is not valid UTF-8",
+            exe.display()
+        )
+    })?;
+    Ok(format!("{}{}", shell_quote(exe), subcommand))
+}
+
+pub fn git_setup(ctx: Context, _args: &ArgMatches) -> Result<Context, Error> {
+    Ok(Context {
+        command: Command::GitSetup {
+            program: vault_program()?,
+        },
+        ..ctx
+    })
+}
+
 pub fn resource_add(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     Ok(Context {
         command: Command::ResourceAdd {
@@ -176,6 +360,7 @@ pub fn init_from(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
     let mut recipients_file: PathBuf = required_os_arg(args, "recipients-file-path")?;
     let secrets: PathBuf = required_os_arg(args, "secrets-dir")?;
     let trust_model = args.value_of("trust-model").map(|v| v.parse().expect("clap to work"));
+    let encryption = args.value_of("encryption").map(|v| v.parse().expect("clap to work"));
     let auto_import = Some(!args.is_present("no-auto-import"));
 
     if args.is_present("first-partition") && secrets == Path::new(".") {
@@ -190,6 +375,7 @@ pub fn init_from(ctx: Context, args: &ArgMatches) -> Result<Context, Error> {
             recipients_file,
             auto_import,
             trust_model,
+            encryption,
             secrets,
             gpg_keys_dir: required_os_arg(args, "gpg-keys-dir")?,
             gpg_key_ids: optional_args(args, "gpg-key-id"),
@@ -209,6 +395,8 @@ pub fn execute(args: &ArgMatches) -> Result<(), Error> {
         ("partitions", Some(args)) => match args.subcommand() {
             ("add", Some(args)) => partitions_add(context, args)?,
             ("remove", Some(args)) => partitions_remove(context, args)?,
+            ("update", Some(args)) => partitions_update(context, args)?,
+            ("list", Some(args)) => partitions_list(context, args)?,
             _ => usage_and_exit(&args),
         },
         ("recipients", Some(args)) => match args.subcommand() {
@@ -216,14 +404,26 @@ pub fn execute(args: &ArgMatches) -> Result<(), Error> {
             ("remove", Some(args)) => recipients_remove(context, args)?,
             ("init", Some(args)) => recipients_init(context, args)?,
             ("list", Some(args)) => recipients_list(context, args)?,
+            ("check", Some(args)) => recipients_check(context, args)?,
+            ("refresh", Some(args)) => recipients_refresh(context, args)?,
+            ("sign", Some(args)) => recipients_sign(context, args)?,
             _ => recipients_list(context, args)?,
         },
         ("init", Some(args)) => init_from(context, args)?,
         ("add", Some(args)) => resource_add(context, args)?,
         ("remove", Some(args)) => vault_resource_remove(context, args)?,
+        ("move", Some(args)) => resource_move(context, args)?,
+        ("copy", Some(args)) => resource_copy(context, args)?,
+        ("grep", Some(args)) => grep(context, args)?,
         ("show", Some(args)) => resource_show(context, args)?,
         ("edit", Some(args)) => resource_edit(context, args)?,
         ("list", Some(args)) => resource_list(context, args)?,
+        ("reencrypt", Some(args)) => reencrypt(context, args)?,
+        ("fsck", Some(args)) => fsck(context, args)?,
+        ("resume", Some(args)) => resume(context, args)?,
+        ("git-setup", Some(args)) => git_setup(context, args)?,
+        ("git-textconv", Some(args)) => git_textconv(context, args)?,
+        ("git-merge-driver", Some(args)) => git_merge_driver(context, args)?,
         _ => context,
     };
     let sout = stdout();
@@ -7,7 +7,6 @@ extern crate lazy_static;
 extern crate atty;
 extern crate conv;
 extern crate glob;
-extern crate gpgme;
 extern crate itertools;
 extern crate sheesy_tools as tools;
 extern crate sheesy_vault as vault;
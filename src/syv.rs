@@ -5,7 +5,7 @@ extern crate failure;
 #[macro_use]
 extern crate lazy_static;
 extern crate conv;
-extern crate gpgme;
+extern crate sheesy_tools as tools;
 extern crate sheesy_vault as vault;
 
 use clap::ArgMatches;
@@ -235,5 +235,54 @@ EDITOR
         expect_run $WITH_FAILURE "$exe" delete existing non-existing existing
       }
     )
+
+    title "'vault move'"
+    (when "moving an existing resource to a new location"
+      add_resource to-move
+
+      it "succeeds" && {
+        WITH_SNAPSHOT="$snapshot/resource-move-existing" \
+        expect_run $SUCCESSFULLY "$exe" move to-move moved/resource
+      }
+
+      it "makes the resource available at its new location" && {
+        WITH_SNAPSHOT="$snapshot/resource-move-existing-after" \
+        expect_run $SUCCESSFULLY "$exe" show moved/resource
+      }
+    )
+
+    (when "moving a resource onto an existing one"
+      add_resource to-overwrite
+      it "fails without touching either of them" && {
+        WITH_SNAPSHOT="$snapshot/resource-move-onto-existing" \
+        expect_run $WITH_FAILURE "$exe" mv existing to-overwrite.gpg
+      }
+    )
+
+    title "'vault copy'"
+    (when "copying an existing resource"
+      it "succeeds" && {
+        WITH_SNAPSHOT="$snapshot/resource-copy-existing" \
+        expect_run $SUCCESSFULLY "$exe" copy moved/resource copied
+      }
+
+      it "keeps the original" && {
+        WITH_SNAPSHOT="$snapshot/resource-move-existing-after" \
+        expect_run $SUCCESSFULLY "$exe" show moved/resource
+      }
+    )
+
+    (when "copying a resource onto an existing one"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/resource-copy-onto-existing" \
+        expect_run $WITH_FAILURE "$exe" cp existing copied
+      }
+      (with "--overwrite set"
+        it "succeeds" && {
+          WITH_SNAPSHOT="$snapshot/resource-copy-onto-existing-overwrite" \
+          expect_run $SUCCESSFULLY "$exe" cp --overwrite existing copied
+        }
+      )
+    )
   )
 )
@@ -0,0 +1,77 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/auto-commit"
+
+(sandboxed
+  title "'vault' with auto_commit"
+  (with "a vault in a git repository which commits changes automatically"
+    { import_user "$fixture/tester.sec.asc"
+      git init -q .
+      git config user.name tester
+      git config user.email tester@example.com
+      "$exe" init --no-auto-import
+      echo "auto_commit: true" >> sy-vault.yml
+      git add --all
+      git commit -q -m "initial vault"
+    } &>/dev/null
+
+    (with "an untracked plaintext file and an unrelated staged file"
+      echo -n plaintext > secret.txt
+      echo -n unrelated > staged.txt
+      git add staged.txt
+
+      (when "adding a resource"
+        it "succeeds and commits it" && {
+          WITH_SNAPSHOT="$snapshot/add-commits-resource" \
+          expect_run $SUCCESSFULLY "$exe" add secret.txt:secret
+        }
+        it "commits only the new resource" && {
+          WITH_SNAPSHOT="$snapshot/add-committed-files" \
+          expect_run $SUCCESSFULLY git show --no-renames --name-status --format=%s HEAD
+        }
+        it "leaves the plaintext file untracked and the staged file uncommitted" && {
+          WITH_SNAPSHOT="$snapshot/add-status" \
+          expect_run $SUCCESSFULLY git status --porcelain
+        }
+      )
+
+      (when "moving the resource"
+        it "succeeds and commits the move" && {
+          WITH_SNAPSHOT="$snapshot/move-commits-resources" \
+          expect_run $SUCCESSFULLY "$exe" move secret moved
+        }
+        it "commits only the moved resource" && {
+          WITH_SNAPSHOT="$snapshot/move-committed-files" \
+          expect_run $SUCCESSFULLY git show --no-renames --name-status --format=%s HEAD
+        }
+      )
+
+      (when "removing the resource"
+        it "succeeds and commits the removal" && {
+          WITH_SNAPSHOT="$snapshot/remove-commits-removal" \
+          expect_run $SUCCESSFULLY "$exe" remove moved
+        }
+        it "commits only the removed resource" && {
+          WITH_SNAPSHOT="$snapshot/remove-committed-files" \
+          expect_run $SUCCESSFULLY git show --no-renames --name-status --format=%s HEAD
+        }
+        it "still leaves the plaintext file untracked and the staged file uncommitted" && {
+          WITH_SNAPSHOT="$snapshot/remove-status" \
+          expect_run $SUCCESSFULLY git status --porcelain
+        }
+      )
+    )
+  )
+)
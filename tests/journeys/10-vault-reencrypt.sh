@@ -0,0 +1,44 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/reencrypt"
+
+(sandboxed
+  title "'vault reencrypt'"
+  (with "a vault with resources"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      echo -n one | "$exe" add :secret
+      echo -n two | "$exe" add :sub/other
+    } &>/dev/null
+
+    (when "re-encrypting all resources"
+      it "succeeds and reports each resource" && {
+        WITH_SNAPSHOT="$snapshot/reencrypt-all" \
+        expect_run $SUCCESSFULLY "$exe" reencrypt
+      }
+      it "leaves all resources readable" && {
+        WITH_SNAPSHOT="$snapshot/reencrypt-all-show" \
+        expect_run $SUCCESSFULLY "$exe" show sub/other
+      }
+    )
+
+    (when "re-encrypting a partition which does not exist"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/reencrypt-unknown-partition" \
+        expect_run $WITH_FAILURE "$exe" reencrypt --partition=unknown
+      }
+    )
+  )
+)
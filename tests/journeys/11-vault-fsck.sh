@@ -0,0 +1,57 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/fsck"
+
+(sandboxed
+  title "'vault fsck'"
+  (with "a vault with resources"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      echo -n one | "$exe" add :secret
+      echo -n two | "$exe" add :sub/other
+    } &>/dev/null
+
+    (when "all resources are encrypted for exactly the recipients"
+      it "succeeds" && {
+        WITH_SNAPSHOT="$snapshot/all-resources-fine" \
+        expect_run $SUCCESSFULLY "$exe" fsck
+      }
+    )
+
+    (when "a resource is not encrypted at all"
+      echo plaintext > sub/plain.gpg
+      it "fails and reports it" && {
+        WITH_SNAPSHOT="$snapshot/unencrypted-resource" \
+        expect_run $WITH_FAILURE "$exe" fsck
+      }
+    )
+
+    (when "a file without the '.gpg' extension is placed among the resources"
+      echo plaintext > sub/notes.txt
+      it "fails and reports it as not encrypted" && {
+        WITH_SNAPSHOT="$snapshot/plaintext-file" \
+        expect_run $WITH_FAILURE "$exe" fsck
+      }
+    )
+
+    (when "a resource has an invalid ASCII armor"
+      printf -- "-----BEGIN PGP MESSAGE-----\n\n!!!\n-----END PGP MESSAGE-----\n" > sub/garbage.gpg
+      it "fails and reports it along with the other resources" && {
+        WITH_SNAPSHOT="$snapshot/unreadable-resource" \
+        expect_run $WITH_FAILURE "$exe" fsck
+      }
+    )
+  )
+)
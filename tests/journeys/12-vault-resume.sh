@@ -0,0 +1,94 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/resume"
+
+(sandboxed
+  title "'vault resume'"
+  (with "a vault with resources"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      echo -n one | "$exe" add :secret
+      echo -n two | "$exe" add :sub/other
+    } &>/dev/null
+
+    (when "resuming without an interrupted change"
+      it "succeeds and says so" && {
+        WITH_SNAPSHOT="$snapshot/resume-nothing" \
+        expect_run $SUCCESSFULLY "$exe" resume
+      }
+    )
+
+    (with "a re-encryption which was interrupted"
+      echo "reencrypt 0" > sy-vault.yml.journal
+
+      (when "re-encrypting again"
+        it "fails as the interrupted change has to be dealt with first" && {
+          WITH_SNAPSHOT="$snapshot/reencrypt-with-journal" \
+          expect_run $WITH_FAILURE "$exe" reencrypt
+        }
+      )
+
+      (when "discarding the interrupted change"
+        it "succeeds" && {
+          WITH_SNAPSHOT="$snapshot/resume-abort" \
+          expect_run $SUCCESSFULLY "$exe" resume --abort
+        }
+        it "removes the journal" && {
+          expect_run $WITH_FAILURE test -e sy-vault.yml.journal
+        }
+      )
+    )
+
+    (with "a re-encryption which was interrupted"
+      echo "reencrypt 0" > sy-vault.yml.journal
+
+      (when "resuming it"
+        it "succeeds and re-encrypts all resources" && {
+          WITH_SNAPSHOT="$snapshot/resume-reencryption" \
+          expect_run $SUCCESSFULLY "$exe" resume
+        }
+        it "removes the journal" && {
+          expect_run $WITH_FAILURE test -e sy-vault.yml.journal
+        }
+      )
+    )
+
+    (with "a change which was interrupted while it was applied"
+      cp sub/other.gpg secret.gpg.sy-staged
+      printf "write ./secret.gpg\ncommit\n" > sy-vault.yml.journal
+
+      (when "discarding the interrupted change"
+        it "fails as it was already being applied" && {
+          WITH_SNAPSHOT="$snapshot/resume-abort-while-committing" \
+          expect_run $WITH_FAILURE "$exe" resume --abort
+        }
+      )
+
+      (when "resuming it"
+        it "succeeds" && {
+          WITH_SNAPSHOT="$snapshot/resume-commit" \
+          expect_run $SUCCESSFULLY "$exe" resume
+        }
+        it "applies the staged change" && {
+          WITH_SNAPSHOT="$snapshot/resume-commit-show" \
+          expect_run $SUCCESSFULLY "$exe" show secret
+        }
+        it "removes the staged file" && {
+          expect_run $WITH_FAILURE test -e secret.gpg.sy-staged
+        }
+      )
+    )
+  )
+)
@@ -0,0 +1,71 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/partitions-update"
+
+(sandboxed
+  title "'vault partitions update'"
+  (with "a vault with two partitions"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import -k etc/keys --first-partition -r etc/recipients --secrets-dir secrets
+      "$exe" partitions add --name second second
+    } &>/dev/null
+
+    (when "naming the first partition"
+      it "succeeds" && {
+        WITH_SNAPSHOT="$snapshot/rename-first" \
+        expect_run $SUCCESSFULLY "$exe" partitions update secrets --name first
+      }
+    )
+
+    (when "giving the second partition the name of the first one"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/rename-to-duplicate-name" \
+        expect_run $WITH_FAILURE "$exe" partitions update second --name first
+      }
+    )
+
+    (when "changing multiple settings of the second partition at once"
+      it "succeeds" && {
+        WITH_SNAPSHOT="$snapshot/update-multiple-settings" \
+        expect_run $SUCCESSFULLY "$exe" partitions update second --trust-model=always --auto-import --name zweite
+      }
+    )
+
+    (when "changing the settings of a partition which does not exist"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/update-unknown-partition" \
+        expect_run $WITH_FAILURE "$exe" partitions update unknown --name other
+      }
+    )
+
+    (when "changing no setting at all"
+      it "fails" && {
+        expect_run $WITH_FAILURE "$exe" partitions update first
+      }
+    )
+
+    (when "listing the partitions"
+      it "shows their new names" && {
+        WITH_SNAPSHOT="$snapshot/list-after-update" \
+        expect_run $SUCCESSFULLY "$exe" partitions list
+      }
+    )
+
+    it "changed only the given settings in the vault configuration" && {
+      WITH_SNAPSHOT="$snapshot/vault-configuration-after-update" \
+      expect_run $SUCCESSFULLY cat sy-vault.yml
+    }
+  )
+)
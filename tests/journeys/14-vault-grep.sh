@@ -0,0 +1,62 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/grep"
+
+(sandboxed
+  title "'vault grep'"
+  (with "a vault with resources"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      printf "user: admin\npassword: hunter2\n" | "$exe" add :db/prod
+      printf "user: dev\npassword: secret\n" | "$exe" add :db/dev
+      echo -n "token: 1234" | "$exe" add :api
+    } &>/dev/null
+
+    (when "searching all resources"
+      it "succeeds and prints matching lines prefixed with their resource" && {
+        WITH_SNAPSHOT="$snapshot/all-resources" \
+        expect_run $SUCCESSFULLY "$exe" grep "^password"
+      }
+    )
+
+    (when "searching a directory of resources"
+      it "succeeds and only prints matches within it" && {
+        WITH_SNAPSHOT="$snapshot/directory" \
+        expect_run $SUCCESSFULLY "$exe" grep user db
+      }
+    )
+
+    (when "searching a single resource"
+      it "succeeds and only prints matches within it" && {
+        WITH_SNAPSHOT="$snapshot/single-resource" \
+        expect_run $SUCCESSFULLY "$exe" grep "[0-9]+" api
+      }
+    )
+
+    (when "nothing matches"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/no-match" \
+        expect_run $WITH_FAILURE "$exe" grep "does not exist"
+      }
+    )
+
+    (when "the regular expression is invalid"
+      it "fails" && {
+        WITH_SNAPSHOT="$snapshot/invalid-regex" \
+        expect_run $WITH_FAILURE "$exe" grep "("
+      }
+    )
+  )
+)
@@ -0,0 +1,79 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/git-diff"
+
+(sandboxed
+  title "'vault git-setup' and 'vault git-textconv'"
+  editor="$PWD/sed-editor.sh"
+  cat <<'EDITOR' > "$editor"
+#!/bin/bash -e
+sed -i -e "$SED_SCRIPT" "${1:?}"
+EDITOR
+  chmod +x "$editor"
+  export EDITOR="$editor"
+  in-space vault
+
+  (with "a vault in a git repository"
+    { import_user "$fixture/tester.sec.asc"
+      git init -q .
+      git config user.name tester
+      git config user.email tester@example.com
+      "$exe" init --no-auto-import
+      printf "a\nb\nc\n" | "$exe" add :secret
+    } &>/dev/null
+
+    (when "setting up git"
+      it "succeeds" && {
+        expect_run $SUCCESSFULLY "$exe" git-setup
+      }
+      it "uses the drivers for all resources" && {
+        WITH_SNAPSHOT="$snapshot/gitattributes" \
+        expect_run $SUCCESSFULLY cat .gitattributes
+      }
+      it "configures the diff driver" && {
+        expect_run $SUCCESSFULLY git config diff.sheesy.textconv
+      }
+    )
+
+    (when "setting up git again"
+      it "succeeds" && {
+        expect_run $SUCCESSFULLY "$exe" git-setup
+      }
+      it "does not add the drivers twice" && {
+        WITH_SNAPSHOT="$snapshot/gitattributes" \
+        expect_run $SUCCESSFULLY cat .gitattributes
+      }
+    )
+
+    { git add --all
+      git commit -q -m "initial vault"
+    } &>/dev/null
+
+    (when "converting a resource to text"
+      it "succeeds and prints its plaintext" && {
+        WITH_SNAPSHOT="$snapshot/textconv" \
+        expect_run $SUCCESSFULLY "$exe" git-textconv secret.gpg
+      }
+    )
+
+    (when "a resource was changed"
+      SED_SCRIPT='s/^c$/C/' "$exe" edit secret &>/dev/null
+      it "shows the changed plaintext in diffs" && {
+        WITH_SNAPSHOT="$snapshot/diff" \
+        expect_run_sh $SUCCESSFULLY "git diff -- secret.gpg | tail -n +5"
+      }
+    )
+  )
+)
@@ -0,0 +1,88 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/git-merge"
+
+(sandboxed
+  title "'vault git-merge-driver'"
+  editor="$PWD/sed-editor.sh"
+  cat <<'EDITOR' > "$editor"
+#!/bin/bash -e
+sed -i -e "$SED_SCRIPT" "${1:?}"
+EDITOR
+  chmod +x "$editor"
+  export EDITOR="$editor"
+  in-space vault
+
+  (with "a vault in a git repository set up to merge resources"
+    { import_user "$fixture/tester.sec.asc"
+      git init -q .
+      git config user.name tester
+      git config user.email tester@example.com
+      "$exe" init --no-auto-import
+      printf "a\nb\nc\n" | "$exe" add :secret
+      "$exe" git-setup
+      git add --all
+      git commit -q -m "initial vault"
+    } &>/dev/null
+
+    it "configures the merge driver" && {
+      expect_run $SUCCESSFULLY git config merge.sheesy.driver
+    }
+
+    (with "a resource changed differently on two branches"
+      { SED_SCRIPT='s/^c$/C/' "$exe" edit secret
+        git commit -q -a -m "change last line"
+        git checkout -q -b other HEAD~1
+        SED_SCRIPT='s/^a$/A/' "$exe" edit secret
+        git commit -q -a -m "change first line"
+        git checkout -q -
+      } &>/dev/null
+
+      (when "merging the branches"
+        it "succeeds" && {
+          expect_run $SUCCESSFULLY git merge -q --no-edit other
+        }
+        it "merges the plaintext of the resource" && {
+          WITH_SNAPSHOT="$snapshot/merged" \
+          expect_run $SUCCESSFULLY "$exe" show secret
+        }
+        it "leaves nothing to commit" && {
+          WITH_SNAPSHOT="$snapshot/merged-status" \
+          expect_run $SUCCESSFULLY git status --porcelain
+        }
+      )
+    )
+
+    (with "a line of a resource changed differently on two branches"
+      { SED_SCRIPT='s/^b$/ours/' "$exe" edit secret
+        git commit -q -a -m "change middle line"
+        git checkout -q -b conflicting HEAD~1
+        SED_SCRIPT='s/^b$/theirs/' "$exe" edit secret
+        git commit -q -a -m "change middle line differently"
+        git checkout -q -
+      } &>/dev/null
+
+      (when "merging the branches"
+        it "fails" && {
+          expect_run $WITH_FAILURE git merge -q --no-edit conflicting
+        }
+        it "marks the conflict within the encrypted resource" && {
+          WITH_SNAPSHOT="$snapshot/conflicted" \
+          expect_run $SUCCESSFULLY "$exe" show secret
+        }
+      )
+    )
+  )
+)
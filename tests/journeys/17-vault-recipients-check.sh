@@ -0,0 +1,39 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/recipients-check"
+
+(sandboxed
+  title "'vault recipients check'"
+  (with "a vault with a resource"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      echo -n one | "$exe" add :secret
+    } &>/dev/null
+
+    (when "checking the keys of all recipients"
+      it "succeeds and reports no problem" && {
+        WITH_SNAPSHOT="$snapshot/check" \
+        expect_run $SUCCESSFULLY "$exe" recipients check
+      }
+    )
+
+    (when "checking the keys of all recipients for programs"
+      it "succeeds and lists each recipient with its problems" && {
+        WITH_SNAPSHOT="$snapshot/check-yaml" \
+        expect_run $SUCCESSFULLY "$exe" recipients check --output=yaml
+      }
+    )
+  )
+)
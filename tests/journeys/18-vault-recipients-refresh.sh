@@ -0,0 +1,47 @@
+#!/bin/bash
+
+set -eu
+exe=${1:?First argument is the executable under test}
+
+root="$(cd "${0%/*}" && pwd)"
+exe="$root/../../$exe"
+# shellcheck source=./tests/gpg-helpers.sh
+source "$root/../gpg-helpers.sh"
+
+WITH_FAILURE=1
+SUCCESSFULLY=0
+
+fixture="$root/fixtures"
+snapshot="$fixture/snapshots/vault/recipients-refresh"
+
+(sandboxed
+  title "'vault recipients refresh'"
+  (with "a vault with a resource"
+    { import_user "$fixture/tester.sec.asc"
+      "$exe" init --no-auto-import
+      echo -n one | "$exe" add :secret
+    } &>/dev/null
+
+    (when "refreshing the exported keys of all recipients"
+      it "succeeds and reports that none changed" && {
+        WITH_SNAPSHOT="$snapshot/refresh-unchanged" \
+        expect_run $SUCCESSFULLY "$exe" recipients refresh
+      }
+    )
+
+    (with "an outdated exported key"
+      fpr="$(cat .gpg-id)"
+      echo "outdated" > ".gpg-keys/$fpr"
+
+      (when "refreshing the exported keys of all recipients and re-encrypting"
+        it "succeeds and reports the changed key and the re-encrypted resources" && {
+          WITH_SNAPSHOT="$snapshot/refresh-changed-and-reencrypt" \
+          expect_run $SUCCESSFULLY "$exe" recipients refresh --reencrypt
+        }
+        it "replaces the exported key" && {
+          expect_run $SUCCESSFULLY grep -q "BEGIN PGP PUBLIC KEY BLOCK" ".gpg-keys/$fpr"
+        }
+      )
+    )
+  )
+)